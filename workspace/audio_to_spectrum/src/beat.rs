@@ -0,0 +1,331 @@
+// builtin
+
+// external
+
+// internal
+use crate::spectrograph::Spectrograph;
+
+// tempos are in beats per minute, times in seconds like the spectrograph timestep
+pub struct BeatConfig {
+    min_bpm: f32,
+    max_bpm: f32,
+    window_sec: f32,
+    hop_sec: f32,
+    tightness: f32,
+}
+
+impl BeatConfig {
+    pub fn new(min_bpm: f32, max_bpm: f32) -> BeatConfig {
+        BeatConfig { min_bpm, max_bpm, ..BeatConfig::default() }
+    }
+
+    // the tempo is estimated over windows of this length, one every hop
+    pub fn set_window(&mut self, window_sec: f32, hop_sec: f32) {
+        self.window_sec = window_sec;
+        self.hop_sec = hop_sec;
+    }
+
+    // how strongly beats are held to the local tempo rather than to onsets
+    pub fn set_tightness(&mut self, tightness: f32) {
+        self.tightness = tightness;
+    }
+
+    pub fn min_bpm(&self) -> f32 {
+        self.min_bpm
+    }
+
+    pub fn max_bpm(&self) -> f32 {
+        self.max_bpm
+    }
+
+    pub fn window_sec(&self) -> f32 {
+        self.window_sec
+    }
+
+    pub fn hop_sec(&self) -> f32 {
+        self.hop_sec
+    }
+
+    pub fn tightness(&self) -> f32 {
+        self.tightness
+    }
+}
+
+impl Default for BeatConfig {
+    fn default() -> BeatConfig {
+        BeatConfig {
+            min_bpm: 40.0,
+            max_bpm: 220.0,
+            window_sec: 8.0,
+            hop_sec: 1.0,
+            tightness: 100.0,
+        }
+    }
+}
+
+pub struct BeatTrack {
+    tempo: f32,
+    tempo_curve: Vec<(f32, f32)>,
+    beats: Vec<f32>,
+    strengths: Vec<f32>,
+}
+
+impl BeatTrack {
+    // tempo over the whole recording
+    pub fn get_tempo(&self) -> f32 {
+        self.tempo
+    }
+
+    // (window center, tempo) pairs
+    pub fn get_tempo_curve(&self) -> &Vec<(f32, f32)> {
+        &self.tempo_curve
+    }
+
+    pub fn get_beats(&self) -> &Vec<f32> {
+        &self.beats
+    }
+
+    // the onset envelope at each beat, for telling downbeats apart
+    pub fn get_beat_strengths(&self) -> &Vec<f32> {
+        &self.strengths
+    }
+
+    pub fn tempo_at(&self, time_sec: f32) -> f32 {
+        self.tempo_curve.iter()
+            .min_by(|a, b| (a.0 - time_sec).abs().total_cmp(&(b.0 - time_sec).abs()))
+            .map_or(self.tempo, |point| point.1)
+    }
+}
+
+// tempos far from this are only picked when the onsets clearly favour them
+const PRIOR_BPM: f32 = 120.0;
+const PRIOR_OCTAVES: f32 = 1.0;
+const LOCAL_MEAN_SEC: f32 = 0.5;
+const COMPRESSION: f32 = 100.0;
+
+/*
+ spectral flux on log compressed magnitudes, summed over every bin.
+ the local mean is taken off so sustained loud passages don't read as onsets.
+*/
+pub fn onset_envelope(graph: &Spectrograph) -> Vec<f32> {
+    let frames: &Vec<Vec<f32>> = graph.graph_ref();
+    let mut flux: Vec<f32> = vec![0.0; frames.len()];
+
+    for i in 1..frames.len() {
+        flux[i] = frames[i].iter()
+            .zip(frames[i - 1].iter())
+            .map(|(now, before)| {
+                ((1.0 + COMPRESSION * now).ln() - (1.0 + COMPRESSION * before).ln()).max(0.0)
+            })
+            .sum();
+    }
+
+    let half: usize = ((LOCAL_MEAN_SEC / graph.get_timestep()) / 2.0).round() as usize;
+    let mut envelope: Vec<f32> = (0..flux.len())
+        .map(|i| {
+            let window: &[f32] = &flux[i.saturating_sub(half)..(i + half + 1).min(flux.len())];
+            let mean: f32 = window.iter().sum::<f32>() / window.len() as f32;
+            (flux[i] - mean).max(0.0)
+        })
+        .collect();
+
+    let max: f32 = envelope.iter().cloned().fold(0.0, f32::max);
+    if max > 0.0 {
+        envelope.iter_mut().for_each(|value| *value /= max);
+    }
+    envelope
+}
+
+pub fn track_beats(graph: &Spectrograph, config: &BeatConfig) -> BeatTrack {
+    let timestep: f32 = graph.get_timestep();
+    let envelope: Vec<f32> = onset_envelope(graph);
+
+    let tempo: f32 = estimate_tempo(&envelope, timestep, config);
+    let tempo_curve: Vec<(f32, f32)> = tempo_curve(&envelope, timestep, config);
+    let mut track: BeatTrack = BeatTrack { tempo, tempo_curve, beats: Vec::new(), strengths: Vec::new() };
+
+    let periods: Vec<f32> = (0..envelope.len())
+        .map(|i| 60.0 / track.tempo_at(i as f32 * timestep) / timestep)
+        .collect();
+    let beats: Vec<usize> = find_beats(&envelope, &periods, config.tightness);
+    track.strengths = beats.iter().map(|i| envelope[*i]).collect();
+    track.beats = beats.iter().map(|i| *i as f32 * timestep).collect();
+
+    track
+}
+
+// the autocorrelation lag with the most weight, refined between frames
+pub fn estimate_tempo(envelope: &[f32], timestep: f32, config: &BeatConfig) -> f32 {
+    let min_lag: usize = (60.0 / config.max_bpm / timestep).floor().max(1.0) as usize;
+    let max_lag: usize = ((60.0 / config.min_bpm / timestep).ceil() as usize)
+        .min(envelope.len().saturating_sub(1));
+    if min_lag + 1 >= max_lag {
+        return PRIOR_BPM;
+    }
+
+    let scores: Vec<f32> = (min_lag - 1..=max_lag + 1)
+        .map(|lag| {
+            let bpm: f32 = 60.0 / (lag.max(1) as f32 * timestep);
+            let octaves: f32 = (bpm / PRIOR_BPM).log2() / PRIOR_OCTAVES;
+            autocorrelation(envelope, lag) * (-0.5 * octaves * octaves).exp()
+        })
+        .collect();
+
+    let mut best: usize = 1;
+    for i in 1..scores.len() - 1 {
+        if scores[i] > scores[best] {
+            best = i;
+        }
+    }
+    if scores[best] <= 0.0 {
+        return PRIOR_BPM;
+    }
+
+    // parabola through the peak and its neighbours
+    let (before, peak, after) = (scores[best - 1], scores[best], scores[best + 1]);
+    let curve: f32 = before - 2.0 * peak + after;
+    let shift: f32 = if curve < 0.0 { 0.5 * (before - after) / curve } else { 0.0 };
+
+    let lag: f32 = (min_lag - 1 + best) as f32 + shift.clamp(-0.5, 0.5);
+    60.0 / (lag * timestep)
+}
+
+fn tempo_curve(envelope: &[f32], timestep: f32, config: &BeatConfig) -> Vec<(f32, f32)> {
+    let window: usize = (config.window_sec / timestep).round() as usize;
+    let hop: usize = ((config.hop_sec / timestep).round() as usize).max(1);
+
+    if envelope.len() <= window {
+        let center: f32 = envelope.len() as f32 * timestep / 2.0;
+        return vec![(center, estimate_tempo(envelope, timestep, config))];
+    }
+
+    (0..=envelope.len() - window)
+        .step_by(hop)
+        .map(|start| {
+            let center: f32 = (start + window / 2) as f32 * timestep;
+            (center, estimate_tempo(&envelope[start..start + window], timestep, config))
+        })
+        .collect()
+}
+
+fn autocorrelation(envelope: &[f32], lag: usize) -> f32 {
+    if lag >= envelope.len() {
+        return 0.0;
+    }
+    let sum: f32 = envelope.iter()
+        .zip(envelope[lag..].iter())
+        .map(|(a, b)| a * b)
+        .sum();
+    sum / (envelope.len() - lag) as f32
+}
+
+/*
+ dynamic programming over the envelope: every frame takes the best earlier beat between
+ half and twice the local period, paying for how far the gap strays from that period.
+ the last beat is the best scoring frame in the final period, and the rest are traced back.
+*/
+fn find_beats(envelope: &[f32], periods: &[f32], tightness: f32) -> Vec<usize> {
+    let len: usize = envelope.len();
+    if len == 0 {
+        return Vec::new();
+    }
+
+    let mut scores: Vec<f32> = envelope.to_vec();
+    let mut previous: Vec<Option<usize>> = vec![None; len];
+
+    for i in 0..len {
+        let period: f32 = periods[i];
+        let min_gap: usize = (0.5 * period).round().max(1.0) as usize;
+        if i < min_gap {
+            continue;
+        }
+        let earliest: usize = i.saturating_sub((2.0 * period).round() as usize);
+        let latest: usize = i - min_gap;
+
+        let best: Option<(f32, usize)> = scores[earliest..=latest].iter()
+            .enumerate()
+            .map(|(offset, before)| {
+                let j: usize = earliest + offset;
+                let gap: f32 = ((i - j) as f32 / period).ln();
+                (before - tightness * gap * gap, j)
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+
+        if let Some((score, j)) = best {
+            if score > 0.0 {
+                scores[i] += score;
+                previous[i] = Some(j);
+            }
+        }
+    }
+
+    let last_period: usize = periods[len - 1].round() as usize;
+    let mut beat: usize = (len.saturating_sub(last_period)..len)
+        .max_by(|a, b| scores[*a].total_cmp(&scores[*b]))
+        .unwrap_or(len - 1);
+
+    let mut beats: Vec<usize> = vec![beat];
+    while let Some(before) = previous[beat] {
+        beats.push(before);
+        beat = before;
+    }
+    beats.reverse();
+    beats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESTEP: f32 = 512.0 / 44100.0;
+
+    // a burst of energy across every bin at each click, silence in between
+    fn clicks(bpm: f32, seconds: f32) -> Spectrograph {
+        let frames: usize = (seconds / TIMESTEP) as usize;
+        let mut graph: Vec<Vec<f32>> = vec![vec![0.0; 12]; frames];
+
+        let mut time: f32 = 0.25;
+        while time < seconds {
+            let frame: usize = (time / TIMESTEP).round() as usize;
+            if frame < frames {
+                graph[frame] = vec![1.0; 12];
+            }
+            time += 60.0 / bpm;
+        }
+        Spectrograph::new(graph, TIMESTEP)
+    }
+
+    #[test]
+    fn steady_clicks() {
+        let graph: Spectrograph = clicks(120.0, 12.0);
+        let track: BeatTrack = track_beats(&graph, &BeatConfig::default());
+
+        assert!((track.get_tempo() - 120.0).abs() < 2.0, "tempo {}", track.get_tempo());
+        assert!(track.get_tempo_curve().iter().all(|(_, bpm)| (bpm - 120.0).abs() < 2.0));
+
+        // beats land on the clicks
+        let beats: &Vec<f32> = track.get_beats();
+        assert!(beats.len() >= 20);
+        assert_eq!(track.get_beat_strengths().len(), beats.len());
+        for beat in beats {
+            let phase: f32 = ((beat - 0.25) / 0.5).round() * 0.5 + 0.25;
+            assert!((beat - phase).abs() < 2.0 * TIMESTEP, "beat {beat}");
+        }
+    }
+
+    #[test]
+    fn tempo_within_range() {
+        let graph: Spectrograph = clicks(150.0, 10.0);
+        let track: BeatTrack = track_beats(&graph, &BeatConfig::new(40.0, 100.0));
+
+        // the range rules out 150, so the half tempo is found instead
+        assert!((track.get_tempo() - 75.0).abs() < 2.0, "tempo {}", track.get_tempo());
+    }
+
+    #[test]
+    fn silence_has_no_tempo() {
+        let graph: Spectrograph = Spectrograph::new(vec![vec![0.0; 12]; 100], TIMESTEP);
+        assert!(onset_envelope(&graph).iter().all(|value| *value == 0.0));
+        assert_eq!(track_beats(&graph, &BeatConfig::default()).get_tempo(), PRIOR_BPM);
+    }
+}
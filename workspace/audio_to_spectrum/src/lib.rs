@@ -3,8 +3,10 @@
 // external
 
 // internal
+pub mod beat;
 mod pcm;
 pub mod spectrograph;
+use beat::{track_beats, BeatConfig, BeatTrack};
 use pcm::{audio_to_pcm, AudioConfig, PCMBuffer};
 use spectrograph::{pcm_to_spectrograph, Spectrograph};
 
@@ -21,6 +23,19 @@ pub fn get_sample_spectrograph(file_path: &str, len_sec: f32) -> Spectrograph {
     pcm_to_spectrograph(pcm)
 }
 
+// a window of the recording starting offset_sec in, for sampling training examples
+pub fn get_window_spectrograph(file_path: &str, offset_sec: f32, len_sec: f32) -> Spectrograph {
+    let offset: usize = (44100.0 * offset_sec).floor() as usize;
+    let samples: usize = (44100.0 * len_sec).floor() as usize;
+    let pcm: PCMBuffer = audio_to_pcm(
+        AudioConfig::with_window(file_path, offset, samples));
+    pcm_to_spectrograph(pcm)
+}
+
+pub fn audio_to_beats(file_path: &str, config: &BeatConfig) -> BeatTrack {
+    track_beats(&audio_to_spectrograph(file_path), config)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -38,7 +53,7 @@ mod tests {
         let path: String = String::from("./tests/700hz_test.mp3");
         let image: String = String::from("./tests/spectrographs/700hz_test_image.png");
         let spectrograph: Spectrograph = audio_to_spectrograph(&path);
-        let _ = spectrograph.generate_heatmap(&image).expect("Failed to generate heatmap");
+        spectrograph.generate_heatmap(&image).expect("Failed to generate heatmap");
     }
 
     #[test]
@@ -47,7 +62,7 @@ mod tests {
         let image: String = String::from("./tests/spectrographs/Happy_bday_image.png");
         let spectrograph: Spectrograph = audio_to_spectrograph(&path);
         println!("Vector length: {}", spectrograph.vector_dim());
-        let _ = spectrograph.generate_heatmap(&image).expect("Failed to generate heatmap");
+        spectrograph.generate_heatmap(&image).expect("Failed to generate heatmap");
     }
 
     #[test]
@@ -56,7 +71,25 @@ mod tests {
         let image: String = String::from("./tests/spectrographs/K331_15s_image.png");
         let spectrograph: Spectrograph = audio_to_spectrograph(&path);
         println!("Vector length: {}", spectrograph.vector_dim());
-        let _ = spectrograph.generate_heatmap(&image).expect("Failed to generate heatmap");
+        spectrograph.generate_heatmap(&image).expect("Failed to generate heatmap");
+    }
+
+    #[test]
+    fn window_spectrograph() {
+        // a window from the start lines up with the plain sample of the same length
+        let sample: Spectrograph = get_sample_spectrograph("./tests/Happy_bday.mp3", 2.0);
+        let start: Spectrograph = get_window_spectrograph("./tests/Happy_bday.mp3", 0.0, 2.0);
+        let later: Spectrograph = get_window_spectrograph("./tests/Happy_bday.mp3", 1.0, 2.0);
+        assert_eq!(sample.num_timestamps(), start.num_timestamps());
+        assert_eq!(later.num_timestamps(), start.num_timestamps());
+        assert_ne!(later.graph_ref(), start.graph_ref());
+    }
+
+    #[test]
+    fn melody_beats() {
+        let track: BeatTrack = audio_to_beats("./tests/Happy_bday.mp3", &BeatConfig::default());
+        println!("Tempo: {} BPM, {} beats", track.get_tempo(), track.get_beats().len());
+        assert!(track.get_beats().windows(2).all(|pair| pair[0] < pair[1]));
     }
 
     #[test]
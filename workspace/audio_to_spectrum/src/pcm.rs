@@ -20,25 +20,33 @@ pub struct AudioConfig<'a> {
     extension: &'a str,
     should_limit: bool,
     max_limit: usize,
+    offset: usize,
 }
 
 impl<'a> AudioConfig<'a> {
-    pub fn new(file_path: &str) -> AudioConfig {
+    pub fn new(file_path: &str) -> AudioConfig<'_> {
         let file: File = File::open(file_path).expect("Failed to open file!");
         let extension = Path::new(file_path)
             .extension()
             .and_then(OsStr::to_str)
             .expect("Invalid file type!");
-        AudioConfig { file, extension, should_limit: false, max_limit: 0 }
+        AudioConfig { file, extension, should_limit: false, max_limit: 0, offset: 0 }
     }
 
-    pub fn with_limit(file_path: &str, sample_limit: usize) -> AudioConfig {
+    pub fn with_limit(file_path: &str, sample_limit: usize) -> AudioConfig<'_> {
         let file: File = File::open(file_path).expect("Failed to open file!");
         let extension = Path::new(file_path)
             .extension()
             .and_then(OsStr::to_str)
             .expect("Invalid file type!");
-        AudioConfig { file, extension, should_limit: true, max_limit: sample_limit }
+        AudioConfig { file, extension, should_limit: true, max_limit: sample_limit, offset: 0 }
+    }
+
+    // samples before the offset are decoded and thrown away, the limit counts from the offset
+    pub fn with_window(file_path: &str, sample_offset: usize, sample_limit: usize) -> AudioConfig<'_> {
+        let mut config: AudioConfig = AudioConfig::with_limit(file_path, sample_limit);
+        config.offset = sample_offset;
+        config
     }
 }
 
@@ -48,6 +56,7 @@ pub struct PCMBuffer {
 
 pub fn audio_to_pcm(cfg: AudioConfig) -> PCMBuffer {
     let mut samples: Vec<f32> = Vec::new();
+    let mut skipped: usize = 0;
 
     let file: File = cfg.file;
 
@@ -106,13 +115,14 @@ pub fn audio_to_pcm(cfg: AudioConfig) -> PCMBuffer {
 
         match decoder.decode(&packet) {
             Ok(_decoded) => {
-                match _decoded {
-                    AudioBufferRef::F32(buf) => {
-                        for &sample in buf.chan(0) {
-                            samples.push(sample);
+                if let AudioBufferRef::F32(buf) = _decoded {
+                    for &sample in buf.chan(0) {
+                        if skipped < cfg.offset {
+                            skipped += 1;
+                            continue;
                         }
+                        samples.push(sample);
                     }
-                    _ => {}
                 }
             }
             Err(Error::IoError(_)) => {
@@ -133,5 +143,10 @@ pub fn audio_to_pcm(cfg: AudioConfig) -> PCMBuffer {
         };
     }
 
+    // packets overshoot the limit, so equal lengths always give the same number of frames
+    if cfg.should_limit {
+        samples.truncate(cfg.max_limit);
+    }
+
     PCMBuffer { samples }
 }
@@ -37,7 +37,7 @@ pub fn pcm_to_spectrograph(pcm: PCMBuffer) -> Spectrograph {
     let cqt_features = cqt.process(&pcm.samples, hop_size)
         .expect("Error computing CQT");
 
-    let max_value = cqt_features.iter().cloned().fold(0./0., f32::max);
+    let max_value = cqt_features.iter().cloned().fold(f32::NAN, f32::max);
     let spectrograph: Vec<Vec<f32>> = cqt_features.outer_iter()
         .map(|timestep| timestep.iter().map(|&val| {
             let normalized_val = val / max_value;
@@ -60,6 +60,11 @@ pub struct Spectrograph {
 }
 
 impl Spectrograph {
+    // timestep in seconds per frame
+    pub fn new(graph: Vec<Vec<f32>>, timestep: f32) -> Spectrograph {
+        Spectrograph { graph, frequency_ratio: 1.0595, timestep_ms: timestep }
+    }
+
     pub fn vector_dim(&self) -> usize {
         if self.num_timestamps() > 0 {
             self.graph[0].len()
@@ -95,6 +100,24 @@ impl Spectrograph {
         self.timestep_ms
     }
 
+    // the timestep is kept in seconds
+    pub fn frame_ms(&self) -> f64 {
+        self.timestep_ms as f64 * 1000.0
+    }
+
+    // each frame folded into 12 pitch classes from C, the lowest bin being A0
+    pub fn chroma(&self) -> Vec<Vec<f32>> {
+        self.graph.iter()
+            .map(|timestep| {
+                let mut chroma: Vec<f32> = vec![0.0; BINS_PER_OCTAVE];
+                for (bin, value) in timestep.iter().enumerate() {
+                    chroma[(bin + 9) % BINS_PER_OCTAVE] += value;
+                }
+                chroma
+            })
+            .collect()
+    }
+
     pub fn generate_heatmap(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
         let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
         root.fill(&WHITE)?;
@@ -130,7 +153,7 @@ impl Spectrograph {
     }
 }
 
-fn find_max(vector: &Vec<f32>) -> (usize, f32) {
+fn find_max(vector: &[f32]) -> (usize, f32) {
     let mut max: f32 = 0.0;
     let mut index: usize = 0;
     for (i, v) in vector.iter().enumerate() {
@@ -159,4 +182,20 @@ mod test {
             println!("Time {time}: max value {value} @ index {index} w/ frequency {frequency}");
         }
     }
+
+    #[test]
+    fn chroma_test() {
+        let pcm: PCMBuffer = audio_to_pcm(AudioConfig::new("./tests/700hz_test.mp3"));
+        let graph: Spectrograph = pcm_to_spectrograph(pcm);
+
+        // 700 Hz sits just above F5
+        let mut totals: Vec<f32> = vec![0.0; BINS_PER_OCTAVE];
+        for frame in graph.chroma() {
+            totals.iter_mut().zip(frame).for_each(|(total, value)| *total += value);
+        }
+        let loudest: usize = (0..BINS_PER_OCTAVE)
+            .max_by(|a, b| totals[*a].total_cmp(&totals[*b]))
+            .unwrap();
+        assert_eq!(loudest, 5);
+    }
 } 
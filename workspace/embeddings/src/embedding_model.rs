@@ -5,7 +5,8 @@ use std::cmp;
 
 // internal
 use super::types::Embedding;
-use midi_encoder::types::{MIDIEncoding, ENCODING_LENGTH};
+use midi_encoder::encoding::{ChordLayout, Encoding};
+use midi_encoder::types::MIDIEncoding;
 use models::networks::activation::Activation;
 use models::networks::configs::{ActivationConfig, ParameterConfig, WeightConfig};
 use models::NN;
@@ -15,29 +16,51 @@ pub struct EmbeddingModel {
     window_radius: usize,
     batch: usize,
     nn: NN,
+    layout: Box<dyn Encoding>,
 }
 
 impl EmbeddingModel {
     pub fn new(dim: usize, window: usize, batch: usize) -> EmbeddingModel {
+        EmbeddingModel::with_layout(dim, window, batch, Box::new(ChordLayout::default()))
+    }
+
+    pub fn with_layout(
+        dim: usize,
+        window: usize,
+        batch: usize,
+        layout: Box<dyn Encoding>,
+    ) -> EmbeddingModel {
         let file_path: String = format!("./tests/weights_d{dim}.txt");
-        let nn: NN = NN::from_save(&file_path).unwrap_or_else(|_err| {
-            println!("No save found -- generating new model");
-            let mut params: ParameterConfig =
-                ParameterConfig::new(1, ENCODING_LENGTH, ENCODING_LENGTH, vec![dim]);
-            let weights: WeightConfig = WeightConfig::new(0.03, 0.07, 0.000, 0.0001);
-            let mut activations: ActivationConfig =
-                ActivationConfig::new(Activation::relu(), Activation::sigmoid());
-            NN::new(&mut params, weights, &mut activations)
-        });
+        let length: usize = layout.vector_length();
+        let nn: NN = match NN::from_save(&file_path) {
+            Ok(nn) if nn.input_dim() == length && nn.output_dim() == length => nn,
+            Ok(_) => {
+                println!("Saved model does not match encoding length -- generating new model");
+                EmbeddingModel::new_network(dim, length)
+            }
+            Err(_) => {
+                println!("No save found -- generating new model");
+                EmbeddingModel::new_network(dim, length)
+            }
+        };
 
         EmbeddingModel {
             dim,
             window_radius: window,
             batch,
             nn,
+            layout,
         }
     }
 
+    fn new_network(dim: usize, length: usize) -> NN {
+        let mut params: ParameterConfig = ParameterConfig::new(1, length, length, vec![dim]);
+        let weights: WeightConfig = WeightConfig::new(0.03, 0.07, 0.000, 0.0001);
+        let mut activations: ActivationConfig =
+            ActivationConfig::new(Activation::relu(), Activation::sigmoid());
+        NN::new(&mut params, weights, &mut activations)
+    }
+
     pub fn save(&self) {
         let file_path: String = format!("./tests/weights_d{}.txt", self.dim);
 
@@ -45,20 +68,12 @@ impl EmbeddingModel {
     }
 
     pub fn learn_embeddings(&mut self, encoding: &MIDIEncoding) {
-        let encoded_vecs: Vec<Vec<f32>> = encoding
-            .get_encoding()
-            .iter()
-            .map(|c| c.get_encoding())
-            .collect();
+        let encoded_vecs: Vec<Vec<f32>> = encoding.to_vectors(self.layout.as_ref());
 
         let mut averaged_vecs: Vec<Vec<f32>> = Vec::new();
 
         for i in 0..encoded_vecs.len() {
-            let start = if i >= self.window_radius {
-                i - self.window_radius
-            } else {
-                0
-            };
+            let start = i.saturating_sub(self.window_radius);
             let end = cmp::min(i + self.window_radius, encoded_vecs.len() - 1);
             let window = &encoded_vecs[start..=end];
 
@@ -72,11 +87,7 @@ impl EmbeddingModel {
     }
 
     pub fn get_embedding(&mut self, encoding: &MIDIEncoding) -> Embedding {
-        let encoded_vecs: Vec<Vec<f32>> = encoding
-            .get_encoding()
-            .iter()
-            .map(|c| c.get_encoding())
-            .collect();
+        let encoded_vecs: Vec<Vec<f32>> = encoding.to_vectors(self.layout.as_ref());
 
         let output_vecs: Vec<Vec<f32>> = self.nn.predict_first_layer(encoded_vecs);
 
@@ -88,7 +99,7 @@ impl EmbeddingModel {
 
         let output_vecs: Vec<Vec<f32>> = self.nn.first_layer_input(embedding_vecs);
 
-        MIDIEncoding::from_vector(output_vecs, 0.5)
+        MIDIEncoding::from_vector(output_vecs, 0.5, self.layout.as_ref())
     }
 
     fn get_window_average(&self, slice: &[Vec<f32>]) -> Vec<f32> {
@@ -9,14 +9,15 @@ mod types;
 #[cfg(test)]
 mod tests {
     use crate::{embedding_model::EmbeddingModel, types::Embedding};
-    use midi_encoder::{decode_to_midi, get_sample_encoding, types::MIDIEncoding};
+    use midi_encoder::{get_sample_encoding, types::MIDIEncoding};
     use models::networks::activation::init_registry;
 
     #[test]
     fn embedding_training_test() {
         init_registry();
 
-        let midi: MIDIEncoding = get_sample_encoding("../midi_encoder/tests/Data_Test.midi", 5.0);
+        let midi: MIDIEncoding = get_sample_encoding("../midi_encoder/tests/Data_Test.midi", 5.0)
+            .expect("Failed to encode MIDI file");
         let mut model: EmbeddingModel = EmbeddingModel::new(32, 1, 8);
 
         model.learn_embeddings(&midi);
@@ -27,7 +28,8 @@ mod tests {
     #[test]
     fn embedding_encode_decode_test() {
         init_registry();
-        let midi: MIDIEncoding = get_sample_encoding("../midi_encoder/tests/Data_Test.midi", 5.0);
+        let midi: MIDIEncoding = get_sample_encoding("../midi_encoder/tests/Data_Test.midi", 5.0)
+            .expect("Failed to encode MIDI file");
         let mut model: EmbeddingModel = EmbeddingModel::new(32, 1, 8);
 
         let embeddings: Embedding = model.get_embedding(&midi);
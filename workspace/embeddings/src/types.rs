@@ -19,10 +19,10 @@ impl Embedding {
     pub fn get_dim(&self) -> (usize, usize) {
         let embedding_num: usize = self.embedding.len();
         let null_vec: Vec<f32> = vec![];
-        let first_embedding: &Vec<f32> = self.embedding.get(0).unwrap_or(&null_vec);
+        let first_embedding: &Vec<f32> = self.embedding.first().unwrap_or(&null_vec);
         let embedding_dim: usize = first_embedding.len();
 
-        return (embedding_num, embedding_dim);
+        (embedding_num, embedding_dim)
     }
 
     pub fn get_embedding(&mut self) -> Vec<Vec<f32>> {
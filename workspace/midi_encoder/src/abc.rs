@@ -0,0 +1,266 @@
+// builtin
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// external
+
+// internal
+use crate::error::MidiError;
+use crate::quantize::QuantizedNote;
+use crate::score::{Element, Measure, NoteValue, Score};
+use crate::spelling::Pitch;
+use crate::types::{ExportOptions, KeySignature};
+
+// L:1/8, so lengths are written in eighths
+const UNIT_TICKS: u64 = 240;
+const BARS_PER_LINE: usize = 4;
+
+pub fn write_abc(
+    notes: &[QuantizedNote],
+    file_path: &str,
+    options: &ExportOptions,
+) -> Result<(), MidiError> {
+    std::fs::write(file_path, score_to_abc(&Score::melody(notes, options)))?;
+    Ok(())
+}
+
+// a single tune from the upper staff, notes starting together are written as chords
+pub fn score_to_abc(score: &Score) -> String {
+    let key: KeySignature = score.key_signature();
+    let mut abc: String = String::from("X:1\n");
+
+    if let Some(title) = score.title() {
+        let _ = writeln!(abc, "T:{}", title);
+    }
+    let _ = writeln!(
+        abc,
+        "M:{}/{}",
+        score.time_signature().get_numerator(),
+        score.time_signature().get_denominator()
+    );
+    abc.push_str("L:1/8\n");
+    let _ = writeln!(abc, "Q:1/4={}", score.tempo_bpm().round());
+
+    let tonic: Pitch = Pitch::tonic(&key);
+    let accidental: &str = match tonic.get_alter() {
+        a if a > 0 => "#",
+        a if a < 0 => "b",
+        _ => "",
+    };
+    let mode: &str = if key.is_minor() { "m" } else { "" };
+    let _ = writeln!(abc, "K:{}{}{}", tonic.get_step(), accidental, mode);
+
+    for (i, measure) in score.get_upper().iter().enumerate() {
+        abc.push_str(&measure_text(measure, &key));
+        abc.push_str(" |");
+        if (i + 1) % BARS_PER_LINE == 0 || i + 1 == score.num_measures() {
+            abc.push('\n');
+        } else {
+            abc.push(' ');
+        }
+    }
+
+    abc
+}
+
+fn measure_text(measure: &Measure, key: &KeySignature) -> String {
+    // accidentals last until the barline, starting from the key signature
+    let mut accidentals: HashMap<(char, i8), i8> = HashMap::new();
+    let mut items: Vec<String> = Vec::new();
+    let elements: &Vec<Element> = measure.get_elements();
+
+    let mut i: usize = 0;
+    while i < elements.len() {
+        // (3:2:n puts the next n notes in triplet time
+        let run: usize = elements[i..]
+            .iter()
+            .take_while(|e| match e {
+                Element::Chord { value, .. } | Element::Rest(value) => value.is_triplet(),
+                Element::MeasureRest => false,
+            })
+            .count();
+        let mut text: String = if run > 0 {
+            format!("(3:2:{}", run)
+        } else {
+            String::new()
+        };
+
+        for element in &elements[i..i + run.max(1)] {
+            text.push_str(&match element {
+                Element::MeasureRest => String::from("Z"),
+                Element::Rest(value) => format!("z{}", length(value)),
+                Element::Chord {
+                    pitches,
+                    value,
+                    tie_start,
+                    ..
+                } => {
+                    let names: String = pitches
+                        .iter()
+                        .map(|pitch| note_name(pitch, key, &mut accidentals))
+                        .collect();
+                    let tie: &str = if *tie_start { "-" } else { "" };
+
+                    if pitches.len() == 1 {
+                        format!("{}{}{}", names, length(value), tie)
+                    } else {
+                        format!("[{}]{}{}", names, length(value), tie)
+                    }
+                }
+            });
+        }
+
+        items.push(text);
+        i += run.max(1);
+    }
+
+    items.join(" ")
+}
+
+// written at the value's own length, the triplet marker takes care of the rest
+fn length(value: &NoteValue) -> String {
+    let plain: NoteValue = NoteValue::new(value.get_base(), value.get_dots(), false);
+    let ticks: u64 = plain.ticks();
+
+    if ticks == UNIT_TICKS {
+        String::new()
+    } else if ticks.is_multiple_of(UNIT_TICKS) {
+        format!("{}", ticks / UNIT_TICKS)
+    } else {
+        let divisor: u64 = gcd(ticks, UNIT_TICKS);
+        let (numerator, denominator) = (ticks / divisor, UNIT_TICKS / divisor);
+        if numerator == 1 {
+            format!("/{}", denominator)
+        } else {
+            format!("{}/{}", numerator, denominator)
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn key_alter(step: char, key: &KeySignature) -> i8 {
+    const SHARPS: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+    let fifths: i8 = key.get_fifths();
+    let position: usize = SHARPS.iter().position(|s| *s == step).unwrap_or(0);
+
+    if fifths > 0 && position < fifths as usize {
+        1
+    } else if fifths < 0 && 6 - position < (-fifths) as usize {
+        -1
+    } else {
+        0
+    }
+}
+
+// C is middle C, c the octave above, with commas and apostrophes from there
+fn note_name(
+    pitch: &Pitch,
+    key: &KeySignature,
+    accidentals: &mut HashMap<(char, i8), i8>,
+) -> String {
+    let step: char = pitch.get_step();
+    let octave: i8 = pitch.get_octave();
+    let current: i8 = *accidentals
+        .get(&(step, octave))
+        .unwrap_or(&key_alter(step, key));
+
+    let mut name: String = String::new();
+    if pitch.get_alter() != current {
+        name.push_str(match pitch.get_alter() {
+            a if a > 0 => "^",
+            a if a < 0 => "_",
+            _ => "=",
+        });
+        accidentals.insert((step, octave), pitch.get_alter());
+    }
+
+    if octave >= 5 {
+        name.push(step.to_ascii_lowercase());
+        name.push_str(&"'".repeat((octave - 5) as usize));
+    } else {
+        name.push(step);
+        name.push_str(&",".repeat((4 - octave) as usize));
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+    use crate::quantize::{quantize, BeatGrid, QuantizeConfig};
+    use crate::span::{spans_to_events, NoteSpan};
+    use crate::types::NoteEvent;
+
+    fn melody(spans: &[(u8, f64, f64)]) -> Vec<QuantizedNote> {
+        let spans: Vec<NoteSpan> = spans
+            .iter()
+            .map(|(key, onset, offset)| NoteSpan::new(*key, onset * 500.0, offset * 500.0, 64))
+            .collect();
+        quantize(
+            &spans_to_events(&spans),
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        )
+    }
+
+    #[test]
+    fn melody_text() {
+        // F# is in the key, the F natural needs a sign and the next F# gets one back
+        let notes: Vec<QuantizedNote> = melody(&[
+            (67, 0.0, 1.0),
+            (66, 1.0, 1.5),
+            (65, 1.5, 2.0),
+            (66, 2.0, 3.0),
+            (79, 3.0, 5.0),
+        ]);
+        let mut options: ExportOptions = ExportOptions::multi_track();
+        options.set_title("Scale");
+        options.set_key_signature(KeySignature::new(1, false));
+
+        let abc: String = score_to_abc(&Score::melody(&notes, &options));
+        assert_eq!(
+            abc,
+            "X:1\nT:Scale\nM:4/4\nL:1/8\nQ:1/4=120\nK:G\nG2 F =F ^F2 g2- | g2 z6 |\n"
+        );
+    }
+
+    #[test]
+    fn triplets_and_chords() {
+        let third: f64 = 1.0 / 3.0;
+        let notes: Vec<QuantizedNote> = melody(&[
+            (60, 0.0, third),
+            (62, third, 2.0 * third),
+            (64, 2.0 * third, 1.0),
+            (60, 1.0, 2.0),
+            (64, 1.0, 2.0),
+            (48, 2.0, 2.25),
+        ]);
+        let abc: String = score_to_abc(&Score::melody(&notes, &ExportOptions::multi_track()));
+        assert!(abc.contains("(3:2:3CDE [CE]2 C,/2"), "{abc}");
+    }
+
+    #[test]
+    fn data_test_abc() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let notes: Vec<QuantizedNote> = quantize(
+            &events,
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        );
+        write_abc(
+            &notes,
+            "./tests/output/Data_score.abc",
+            &ExportOptions::multi_track(),
+        )
+        .expect("Failed to write ABC file");
+    }
+}
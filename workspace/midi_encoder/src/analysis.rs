@@ -0,0 +1,368 @@
+// builtin
+
+// external
+
+// internal
+use crate::quantize::QuantizedNote;
+use crate::span::{events_to_spans, NoteSpan};
+use crate::types::{KeySignature, NoteEvent, TimeSignature, TICKS_PER_BEAT};
+
+pub const PITCH_CLASSES: usize = 12;
+
+// Krumhansl-Kessler probe tone ratings, starting from the tonic
+const MAJOR_PROFILE: [f32; PITCH_CLASSES] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; PITCH_CLASSES] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEstimate {
+    tonic: u8,
+    minor: bool,
+    correlation: f32,
+}
+
+impl KeyEstimate {
+    // pitch class of the tonic, 0 for C
+    pub fn get_tonic(&self) -> u8 {
+        self.tonic
+    }
+
+    pub fn is_minor(&self) -> bool {
+        self.minor
+    }
+
+    // how well the best profile fits, from -1 to 1
+    pub fn get_correlation(&self) -> f32 {
+        self.correlation
+    }
+
+    // sharps up to F# major and D# minor, flats from D flat major and B flat minor
+    pub fn key_signature(&self) -> KeySignature {
+        let major_tonic: u8 = if self.minor {
+            (self.tonic + 3) % 12
+        } else {
+            self.tonic
+        };
+        let fifths: i8 = ((major_tonic as i8 * 7) % 12 + 5).rem_euclid(12) - 5;
+        KeySignature::new(fifths, self.minor)
+    }
+}
+
+// note durations per pitch class, so held notes count for more than passing ones
+pub fn pitch_class_histogram(events: &[NoteEvent]) -> [f32; PITCH_CLASSES] {
+    let mut histogram: [f32; PITCH_CLASSES] = [0.0; PITCH_CLASSES];
+    let spans: Vec<NoteSpan> = events_to_spans(events);
+
+    for span in spans.iter() {
+        histogram[(span.get_key() % 12) as usize] += span.get_duration_ms().max(1.0) as f32;
+    }
+
+    histogram
+}
+
+// chroma frames are summed as they are, each frame holding one value per pitch class from C
+pub fn chroma_histogram(frames: &[Vec<f32>]) -> [f32; PITCH_CLASSES] {
+    let mut histogram: [f32; PITCH_CLASSES] = [0.0; PITCH_CLASSES];
+
+    for frame in frames {
+        for (pitch_class, value) in frame.iter().take(PITCH_CLASSES).enumerate() {
+            histogram[pitch_class] += value;
+        }
+    }
+
+    histogram
+}
+
+// the major or minor key whose rotated profile correlates best with the histogram
+pub fn estimate_key(histogram: &[f32; PITCH_CLASSES]) -> KeyEstimate {
+    let mut best: KeyEstimate = KeyEstimate {
+        tonic: 0,
+        minor: false,
+        correlation: f32::NEG_INFINITY,
+    };
+
+    for tonic in 0..PITCH_CLASSES {
+        for (profile, minor) in [(&MAJOR_PROFILE, false), (&MINOR_PROFILE, true)] {
+            let rotated: Vec<f32> = (0..PITCH_CLASSES)
+                .map(|pc| profile[(pc + PITCH_CLASSES - tonic) % PITCH_CLASSES])
+                .collect();
+            let correlation: f32 = pearson(histogram, &rotated);
+
+            if correlation > best.correlation {
+                best = KeyEstimate {
+                    tonic: tonic as u8,
+                    minor,
+                    correlation,
+                };
+            }
+        }
+    }
+
+    if !best.correlation.is_finite() {
+        best.correlation = 0.0;
+    }
+    best
+}
+
+fn pearson(a: &[f32], b: &[f32]) -> f32 {
+    let n: f32 = a.len() as f32;
+    let mean_a: f32 = a.iter().sum::<f32>() / n;
+    let mean_b: f32 = b.iter().sum::<f32>() / n;
+
+    let mut covariance: f32 = 0.0;
+    let mut var_a: f32 = 0.0;
+    let mut var_b: f32 = 0.0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        covariance += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+
+    if var_a == 0.0 || var_b == 0.0 {
+        return 0.0;
+    }
+    covariance / (var_a * var_b).sqrt()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterEstimate {
+    beats_per_measure: u8,
+    pickup_beats: u8,
+    strength: f32,
+}
+
+impl MeterEstimate {
+    pub fn get_beats_per_measure(&self) -> u8 {
+        self.beats_per_measure
+    }
+
+    // beats before the first downbeat
+    pub fn get_pickup_beats(&self) -> u8 {
+        self.pickup_beats
+    }
+
+    // how much stronger downbeats are than the average beat
+    pub fn get_strength(&self) -> f32 {
+        self.strength
+    }
+
+    // beats are quarter notes, like the quantizer's
+    pub fn time_signature(&self) -> TimeSignature {
+        TimeSignature::new(self.beats_per_measure, 4).expect("Meters have at least one beat")
+    }
+}
+
+// a longer measure has to explain the accents this much better to be picked over a shorter one
+const METER_MARGIN: f32 = 1.05;
+
+// how heavily each quantized beat is played, from the notes starting right on it
+pub fn beat_accents(notes: &[QuantizedNote]) -> Vec<f32> {
+    let last_beat: u64 = notes
+        .iter()
+        .map(|n| n.get_onset_ticks() / TICKS_PER_BEAT)
+        .max()
+        .unwrap_or(0);
+    let mut accents: Vec<f32> = vec![0.0; last_beat as usize + 1];
+
+    for note in notes
+        .iter()
+        .filter(|n| n.get_onset_ticks().is_multiple_of(TICKS_PER_BEAT))
+    {
+        let beat: usize = (note.get_onset_ticks() / TICKS_PER_BEAT) as usize;
+        accents[beat] += note.get_velocity() as f32 / 127.0 * note.get_beats().min(4.0) as f32;
+    }
+
+    accents
+}
+
+/*
+ tries measures of two, three and four beats at every phase and keeps the one whose
+ downbeats stand out the most. a 4/4 bar only wins over 2/4 when its downbeat is
+ heavier than the beat halfway through, and accents without any clear downbeat
+ fall back to 4/4.
+*/
+pub fn estimate_meter(accents: &[f32]) -> MeterEstimate {
+    let mean: f32 = accents.iter().sum::<f32>() / accents.len().max(1) as f32;
+    let fallback: MeterEstimate = MeterEstimate {
+        beats_per_measure: 4,
+        pickup_beats: 0,
+        strength: 1.0,
+    };
+    if mean <= 0.0 {
+        return fallback;
+    }
+
+    let mut best: Option<MeterEstimate> = None;
+    for beats in [2, 3, 4] {
+        for phase in 0..beats {
+            let downbeats: Vec<f32> = accents.iter().skip(phase).step_by(beats).copied().collect();
+            let strength: f32 =
+                downbeats.iter().sum::<f32>() / downbeats.len().max(1) as f32 / mean;
+
+            let better: bool = match best {
+                None => true,
+                Some(best) if beats as u8 == best.beats_per_measure => strength > best.strength,
+                Some(best) => strength > best.strength * METER_MARGIN,
+            };
+            if better {
+                best = Some(MeterEstimate {
+                    beats_per_measure: beats as u8,
+                    pickup_beats: phase as u8,
+                    strength,
+                });
+            }
+        }
+    }
+
+    best.filter(|best| best.strength > METER_MARGIN)
+        .unwrap_or(fallback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+    use crate::quantize::{quantize, BeatGrid, QuantizeConfig};
+    use crate::span::spans_to_events;
+
+    fn melody(keys: &[u8], beat_ms: f64) -> Vec<NoteEvent> {
+        let spans: Vec<NoteSpan> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| NoteSpan::new(*key, i as f64 * beat_ms, (i + 1) as f64 * beat_ms, 80))
+            .collect();
+        spans_to_events(&spans)
+    }
+
+    #[test]
+    fn major_and_minor_keys() {
+        // C major arpeggios and scale, leaning on the tonic triad
+        let c_major: Vec<NoteEvent> =
+            melody(&[60, 64, 67, 72, 62, 65, 69, 71, 60, 67, 64, 60], 500.0);
+        let key: KeyEstimate = estimate_key(&pitch_class_histogram(&c_major));
+        assert_eq!((key.get_tonic(), key.is_minor()), (0, false));
+        assert_eq!(key.key_signature(), KeySignature::new(0, false));
+
+        // A harmonic minor, the G sharp rules out C major
+        let a_minor: Vec<NoteEvent> =
+            melody(&[57, 60, 64, 69, 68, 69, 71, 72, 64, 57, 68, 57], 500.0);
+        let key: KeyEstimate = estimate_key(&pitch_class_histogram(&a_minor));
+        assert_eq!((key.get_tonic(), key.is_minor()), (9, true));
+        assert_eq!(key.key_signature(), KeySignature::new(0, true));
+    }
+
+    #[test]
+    fn key_signatures_from_tonic() {
+        let fifths = |tonic: u8, minor: bool| -> i8 {
+            KeyEstimate {
+                tonic,
+                minor,
+                correlation: 1.0,
+            }
+            .key_signature()
+            .get_fifths()
+        };
+        assert_eq!(fifths(7, false), 1);
+        assert_eq!(fifths(5, false), -1);
+        assert_eq!(fifths(1, false), -5);
+        assert_eq!(fifths(6, false), 6);
+        assert_eq!(fifths(2, true), -1);
+        assert_eq!(fifths(4, true), 1);
+    }
+
+    #[test]
+    fn chroma_matches_notes() {
+        let events: Vec<NoteEvent> = melody(&[62, 66, 69, 74, 66, 69], 500.0);
+        let frames: Vec<Vec<f32>> = events_to_spans(&events)
+            .iter()
+            .map(|span| {
+                let mut frame: Vec<f32> = vec![0.0; PITCH_CLASSES];
+                frame[(span.get_key() % 12) as usize] = 1.0;
+                frame
+            })
+            .collect();
+
+        let from_notes: KeyEstimate = estimate_key(&pitch_class_histogram(&events));
+        let from_chroma: KeyEstimate = estimate_key(&chroma_histogram(&frames));
+        assert_eq!(from_notes.get_tonic(), 2);
+        assert_eq!(from_notes.get_tonic(), from_chroma.get_tonic());
+        assert_eq!(from_notes.is_minor(), from_chroma.is_minor());
+    }
+
+    #[test]
+    fn waltz_and_march() {
+        let waltz: Vec<f32> = (0..24)
+            .map(|i| if i % 3 == 1 { 1.0 } else { 0.3 })
+            .collect();
+        let meter: MeterEstimate = estimate_meter(&waltz);
+        assert_eq!(
+            meter.time_signature(),
+            TimeSignature::new(3, 4).expect("3/4 is a valid meter")
+        );
+        assert_eq!(meter.get_pickup_beats(), 1);
+
+        // a march leans on beat one more than on beat three
+        let march: Vec<f32> = (0..24).map(|i| [1.0, 0.3, 0.7, 0.3][i % 4]).collect();
+        let meter: MeterEstimate = estimate_meter(&march);
+        assert_eq!(meter.get_beats_per_measure(), 4);
+        assert_eq!(meter.get_pickup_beats(), 0);
+
+        assert_eq!(estimate_meter(&[0.5; 24]).get_beats_per_measure(), 4);
+        assert_eq!(estimate_meter(&[]).get_beats_per_measure(), 4);
+    }
+
+    #[test]
+    fn even_strong_weak_beats_read_as_two_four() {
+        let alternating: Vec<f32> = (0..24)
+            .map(|i| if i % 2 == 1 { 1.0 } else { 0.5 })
+            .collect();
+        let meter: MeterEstimate = estimate_meter(&alternating);
+        assert_eq!(
+            meter.time_signature(),
+            TimeSignature::new(2, 4).expect("2/4 is a valid meter")
+        );
+        assert_eq!(meter.get_pickup_beats(), 1);
+    }
+
+    #[test]
+    fn meter_from_quantized_notes() {
+        // a bass note on every downbeat of a 3/4 bar, chords on the other beats
+        let mut spans: Vec<NoteSpan> = Vec::new();
+        for beat in 0..24 {
+            let onset: f64 = beat as f64 * 500.0;
+            if beat % 3 == 0 {
+                spans.push(NoteSpan::new(36, onset, onset + 1500.0, 100));
+            } else {
+                spans.push(NoteSpan::new(64, onset, onset + 250.0, 50));
+            }
+        }
+        let notes: Vec<QuantizedNote> = quantize(
+            &spans_to_events(&spans),
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        );
+
+        let meter: MeterEstimate = estimate_meter(&beat_accents(&notes));
+        assert_eq!(
+            meter.time_signature(),
+            TimeSignature::new(3, 4).expect("3/4 is a valid meter")
+        );
+        assert_eq!(meter.get_pickup_beats(), 0);
+    }
+
+    #[test]
+    fn data_test_key() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let key: KeyEstimate = estimate_key(&pitch_class_histogram(&events));
+        println!(
+            "Key: {:?}, fifths {}",
+            key,
+            key.key_signature().get_fifths()
+        );
+        assert!(key.get_correlation() > 0.5);
+    }
+}
@@ -1,14 +1,34 @@
 pub const ENCODING_LENGTH: usize = 88 * 2 + NON_KEY_VARS;
 pub const KEY_RANGE: usize = 88 * 2;
 
+// A0 and C8, the ends of the 88 key piano
+pub const LOWEST_KEY: u8 = 21;
+pub const HIGHEST_KEY: u8 = 108;
+
 /*
   1 - time_delta;
   2 - start;
   3 - end;
+  4 - pedal;
 */
 
-pub const NON_KEY_VARS: usize = 3;
+pub const NON_KEY_VARS: usize = 4;
 
 pub const TIME_DELTA_INDEX: usize = 0;
 pub const START_INDEX: usize = 1;
 pub const END_INDEX: usize = 2;
+pub const PEDAL_INDEX: usize = 3;
+
+pub const SUSTAIN_CONTROLLER: u8 = 64;
+
+pub const DEFAULT_QUANTIZE_MS: f32 = 5.0;
+
+pub const DEFAULT_VELOCITY: u8 = 64;
+
+// time deltas past this saturate in the bounded encodings
+pub const MAX_TIME_DELTA_MS: f32 = 10_000.0;
+
+pub const DEFAULT_TEMPO: u32 = 500_000; // microseconds per beat
+pub const TICKS_PER_BEAT: u64 = 480;
+pub const DEFAULT_SPLIT_KEY: u8 = 60;
+pub const ACOUSTIC_GRAND: u8 = 0;
@@ -3,75 +3,155 @@
 // external
 
 // internal
-use crate::types::{AddNoteResult, Chord, EncodingData, MIDIEncoding, NoteEvent};
+use crate::error::MidiError;
+use crate::pedal::apply_sustain;
+use crate::span::{event_times, KEYS};
+use crate::types::{
+    AddNoteResult, Chord, DecodedEvents, EncodingData, MIDIEncoding, NoteEvent, OutOfRangePolicy,
+};
 
-pub fn encode(mut data: EncodingData) -> MIDIEncoding {
-    let events: Vec<NoteEvent> = data.get_events();
+pub fn encode(mut data: EncodingData) -> Result<MIDIEncoding, MidiError> {
+    let events: Vec<NoteEvent> = if data.applies_sustain() {
+        apply_sustain(data.get_events())
+    } else {
+        data.get_events()
+    };
 
-    let mut time: f32 = 0.0;
+    let policy: OutOfRangePolicy = data.key_policy();
+    let mut dropped_notes: usize = 0;
+    let mut folded_notes: usize = 0;
+
+    let step: f64 = data.quantization_ms() as f64;
+    let times: Vec<f64> = event_times(&events);
+    // deltas run from the last kept event, so dropped events pass their time on
+    let mut last_time: f64 = 0.0;
     let mut encoding: Vec<Chord> = Vec::new();
     encoding.push(Chord::start());
     let chord: &mut Chord = &mut Chord::none();
 
-    for event in events {
-        let time_delta = event.get_time_delta();
-        time += time_delta;
-        if !data.continue_sampling(time.ceil() as u32) {
+    for (event, time) in events.into_iter().zip(times) {
+        let event: NoteEvent = if event.is_pedal() {
+            event
+        } else {
+            let key: u8 = event.get_note_ref().get_key();
+            match policy.apply(key)? {
+                Some(new_key) if new_key == key => event,
+                Some(new_key) => {
+                    // a note counts once, by its press
+                    if event.get_note_ref().is_note_on() {
+                        folded_notes += 1;
+                    }
+                    event.with_key(new_key)
+                }
+                None => {
+                    if event.get_note_ref().is_note_on() {
+                        dropped_notes += 1;
+                    }
+                    continue;
+                }
+            }
+        };
+
+        // quantize absolute times so rounding error never builds up across deltas
+        let time_delta: f32 = if step > 0.0 {
+            let quantized: f64 = (time / step).round() * step;
+            let delta: f32 = (quantized - last_time) as f32;
+            last_time = quantized;
+            delta
+        } else {
+            let delta: f32 = (time - last_time) as f32;
+            last_time = time;
+            delta
+        };
+
+        if !data.continue_sampling(last_time.ceil() as u32) {
             break;
         }
 
         let same_timestep: bool = time_delta == 0.0;
 
-        if same_timestep {
-            match chord.try_add(event.get_note(), time_delta) {
-                AddNoteResult::Duplicate => {
-                    println!("Tried to add duplicate event -- skipping");
-                }
-                AddNoteResult::Ok => {}
-            }
-        } else {
+        if !same_timestep {
             if !chord.is_none() {
                 encoding.push(chord.clone());
             }
             chord.reset();
-            chord.try_add(event.get_note(), time_delta);
+        }
+
+        if event.is_pedal() {
+            chord.set_pedal(event.get_note_ref().is_note_on(), time_delta);
+            continue;
+        }
+
+        match chord.try_add(event.get_note(), time_delta) {
+            AddNoteResult::Duplicate => {
+                println!("Tried to add duplicate event -- skipping");
+            }
+            AddNoteResult::Ok => {}
         }
     }
 
     encoding.push(chord.clone());
     encoding.push(Chord::end());
 
-    MIDIEncoding::new(encoding)
+    Ok(MIDIEncoding::with_counts(
+        encoding,
+        dropped_notes,
+        folded_notes,
+    ))
 }
 
-pub fn decode(midi: MIDIEncoding) -> Vec<NoteEvent> {
+// with a start marker, chords before it are ignored, and nothing after an end marker is played
+pub fn decode(midi: MIDIEncoding) -> DecodedEvents {
     let mut events: Vec<NoteEvent> = Vec::new();
+    let mut pedal: bool = false;
+    let mut sounding: [u32; KEYS] = [0; KEYS];
+    let mut started: bool = !midi.get_encoding().iter().any(|chord| chord.is_start());
 
     for chord in midi.get_encoding().iter() {
-        if chord.is_none() {
+        if chord.is_start() {
+            started = true;
+            continue;
+        }
+        if chord.is_end() && started {
+            break;
+        }
+        if chord.is_none() || !started {
             continue;
         }
 
-        let event: Vec<NoteEvent> = chord.get_events();
+        let event: Vec<NoteEvent> = chord.get_events(pedal, &mut sounding);
+        pedal = chord.is_pedal_down();
 
         for e in event {
             events.push(e);
         }
     }
 
-    events
+    // notes never released would ring on forever once written
+    let stuck: u32 = sounding.iter().sum();
+    for (key, count) in sounding.iter().enumerate() {
+        for _ in 0..*count {
+            events.push(NoteEvent::new(0.0, key as u8, false));
+        }
+    }
+
+    DecodedEvents::new(events, stuck as usize)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::midi::parse_midi;
+    use crate::encoding::ChordLayout;
+    use crate::midi::{parse_midi, write_midi};
+    use crate::span::{events_to_spans, stuck_notes, NoteSpan};
+    use crate::types::{EncodingIndex, TimeScale};
 
     #[test]
     fn encode_simple_midi() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Timing_Test.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
         let data: EncodingData = EncodingData::new(events);
-        let midi: MIDIEncoding = encode(data);
+        let midi: MIDIEncoding = encode(data).expect("Failed to encode events");
         for (i, note) in midi.get_encoding().iter().enumerate() {
             println!("Chord {i}: {:?}", note);
         }
@@ -79,10 +159,11 @@ mod tests {
 
     #[test]
     fn encode_complex_midi() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Double_Note_Test.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
         let data: EncodingData = EncodingData::new(events);
 
-        let midi: MIDIEncoding = encode(data);
+        let midi: MIDIEncoding = encode(data).expect("Failed to encode events");
         for (i, vector) in midi.get_encoding().iter().enumerate() {
             println!("Chord {i}: {:?}", vector);
         }
@@ -90,7 +171,8 @@ mod tests {
 
     #[test]
     fn encode_test_data() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Data_Test.midi");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
 
         let mut total_time: f32 = 0.0;
         for event in &events {
@@ -99,9 +181,9 @@ mod tests {
         println!("Total time: {total_time}");
         let data: EncodingData = EncodingData::new(events);
 
-        let midi: MIDIEncoding = encode(data);
+        let midi: MIDIEncoding = encode(data).expect("Failed to encode events");
 
-        let decoded_events: Vec<NoteEvent> = decode(midi);
+        let decoded_events: Vec<NoteEvent> = decode(midi).into_events();
 
         total_time = 0.0;
         for event in &decoded_events {
@@ -112,24 +194,305 @@ mod tests {
 
     #[test]
     fn simple_encode_and_decode() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Timing_Test.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
         let data: EncodingData = EncodingData::new(events);
-        let encoding: MIDIEncoding = encode(data);
+        let encoding: MIDIEncoding = encode(data).expect("Failed to encode events");
 
-        let decoded: Vec<NoteEvent> = decode(encoding);
-        let copy_events: Vec<NoteEvent> = parse_midi("./tests/Timing_Test.mid");
+        let decoded: Vec<NoteEvent> = decode(encoding).into_events();
+        let copy_events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
 
         assert_eq!(decoded, copy_events);
     }
 
+    #[test]
+    fn pedal_encode_and_decode() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(250.0, 60, false),
+            NoteEvent::new(0.0, 64, true),
+            NoteEvent::pedal(250.0, false),
+            NoteEvent::new(100.0, 64, false),
+        ];
+        let data: EncodingData = EncodingData::new(events.clone());
+        let encoding: MIDIEncoding = encode(data).expect("Failed to encode events");
+
+        let pedal_flags: Vec<f32> = encoding
+            .get_encoding()
+            .iter()
+            .map(|chord| chord.get_encoding()[EncodingIndex::pedal_index()])
+            .collect();
+        assert_eq!(pedal_flags, vec![0.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+
+        let decoded: Vec<NoteEvent> = decode(encoding).into_events();
+        let expected: Vec<NoteEvent> = vec![
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(250.0, 60, false),
+            NoteEvent::new(0.0, 64, true),
+            NoteEvent::pedal(250.0, false),
+            NoteEvent::new(100.0, 64, false),
+        ];
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn sustained_encoding() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(250.0, 60, false),
+            NoteEvent::pedal(250.0, false),
+        ];
+        let mut data: EncodingData = EncodingData::new(events);
+        data.set_sustain(true);
+
+        let decoded: Vec<NoteEvent> =
+            decode(encode(data).expect("Failed to encode events")).into_events();
+        let expected: Vec<NoteEvent> = vec![
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::pedal(500.0, false),
+            NoteEvent::new(0.0, 60, false),
+        ];
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn quantization_does_not_drift() {
+        let events: Vec<NoteEvent> = (0..1000)
+            .map(|i| NoteEvent::new(3.0, 60, i % 2 == 0))
+            .collect();
+        let mut data: EncodingData = EncodingData::new(events);
+        data.set_quantization(5.0);
+
+        let decoded: Vec<NoteEvent> =
+            decode(encode(data).expect("Failed to encode events")).into_events();
+        let total_time: f32 = decoded.iter().map(|e| e.get_time_delta()).sum();
+
+        assert_eq!(total_time, 3000.0);
+    }
+
+    #[test]
+    fn unquantized_data_round_trip() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let data: EncodingData = EncodingData::new(events.clone());
+        let decoded: Vec<NoteEvent> =
+            decode(encode(data).expect("Failed to encode events")).into_events();
+
+        write_midi(&decoded, "./tests/output/Data_round_trip.mid")
+            .expect("Failed to write MIDI file");
+        let rewritten: Vec<NoteEvent> =
+            parse_midi("./tests/output/Data_round_trip.mid").expect("Failed to parse MIDI file");
+
+        assert_eq!(rewritten.len(), events.len());
+
+        // events sharing a time may be reordered, so compare the notes they make up
+        let sorted_spans = |events: &[NoteEvent]| -> Vec<NoteSpan> {
+            let mut spans: Vec<NoteSpan> = events_to_spans(events);
+            spans.sort_by(|a, b| {
+                a.get_onset_ms()
+                    .total_cmp(&b.get_onset_ms())
+                    .then(a.get_key().cmp(&b.get_key()))
+            });
+            spans
+        };
+
+        // written files use 480 ticks per beat at 120 bpm, so times may only move by half a tick
+        for (original, copy) in sorted_spans(&events)
+            .iter()
+            .zip(sorted_spans(&rewritten).iter())
+        {
+            assert_eq!(original.get_key(), copy.get_key());
+            assert_eq!(original.get_velocity(), copy.get_velocity());
+            assert!((original.get_onset_ms() - copy.get_onset_ms()).abs() <= 0.53);
+            assert!((original.get_offset_ms() - copy.get_offset_ms()).abs() <= 0.53);
+        }
+    }
+
+    fn round_trip(events: Vec<NoteEvent>) -> Vec<NoteEvent> {
+        decode(encode(EncodingData::new(events)).expect("Failed to encode events")).into_events()
+    }
+
+    #[test]
+    fn repeated_notes_round_trip() {
+        // the release and re-strike share a time and were added press first
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(100.0, 60, true),
+            NoteEvent::new(0.0, 60, false),
+            NoteEvent::new(100.0, 60, false),
+        ];
+
+        assert_eq!(
+            round_trip(events),
+            vec![
+                NoteEvent::new(0.0, 60, true),
+                NoteEvent::new(100.0, 60, false),
+                NoteEvent::new(0.0, 60, true),
+                NoteEvent::new(100.0, 60, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_notes_round_trip() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(50.0, 60, true),
+            NoteEvent::new(50.0, 60, false),
+            NoteEvent::new(50.0, 60, false),
+        ];
+
+        assert_eq!(round_trip(events.clone()), events);
+        assert!(stuck_notes(&round_trip(events)).is_empty());
+    }
+
+    #[test]
+    fn zero_length_notes_round_trip() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(0.0, 60, false),
+            NoteEvent::new(100.0, 62, true),
+            NoteEvent::new(100.0, 62, false),
+            NoteEvent::new(0.0, 62, true),
+            NoteEvent::new(0.0, 62, false),
+        ];
+
+        assert_eq!(round_trip(events.clone()), events);
+        assert!(stuck_notes(&round_trip(events)).is_empty());
+    }
+
+    #[test]
+    fn stuck_notes_are_released() {
+        let chords: Vec<Chord> = vec![
+            Chord::new(NoteEvent::new(0.0, 60, true), 0.0),
+            Chord::new(NoteEvent::new(0.0, 64, true), 100.0),
+            Chord::new(NoteEvent::new(0.0, 64, false), 100.0),
+        ];
+        let decoded: DecodedEvents = decode(MIDIEncoding::new(chords));
+        assert_eq!(decoded.get_stuck_notes(), 1);
+
+        let decoded: Vec<NoteEvent> = decoded.into_events();
+        assert!(stuck_notes(&decoded).is_empty());
+        assert_eq!(decoded.last(), Some(&NoteEvent::new(0.0, 60, false)));
+    }
+
+    #[test]
+    fn double_note_round_trip_keeps_notes() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
+        let decoded: Vec<NoteEvent> = round_trip(events.clone());
+
+        assert!(stuck_notes(&decoded).is_empty());
+        assert_eq!(events_to_spans(&decoded), events_to_spans(&events));
+    }
+
+    fn out_of_range_events() -> Vec<NoteEvent> {
+        vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(100.0, 9, true),
+            NoteEvent::new(100.0, 60, false),
+            NoteEvent::new(100.0, 9, false),
+            NoteEvent::new(0.0, 120, true),
+            NoteEvent::new(100.0, 120, false),
+        ]
+    }
+
+    #[test]
+    fn drop_out_of_range_keys() {
+        let data: EncodingData = EncodingData::new(out_of_range_events());
+        let encoding: MIDIEncoding = encode(data).expect("Failed to encode events");
+        assert_eq!(encoding.get_dropped_notes(), 2);
+
+        let decoded: Vec<NoteEvent> = decode(encoding).into_events();
+        let expected: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(200.0, 60, false),
+        ];
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn fold_out_of_range_keys() {
+        let mut data: EncodingData = EncodingData::new(out_of_range_events());
+        data.set_key_policy(OutOfRangePolicy::Fold);
+        let encoding: MIDIEncoding = encode(data).expect("Failed to encode events");
+        assert_eq!(encoding.get_dropped_notes(), 0);
+        assert_eq!(encoding.get_folded_notes(), 2);
+
+        let keys: Vec<u8> = decode(encoding)
+            .into_events()
+            .iter()
+            .map(|e| e.get_note_ref().get_key())
+            .collect();
+        assert_eq!(keys, vec![60, 21, 60, 21, 108, 108]);
+    }
+
+    #[test]
+    fn error_on_out_of_range_keys() {
+        let mut data: EncodingData = EncodingData::new(out_of_range_events());
+        data.set_key_policy(OutOfRangePolicy::Error);
+        let result: Result<MIDIEncoding, MidiError> = encode(data);
+        assert!(matches!(result, Err(MidiError::KeyOutOfRange(9))));
+    }
+
+    #[test]
+    fn markers_survive_vectors() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
+        let encoding: MIDIEncoding =
+            encode(EncodingData::new(events.clone())).expect("Failed to encode events");
+
+        let layout: ChordLayout = ChordLayout::new(TimeScale::Raw);
+        let vectors: Vec<Vec<f32>> = encoding.to_vectors(&layout);
+        assert_eq!(vectors[0][EncodingIndex::start_index()], 1.0);
+        assert_eq!(vectors[vectors.len() - 1][EncodingIndex::end_index()], 1.0);
+
+        let rebuilt: MIDIEncoding = MIDIEncoding::from_vector(vectors, 0.5, &layout);
+        assert!(rebuilt.get_encoding()[0].is_start());
+        assert!(rebuilt.get_encoding()[rebuilt.len() - 1].is_end());
+        // vectors keep notes and times but not velocities or the order within a chord
+        let decoded: Vec<NoteEvent> = decode(rebuilt).into_events();
+        let total_time =
+            |events: &[NoteEvent]| -> f32 { events.iter().map(|e| e.get_time_delta()).sum() };
+        assert_eq!(decoded.len(), events.len());
+        assert_eq!(total_time(&decoded), total_time(&events));
+    }
+
+    #[test]
+    fn decode_stops_at_markers() {
+        let chords: Vec<Chord> = vec![
+            Chord::new(NoteEvent::new(0.0, 50, true), 100.0),
+            Chord::start(),
+            Chord::new(NoteEvent::new(0.0, 60, true), 0.0),
+            Chord::new(NoteEvent::new(0.0, 60, false), 100.0),
+            Chord::end(),
+            Chord::new(NoteEvent::new(0.0, 70, true), 100.0),
+        ];
+
+        assert_eq!(
+            decode(MIDIEncoding::new(chords)).into_events(),
+            vec![
+                NoteEvent::new(0.0, 60, true),
+                NoteEvent::new(100.0, 60, false)
+            ]
+        );
+    }
+
     #[test]
     fn complex_encode_and_decode() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Double_Note_Test.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
         let data: EncodingData = EncodingData::new(events);
-        let encoding: MIDIEncoding = encode(data);
+        let encoding: MIDIEncoding = encode(data).expect("Failed to encode events");
 
-        let decoded: Vec<NoteEvent> = decode(encoding);
-        let copy_events: Vec<NoteEvent> = parse_midi("./tests/Double_Note_Test.mid");
+        let decoded: Vec<NoteEvent> = decode(encoding).into_events();
+        let copy_events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
 
         println!("Decoded: {:#?}", decoded);
         println!("Original: {:#?}", copy_events);
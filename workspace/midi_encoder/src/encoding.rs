@@ -0,0 +1,146 @@
+// builtin
+
+// external
+
+// internal
+use crate::piano_roll::{RollIndex, ROLL_KEYS};
+use crate::types::{
+    Chord, EncodingIndex, Note, OutOfRangePolicy, TimeScale, HIGHEST_KEY, LOWEST_KEY,
+};
+
+// how chords are laid out as model vectors
+pub trait Encoding {
+    fn vector_length(&self) -> usize;
+
+    fn encode_chord(&self, chord: &Chord) -> Vec<f32>;
+
+    fn decode_vector(&self, vector: &[f32], cutoff: f32) -> Chord;
+}
+
+// interleaved on/off and note slots per key, followed by time delta, start, end and pedal
+#[derive(Default)]
+pub struct ChordLayout {
+    time_scale: TimeScale,
+}
+
+impl ChordLayout {
+    pub fn new(time_scale: TimeScale) -> ChordLayout {
+        ChordLayout { time_scale }
+    }
+}
+
+impl Encoding for ChordLayout {
+    fn vector_length(&self) -> usize {
+        self.time_scale.vector_length()
+    }
+
+    fn encode_chord(&self, chord: &Chord) -> Vec<f32> {
+        chord
+            .get_scaled_encoding(OutOfRangePolicy::Drop, self.time_scale)
+            .expect("Dropping out of range notes cannot fail")
+    }
+
+    fn decode_vector(&self, vector: &[f32], cutoff: f32) -> Chord {
+        Chord::from_scaled_vec(vector, cutoff, self.time_scale)
+    }
+}
+
+/*
+ frame chords as the onset and sustain planes of a piano roll, without its offsets.
+ time is implied by the frame period rather than stored in the vector.
+*/
+pub struct FrameLayout {
+    frame_ms: f32,
+}
+
+impl FrameLayout {
+    pub fn new(frame_ms: f32) -> FrameLayout {
+        FrameLayout { frame_ms }
+    }
+}
+
+impl Encoding for FrameLayout {
+    fn vector_length(&self) -> usize {
+        2 * ROLL_KEYS
+    }
+
+    fn encode_chord(&self, chord: &Chord) -> Vec<f32> {
+        let mut vector: Vec<f32> = vec![0.0; self.vector_length()];
+
+        for note in chord.get_notes_ref() {
+            let key: u8 = note.get_key();
+            if !EncodingIndex::in_range(key) {
+                continue;
+            }
+
+            vector[RollIndex::sustain_index(key)] = 1.0;
+            if note.is_note_on() {
+                vector[RollIndex::onset_index(key)] = 1.0;
+            }
+        }
+
+        vector
+    }
+
+    fn decode_vector(&self, vector: &[f32], cutoff: f32) -> Chord {
+        if vector.len() != self.vector_length() {
+            panic!("Not a valid frame encoding");
+        }
+
+        let notes: Vec<Note> = (LOWEST_KEY..=HIGHEST_KEY)
+            .filter(|key| vector[RollIndex::sustain_index(*key)] >= cutoff)
+            .map(|key| Note::new(key, vector[RollIndex::onset_index(key)] >= cutoff))
+            .collect();
+
+        Chord::frame(notes, self.frame_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piano_roll::PianoRoll;
+    use crate::span::NoteSpan;
+    use crate::types::{MIDIEncoding, TimeBins, ENCODING_LENGTH};
+
+    #[test]
+    fn chord_layout_round_trip() {
+        let mut chord: Chord = Chord::frame(vec![Note::new(60, true)], 25.0);
+        chord.try_add(Note::new(64, false), 25.0);
+
+        let layout: ChordLayout = ChordLayout::new(TimeScale::Raw);
+        let vector: Vec<f32> = layout.encode_chord(&chord);
+        assert_eq!(vector.len(), layout.vector_length());
+
+        let decoded: Chord = layout.decode_vector(&vector, 0.5);
+        assert_eq!(decoded.get_notes_ref(), chord.get_notes_ref());
+        assert_eq!(decoded.get_time_delta(), 25.0);
+
+        let bins: TimeBins = TimeBins::new(32, 1000.0).expect("Bin count should be valid");
+        let binned: ChordLayout = ChordLayout::new(TimeScale::Bins(bins));
+        let vector: Vec<f32> = binned.encode_chord(&chord);
+        assert_eq!(vector.len(), ENCODING_LENGTH + 32);
+        assert_eq!(
+            binned.decode_vector(&vector, 0.5).get_notes_ref(),
+            chord.get_notes_ref()
+        );
+    }
+
+    #[test]
+    fn frame_layout_round_trip() {
+        let spans: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 0.0, 30.0, 64),
+            NoteSpan::new(64, 10.0, 50.0, 64),
+        ];
+        let encoding: MIDIEncoding = PianoRoll::from_spans(&spans, 10.0, 6).to_encoding();
+
+        let layout: FrameLayout = FrameLayout::new(10.0);
+        let vectors: Vec<Vec<f32>> = encoding.to_vectors(&layout);
+        assert!(vectors.iter().all(|v| v.len() == 2 * ROLL_KEYS));
+
+        let decoded: MIDIEncoding = MIDIEncoding::from_vector(vectors, 0.5, &layout);
+        for (original, copy) in encoding.get_encoding().iter().zip(decoded.get_encoding()) {
+            assert_eq!(original.get_notes_ref(), copy.get_notes_ref());
+        }
+    }
+}
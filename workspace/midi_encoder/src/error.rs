@@ -0,0 +1,92 @@
+// builtin
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+// external
+
+// internal
+use crate::tokens::Token;
+
+#[derive(Debug)]
+pub enum MidiError {
+    Io(io::Error),
+    Parse(midly::Error),
+    KeyOutOfRange(u8),
+    TimeOutOfRange(f32),
+    InvalidToken(Token),
+    InvalidBinCount(usize),
+    InvalidTimeRange(f32),
+    InvalidTimeSignature(u8, u8),
+    TooFewBeats(usize),
+    InvalidBeat(usize, f64),
+}
+
+impl Display for MidiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::Io(err) => write!(f, "Failed to access MIDI file: {}", err),
+            MidiError::Parse(err) => write!(f, "Failed to parse MIDI file: {}", err),
+            MidiError::KeyOutOfRange(key) => write!(f, "Key {} is outside the MIDI range", key),
+            MidiError::TimeOutOfRange(ms) => {
+                write!(
+                    f,
+                    "Time delta of {}ms cannot be written as a MIDI delta",
+                    ms
+                )
+            }
+            MidiError::InvalidToken(token) => {
+                write!(f, "Token {:?} is outside the vocabulary", token)
+            }
+            MidiError::InvalidBinCount(count) => {
+                write!(f, "Time deltas need at least 2 bins, got {}", count)
+            }
+            MidiError::InvalidTimeRange(max_ms) => {
+                write!(
+                    f,
+                    "Time deltas need a positive finite range, got {}ms",
+                    max_ms
+                )
+            }
+            MidiError::InvalidTimeSignature(numerator, denominator) => {
+                write!(
+                    f,
+                    "Time signature {}/{} needs beats and a power of two denominator",
+                    numerator, denominator
+                )
+            }
+            MidiError::TooFewBeats(count) => {
+                write!(f, "A beat grid needs at least 2 beats, got {}", count)
+            }
+            MidiError::InvalidBeat(index, ms) => {
+                write!(
+                    f,
+                    "Beat {} at {}ms is not finite or not after the beat before it",
+                    index, ms
+                )
+            }
+        }
+    }
+}
+
+impl Error for MidiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MidiError::Io(err) => Some(err),
+            MidiError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MidiError {
+    fn from(err: io::Error) -> Self {
+        MidiError::Io(err)
+    }
+}
+
+impl From<midly::Error> for MidiError {
+    fn from(err: midly::Error) -> Self {
+        MidiError::Parse(err)
+    }
+}
@@ -0,0 +1,522 @@
+// builtin
+
+// external
+
+// internal
+use crate::piano_roll::PianoRoll;
+use crate::span::{events_to_spans, NoteSpan};
+use crate::types::{NoteEvent, HIGHEST_KEY, LOWEST_KEY};
+
+/*
+ tolerances for counting an estimated note as a reference note, the same as mir_eval's:
+ onsets within 50 ms, offsets within 20% of the reference note's length or 50 ms,
+ whichever is longer, and velocities within 10% once rescaled to the reference.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct MatchConfig {
+    onset_tolerance_ms: f64,
+    offsets: bool,
+    offset_ratio: f64,
+    offset_min_ms: f64,
+    velocity: bool,
+    velocity_tolerance: f64,
+    frame_ms: f64,
+}
+
+impl MatchConfig {
+    pub fn new(onset_tolerance_ms: f64) -> MatchConfig {
+        MatchConfig {
+            onset_tolerance_ms,
+            offsets: false,
+            offset_ratio: 0.2,
+            offset_min_ms: 50.0,
+            velocity: false,
+            velocity_tolerance: 0.1,
+            frame_ms: 10.0,
+        }
+    }
+
+    pub fn set_offsets(&mut self, offsets: bool) {
+        self.offsets = offsets;
+    }
+
+    pub fn set_offset_tolerance(&mut self, ratio: f64, min_ms: f64) {
+        self.offset_ratio = ratio;
+        self.offset_min_ms = min_ms;
+    }
+
+    pub fn set_velocity(&mut self, velocity: bool) {
+        self.velocity = velocity;
+    }
+
+    pub fn set_velocity_tolerance(&mut self, tolerance: f64) {
+        self.velocity_tolerance = tolerance;
+    }
+
+    // frame length for frame level scores
+    pub fn set_frame_ms(&mut self, frame_ms: f64) {
+        self.frame_ms = frame_ms;
+    }
+
+    pub fn onset_tolerance_ms(&self) -> f64 {
+        self.onset_tolerance_ms
+    }
+
+    pub fn matches_offsets(&self) -> bool {
+        self.offsets
+    }
+
+    pub fn matches_velocity(&self) -> bool {
+        self.velocity
+    }
+
+    pub fn frame_ms(&self) -> f64 {
+        self.frame_ms
+    }
+
+    fn offset_tolerance_ms(&self, reference: &NoteSpan) -> f64 {
+        (reference.get_duration_ms() * self.offset_ratio).max(self.offset_min_ms)
+    }
+}
+
+impl Default for MatchConfig {
+    fn default() -> MatchConfig {
+        MatchConfig::new(50.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scores {
+    precision: f64,
+    recall: f64,
+    f1: f64,
+}
+
+impl Scores {
+    // empty estimates or references score zero rather than dividing by zero
+    pub fn from_counts(matched: usize, estimated: usize, reference: usize) -> Scores {
+        let precision: f64 = if estimated > 0 {
+            matched as f64 / estimated as f64
+        } else {
+            0.0
+        };
+        let recall: f64 = if reference > 0 {
+            matched as f64 / reference as f64
+        } else {
+            0.0
+        };
+        let f1: f64 = if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        };
+
+        Scores {
+            precision,
+            recall,
+            f1,
+        }
+    }
+
+    pub fn get_precision(&self) -> f64 {
+        self.precision
+    }
+
+    pub fn get_recall(&self) -> f64 {
+        self.recall
+    }
+
+    pub fn get_f1(&self) -> f64 {
+        self.f1
+    }
+}
+
+/*
+ pairs of (reference, estimate) indices, each note used at most once. the pairs are a
+ maximum matching, so a note that could match several others never blocks a better pairing.
+*/
+pub fn match_notes(
+    reference: &[NoteSpan],
+    estimate: &[NoteSpan],
+    config: &MatchConfig,
+) -> Vec<(usize, usize)> {
+    let timing = |r: &NoteSpan, e: &NoteSpan| -> bool {
+        r.get_key() == e.get_key()
+            && (r.get_onset_ms() - e.get_onset_ms()).abs() <= config.onset_tolerance_ms
+            && (!config.offsets
+                || (r.get_offset_ms() - e.get_offset_ms()).abs() <= config.offset_tolerance_ms(r))
+    };
+
+    if !config.velocity {
+        return max_matching(reference, estimate, config.onset_tolerance_ms, timing);
+    }
+
+    /*
+     velocities are only meaningful relative to each other, so the reference is scaled to
+     [0, 1] and the estimate is fit to it over the notes that already match in time
+    */
+    let timed: Vec<(usize, usize)> =
+        max_matching(reference, estimate, config.onset_tolerance_ms, timing);
+    let lowest: f64 = reference
+        .iter()
+        .map(|n| n.get_velocity() as f64)
+        .fold(f64::INFINITY, f64::min);
+    let highest: f64 = reference
+        .iter()
+        .map(|n| n.get_velocity() as f64)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let range: f64 = (highest - lowest).max(1.0);
+    let normalized = |n: &NoteSpan| -> f64 { (n.get_velocity() as f64 - lowest) / range };
+
+    let pairs: Vec<(f64, f64)> = timed
+        .iter()
+        .map(|(r, e)| {
+            (
+                estimate[*e].get_velocity() as f64,
+                normalized(&reference[*r]),
+            )
+        })
+        .collect();
+    let (slope, intercept) = fit_line(&pairs);
+
+    max_matching(reference, estimate, config.onset_tolerance_ms, |r, e| {
+        timing(r, e)
+            && (slope * e.get_velocity() as f64 + intercept - normalized(r)).abs()
+                <= config.velocity_tolerance
+    })
+}
+
+// least squares y = slope * x + intercept, flat through the mean when x doesn't vary
+fn fit_line(points: &[(f64, f64)]) -> (f64, f64) {
+    if points.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n: f64 = points.len() as f64;
+    let mean_x: f64 = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y: f64 = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let mut covariance: f64 = 0.0;
+    let mut variance: f64 = 0.0;
+    for (x, y) in points {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance += (x - mean_x).powi(2);
+    }
+
+    if variance == 0.0 {
+        return (0.0, mean_y);
+    }
+    let slope: f64 = covariance / variance;
+    (slope, mean_y - slope * mean_x)
+}
+
+/*
+ augmenting paths from every reference note in turn. candidates only come from the
+ estimates with onsets inside the tolerance, every rule here requires that anyway.
+*/
+fn max_matching<F>(
+    reference: &[NoteSpan],
+    estimate: &[NoteSpan],
+    onset_tolerance_ms: f64,
+    is_match: F,
+) -> Vec<(usize, usize)>
+where
+    F: Fn(&NoteSpan, &NoteSpan) -> bool,
+{
+    let mut by_onset: Vec<usize> = (0..estimate.len()).collect();
+    by_onset.sort_by(|a, b| {
+        estimate[*a]
+            .get_onset_ms()
+            .total_cmp(&estimate[*b].get_onset_ms())
+    });
+
+    let candidates: Vec<Vec<usize>> = reference
+        .iter()
+        .map(|r| {
+            let start: usize = by_onset.partition_point(|e| {
+                estimate[*e].get_onset_ms() < r.get_onset_ms() - onset_tolerance_ms
+            });
+            by_onset[start..]
+                .iter()
+                .take_while(|e| {
+                    estimate[**e].get_onset_ms() <= r.get_onset_ms() + onset_tolerance_ms
+                })
+                .copied()
+                .filter(|e| is_match(r, &estimate[*e]))
+                .collect()
+        })
+        .collect();
+
+    // visited marks the reference note whose search last reached each estimate
+    let mut owner: Vec<Option<usize>> = vec![None; estimate.len()];
+    let mut visited: Vec<usize> = vec![usize::MAX; estimate.len()];
+    for r in 0..reference.len() {
+        augment(r, r, &candidates, &mut owner, &mut visited);
+    }
+
+    let mut pairs: Vec<(usize, usize)> = owner
+        .iter()
+        .enumerate()
+        .filter_map(|(e, r)| r.map(|r| (r, e)))
+        .collect();
+    pairs.sort();
+    pairs
+}
+
+fn augment(
+    r: usize,
+    search: usize,
+    candidates: &[Vec<usize>],
+    owner: &mut [Option<usize>],
+    visited: &mut [usize],
+) -> bool {
+    for e in candidates[r].iter() {
+        if visited[*e] == search {
+            continue;
+        }
+        visited[*e] = search;
+
+        let free: bool = match owner[*e] {
+            None => true,
+            Some(other) => augment(other, search, candidates, owner, visited),
+        };
+        if free {
+            owner[*e] = Some(r);
+            return true;
+        }
+    }
+    false
+}
+
+pub fn note_scores(reference: &[NoteSpan], estimate: &[NoteSpan], config: &MatchConfig) -> Scores {
+    let matched: usize = match_notes(reference, estimate, config).len();
+    Scores::from_counts(matched, estimate.len(), reference.len())
+}
+
+// sounding keys compared frame by frame, frames missing from either roll count as silent
+pub fn frame_scores(reference: &PianoRoll, estimate: &PianoRoll) -> Scores {
+    let frames: usize = reference.len().max(estimate.len());
+    let mut matched: usize = 0;
+    let mut estimated: usize = 0;
+    let mut expected: usize = 0;
+
+    for frame in 0..frames {
+        for key in LOWEST_KEY..=HIGHEST_KEY {
+            let in_reference: bool = frame < reference.len() && reference.is_sustained(frame, key);
+            let in_estimate: bool = frame < estimate.len() && estimate.is_sustained(frame, key);
+            matched += (in_reference && in_estimate) as usize;
+            estimated += in_estimate as usize;
+            expected += in_reference as usize;
+        }
+    }
+
+    Scores::from_counts(matched, estimated, expected)
+}
+
+// the usual transcription report: notes by onset, by onset and offset, with velocity, and frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Evaluation {
+    onsets: Scores,
+    offsets: Scores,
+    velocity: Scores,
+    frames: Scores,
+    reference_notes: usize,
+    estimated_notes: usize,
+}
+
+impl Evaluation {
+    pub fn get_onsets(&self) -> Scores {
+        self.onsets
+    }
+
+    pub fn get_offsets(&self) -> Scores {
+        self.offsets
+    }
+
+    pub fn get_velocity(&self) -> Scores {
+        self.velocity
+    }
+
+    pub fn get_frames(&self) -> Scores {
+        self.frames
+    }
+
+    pub fn get_reference_notes(&self) -> usize {
+        self.reference_notes
+    }
+
+    pub fn get_estimated_notes(&self) -> usize {
+        self.estimated_notes
+    }
+
+    pub fn print(&self) -> String {
+        let mut result: String = format!(
+            "Reference notes: {}, estimated notes: {}\n",
+            self.reference_notes, self.estimated_notes
+        );
+        for (name, scores) in [
+            ("Onset", self.onsets),
+            ("Onset + offset", self.offsets),
+            ("Onset + offset + velocity", self.velocity),
+            ("Frame", self.frames),
+        ] {
+            result.push_str(&format!(
+                "{}: P {:.3}, R {:.3}, F1 {:.3}\n",
+                name,
+                scores.get_precision(),
+                scores.get_recall(),
+                scores.get_f1()
+            ));
+        }
+        result
+    }
+}
+
+// the offset and velocity switches in config are ignored, every level is scored
+pub fn evaluate(
+    reference: &[NoteEvent],
+    estimate: &[NoteEvent],
+    config: &MatchConfig,
+) -> Evaluation {
+    let reference: Vec<NoteSpan> = events_to_spans(reference);
+    let estimate: Vec<NoteSpan> = events_to_spans(estimate);
+
+    let mut onset_config: MatchConfig = *config;
+    onset_config.set_offsets(false);
+    onset_config.set_velocity(false);
+    let mut offset_config: MatchConfig = onset_config;
+    offset_config.set_offsets(true);
+    let mut velocity_config: MatchConfig = offset_config;
+    velocity_config.set_velocity(true);
+
+    let end_ms: f64 = reference
+        .iter()
+        .chain(estimate.iter())
+        .map(|n| n.get_offset_ms())
+        .fold(0.0, f64::max);
+    let num_frames: usize = (end_ms / config.frame_ms).ceil() as usize;
+
+    Evaluation {
+        onsets: note_scores(&reference, &estimate, &onset_config),
+        offsets: note_scores(&reference, &estimate, &offset_config),
+        velocity: note_scores(&reference, &estimate, &velocity_config),
+        frames: frame_scores(
+            &PianoRoll::from_spans(&reference, config.frame_ms, num_frames),
+            &PianoRoll::from_spans(&estimate, config.frame_ms, num_frames),
+        ),
+        reference_notes: reference.len(),
+        estimated_notes: estimate.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+    use crate::span::spans_to_events;
+
+    #[test]
+    fn onset_tolerance() {
+        let reference: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 0.0, 500.0, 80),
+            NoteSpan::new(64, 500.0, 1000.0, 80),
+            NoteSpan::new(67, 1000.0, 1500.0, 80),
+        ];
+        let estimate: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 30.0, 500.0, 80),
+            NoteSpan::new(64, 570.0, 1000.0, 80),
+            NoteSpan::new(66, 1000.0, 1500.0, 80),
+            NoteSpan::new(67, 990.0, 1200.0, 80),
+        ];
+
+        let config: MatchConfig = MatchConfig::default();
+        assert_eq!(
+            match_notes(&reference, &estimate, &config),
+            vec![(0, 0), (2, 3)]
+        );
+        let scores: Scores = note_scores(&reference, &estimate, &config);
+        assert_eq!(scores.get_precision(), 0.5);
+        assert!((scores.get_recall() - 2.0 / 3.0).abs() < 1e-9);
+        assert!((scores.get_f1() - 4.0 / 7.0).abs() < 1e-9);
+
+        // the last note ends 300 ms early, more than a fifth of its length
+        let mut offsets: MatchConfig = MatchConfig::default();
+        offsets.set_offsets(true);
+        assert_eq!(match_notes(&reference, &estimate, &offsets), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn matching_is_one_to_one() {
+        // the first estimate fits both references, the second only the later one
+        let reference: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 0.0, 100.0, 80),
+            NoteSpan::new(60, 40.0, 140.0, 80),
+        ];
+        let estimate: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 20.0, 120.0, 80),
+            NoteSpan::new(60, 85.0, 185.0, 80),
+        ];
+        assert_eq!(
+            match_notes(&reference, &estimate, &MatchConfig::default()),
+            vec![(0, 0), (1, 1)]
+        );
+
+        let twice: Vec<NoteSpan> = vec![NoteSpan::new(60, 0.0, 100.0, 80); 2];
+        let scores: Scores = note_scores(&reference[..1], &twice, &MatchConfig::default());
+        assert_eq!((scores.get_precision(), scores.get_recall()), (0.5, 1.0));
+    }
+
+    #[test]
+    fn velocity_is_relative() {
+        let reference: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 0.0, 500.0, 40),
+            NoteSpan::new(62, 500.0, 1000.0, 80),
+            NoteSpan::new(64, 1000.0, 1500.0, 120),
+        ];
+        // twice as quiet overall but in the same proportions, apart from the last note
+        let estimate: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 0.0, 500.0, 20),
+            NoteSpan::new(62, 500.0, 1000.0, 40),
+            NoteSpan::new(64, 1000.0, 1500.0, 30),
+        ];
+
+        let mut config: MatchConfig = MatchConfig::default();
+        config.set_velocity(true);
+        assert_eq!(match_notes(&reference, &estimate[..2], &config).len(), 2);
+        assert!(match_notes(&reference, &estimate, &config).len() < 3);
+    }
+
+    #[test]
+    fn frame_level() {
+        let reference: PianoRoll =
+            PianoRoll::from_spans(&[NoteSpan::new(60, 0.0, 100.0, 80)], 10.0, 20);
+        let estimate: PianoRoll =
+            PianoRoll::from_spans(&[NoteSpan::new(60, 50.0, 150.0, 80)], 10.0, 20);
+
+        let scores: Scores = frame_scores(&reference, &estimate);
+        assert_eq!((scores.get_precision(), scores.get_recall()), (0.5, 0.5));
+        assert_eq!(frame_scores(&reference, &reference).get_f1(), 1.0);
+        assert_eq!(
+            frame_scores(&reference, &PianoRoll::new(10.0, Vec::new())).get_f1(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn data_test_evaluation() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let evaluation: Evaluation = evaluate(&events, &events, &MatchConfig::default());
+        println!("{}", evaluation.print());
+        assert_eq!(evaluation.get_onsets().get_f1(), 1.0);
+        assert_eq!(evaluation.get_velocity().get_f1(), 1.0);
+        assert_eq!(evaluation.get_frames().get_f1(), 1.0);
+
+        // dropping every other note halves the recall
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+        let half: Vec<NoteSpan> = spans.iter().step_by(2).cloned().collect();
+        let evaluation: Evaluation =
+            evaluate(&events, &spans_to_events(&half), &MatchConfig::default());
+        assert_eq!(evaluation.get_onsets().get_precision(), 1.0);
+        assert!((evaluation.get_onsets().get_recall() - 0.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,478 @@
+// builtin
+
+// external
+
+// internal
+use crate::quantize::{BeatGrid, QuantizedNote};
+use crate::span::{event_times, KEYS};
+use crate::types::{HandSplit, Note, NoteEvent, DEFAULT_SPLIT_KEY, TICKS_PER_BEAT};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+// every key below split_key goes to the left hand
+pub fn split_at_key(events: &[NoteEvent], split_key: u8) -> Vec<Hand> {
+    assign_presses(events, |note: &Note| {
+        if note.get_key() < split_key {
+            Hand::Left
+        } else {
+            Hand::Right
+        }
+    })
+}
+
+pub struct HandConfig {
+    max_span: u8,
+    max_notes: usize,
+    chord_window_ms: f64,
+    continuity_weight: f32,
+}
+
+impl HandConfig {
+    // a hand can reach max_span semitones and hold max_notes keys at once
+    pub fn new(max_span: u8, max_notes: usize) -> HandConfig {
+        HandConfig {
+            max_span,
+            max_notes,
+            ..HandConfig::default()
+        }
+    }
+
+    // presses closer together than the window are played as one chord
+    pub fn set_chord_window_ms(&mut self, chord_window_ms: f64) {
+        self.chord_window_ms = chord_window_ms;
+    }
+
+    // cost per semitone a hand has to move from where it last played
+    pub fn set_continuity_weight(&mut self, continuity_weight: f32) {
+        self.continuity_weight = continuity_weight;
+    }
+
+    pub fn max_span(&self) -> u8 {
+        self.max_span
+    }
+
+    pub fn max_notes(&self) -> usize {
+        self.max_notes
+    }
+
+    pub fn chord_window_ms(&self) -> f64 {
+        self.chord_window_ms
+    }
+
+    pub fn continuity_weight(&self) -> f32 {
+        self.continuity_weight
+    }
+}
+
+impl Default for HandConfig {
+    fn default() -> HandConfig {
+        HandConfig {
+            max_span: 14,
+            max_notes: 5,
+            chord_window_ms: 30.0,
+            continuity_weight: 1.0,
+        }
+    }
+}
+
+// going past what a hand can reach costs far more than moving it
+const SPAN_COST: f32 = 24.0;
+const CROSSING_COST: f32 = 12.0;
+
+struct HandState {
+    position: f32,
+    held: Vec<(u8, f64)>,
+}
+
+impl HandState {
+    fn new(position: f32) -> HandState {
+        HandState {
+            position,
+            held: Vec::new(),
+        }
+    }
+
+    fn release_until(&mut self, time_ms: f64) {
+        self.held.retain(|(_, release_ms)| *release_ms > time_ms);
+    }
+
+    fn lowest(&self) -> Option<u8> {
+        self.held.iter().map(|(key, _)| *key).min()
+    }
+
+    fn highest(&self) -> Option<u8> {
+        self.held.iter().map(|(key, _)| *key).max()
+    }
+
+    // cost of this hand taking keys on top of the ones it still holds
+    fn cost(&self, keys: &[u8], config: &HandConfig) -> f32 {
+        if keys.is_empty() {
+            return 0.0;
+        }
+
+        let mut cost: f32 = 0.0;
+
+        let low: u8 = keys[0].min(self.lowest().unwrap_or(u8::MAX));
+        let high: u8 = keys[keys.len() - 1].max(self.highest().unwrap_or(0));
+        if high - low > config.max_span {
+            cost += SPAN_COST * (high - low - config.max_span) as f32;
+        }
+
+        let count: usize = keys.len() + self.held.len();
+        if count > config.max_notes {
+            cost += SPAN_COST * (count - config.max_notes) as f32;
+        }
+
+        let center: f32 = keys.iter().map(|k| *k as f32).sum::<f32>() / keys.len() as f32;
+        cost + config.continuity_weight * (center - self.position).abs()
+    }
+
+    fn take(&mut self, keys: &[u8], releases: &[f64]) {
+        if keys.is_empty() {
+            return;
+        }
+
+        let center: f32 = keys.iter().map(|k| *k as f32).sum::<f32>() / keys.len() as f32;
+        self.position = 0.5 * self.position + 0.5 * center;
+        self.held
+            .extend(keys.iter().copied().zip(releases.iter().copied()));
+    }
+}
+
+/*
+ presses are grouped into chords, and each chord is split between the hands at the point
+ that keeps both within reach, moves them the least from where they last played and
+ doesn't cross them over the keys the other hand is still holding.
+*/
+pub fn separate_hands(events: &[NoteEvent], config: &HandConfig) -> Vec<Hand> {
+    let mut left: HandState = HandState::new(48.0);
+    let mut right: HandState = HandState::new(72.0);
+    let mut press_hands: Vec<Hand> = Vec::new();
+
+    for chord in press_chords(events, config.chord_window_ms) {
+        left.release_until(chord.time_ms);
+        right.release_until(chord.time_ms);
+
+        let keys: Vec<u8> = chord.presses.iter().map(|(key, _)| *key).collect();
+        let releases: Vec<f64> = chord.presses.iter().map(|(_, r)| *r).collect();
+
+        let mut best: (f32, usize) = (f32::INFINITY, 0);
+        for split in 0..=keys.len() {
+            let (low, high) = keys.split_at(split);
+            let mut cost: f32 = left.cost(low, config) + right.cost(high, config);
+
+            if let (Some(top), Some(bottom)) = (low.last(), right.lowest()) {
+                cost += CROSSING_COST * top.saturating_sub(bottom) as f32;
+            }
+            if let (Some(bottom), Some(top)) = (high.first(), left.highest()) {
+                cost += CROSSING_COST * top.saturating_sub(*bottom) as f32;
+            }
+
+            if cost < best.0 {
+                best = (cost, split);
+            }
+        }
+
+        let split: usize = best.1;
+        left.take(&keys[..split], &releases[..split]);
+        right.take(&keys[split..], &releases[split..]);
+
+        // back to the order the presses came in
+        let mut hands: Vec<(usize, Hand)> = chord
+            .order
+            .iter()
+            .enumerate()
+            .map(|(i, order)| (*order, if i < split { Hand::Left } else { Hand::Right }))
+            .collect();
+        hands.sort_by_key(|(order, _)| *order);
+        press_hands.extend(hands.into_iter().map(|(_, hand)| hand));
+    }
+
+    let mut press_hands = press_hands.into_iter();
+    assign_presses(events, |_| press_hands.next().unwrap_or(Hand::Right))
+}
+
+// one hand's events with time deltas carried over the other hand's, pedals go to both
+pub fn hand_events(events: &[NoteEvent], hands: &[Hand], hand: Hand) -> Vec<NoteEvent> {
+    let mut part: Vec<NoteEvent> = Vec::new();
+    let mut last_time: f64 = 0.0;
+
+    for ((event, event_hand), time) in events.iter().zip(hands).zip(event_times(events)) {
+        if event.is_pedal() || *event_hand == hand {
+            let time_delta: f32 = (time - last_time) as f32;
+            part.push(event.clone().with_time_delta(time_delta).at_time(time));
+            last_time = time;
+        }
+    }
+
+    part
+}
+
+/*
+ hands for quantized notes, in the order the notes are given. the grid turns beats back
+ into ms for the heuristic. a score always has two staves, so no split falls back to
+ splitting at DEFAULT_SPLIT_KEY.
+*/
+pub fn quantized_hands(notes: &[QuantizedNote], split: HandSplit, grid: &BeatGrid) -> Vec<Hand> {
+    let split_key: u8 = match split {
+        HandSplit::SplitPoint(split_key) => split_key,
+        HandSplit::None => DEFAULT_SPLIT_KEY,
+        HandSplit::Heuristic => {
+            return heuristic_hands(notes, grid);
+        }
+    };
+
+    notes
+        .iter()
+        .map(|note| {
+            if note.get_key() < split_key {
+                Hand::Left
+            } else {
+                Hand::Right
+            }
+        })
+        .collect()
+}
+
+fn heuristic_hands(notes: &[QuantizedNote], grid: &BeatGrid) -> Vec<Hand> {
+    // (tick, releases before presses, note)
+    let mut timeline: Vec<(u64, bool, usize)> = Vec::with_capacity(notes.len() * 2);
+    for (i, note) in notes.iter().enumerate() {
+        timeline.push((note.get_onset_ticks(), true, i));
+        timeline.push((note.get_offset_ticks(), false, i));
+    }
+    timeline.sort();
+
+    // the chord window and release times are in ms
+    let mut last_ms: f64 = grid.time_at(0.0);
+    let events: Vec<NoteEvent> = timeline
+        .iter()
+        .map(|(tick, on, i)| {
+            let time_ms: f64 = grid.time_at(*tick as f64 / TICKS_PER_BEAT as f64);
+            let delta: f32 = (time_ms - last_ms) as f32;
+            last_ms = time_ms;
+            NoteEvent::new(delta, notes[*i].get_key(), *on)
+        })
+        .collect();
+
+    let mut hands: Vec<Hand> = vec![Hand::Right; notes.len()];
+    let config: HandConfig = HandConfig::default();
+    for ((_, on, i), hand) in timeline.iter().zip(separate_hands(&events, &config)) {
+        if *on {
+            hands[*i] = hand;
+        }
+    }
+
+    hands
+}
+
+struct PressChord {
+    time_ms: f64,
+    // key and release time, sorted by key
+    presses: Vec<(u8, f64)>,
+    // position of each sorted press in the order they were played
+    order: Vec<usize>,
+}
+
+fn press_chords(events: &[NoteEvent], chord_window_ms: f64) -> Vec<PressChord> {
+    let mut presses: Vec<(f64, u8, f64)> = Vec::new();
+    let mut held: Vec<Vec<usize>> = vec![Vec::new(); KEYS];
+    for (event, time_ms) in events.iter().zip(event_times(events)) {
+        if event.is_pedal() {
+            continue;
+        }
+
+        let note: &Note = event.get_note_ref();
+        let key: usize = note.get_key() as usize;
+        if note.is_note_on() {
+            held[key].push(presses.len());
+            presses.push((time_ms, note.get_key(), f64::INFINITY));
+        } else if !held[key].is_empty() {
+            let press: usize = held[key].remove(0);
+            presses[press].2 = time_ms;
+        }
+    }
+
+    let mut chords: Vec<PressChord> = Vec::new();
+    let mut start: usize = 0;
+    while start < presses.len() {
+        let time_ms: f64 = presses[start].0;
+        let end: usize = presses[start..]
+            .iter()
+            .position(|(onset, _, _)| *onset - time_ms > chord_window_ms)
+            .map_or(presses.len(), |p| start + p);
+
+        let mut sorted: Vec<(usize, u8, f64)> = (start..end)
+            .map(|i| (i - start, presses[i].1, presses[i].2))
+            .collect();
+        sorted.sort_by_key(|(_, key, _)| *key);
+
+        chords.push(PressChord {
+            time_ms,
+            presses: sorted.iter().map(|(_, key, r)| (*key, *r)).collect(),
+            order: sorted.iter().map(|(order, _, _)| *order).collect(),
+        });
+        start = end;
+    }
+
+    chords
+}
+
+// releases follow the hand of the press they end, pedal events are marked right
+pub(crate) fn assign_presses<F>(events: &[NoteEvent], mut press_hand: F) -> Vec<Hand>
+where
+    F: FnMut(&Note) -> Hand,
+{
+    let mut held: Vec<Vec<Hand>> = vec![Vec::new(); KEYS];
+    let mut hands: Vec<Hand> = Vec::with_capacity(events.len());
+
+    for event in events {
+        let note: &Note = event.get_note_ref();
+        let key: usize = note.get_key() as usize;
+
+        let hand: Hand = if event.is_pedal() {
+            Hand::Right
+        } else if note.is_note_on() {
+            let hand: Hand = press_hand(note);
+            held[key].push(hand);
+            hand
+        } else if held[key].is_empty() {
+            Hand::Right
+        } else {
+            held[key].remove(0)
+        };
+
+        hands.push(hand);
+    }
+
+    hands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_follow_presses() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 40, true),
+            NoteEvent::new(0.0, 76, true),
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(100.0, 40, false),
+            NoteEvent::new(0.0, 76, false),
+        ];
+
+        assert_eq!(
+            split_at_key(&events, 60),
+            vec![
+                Hand::Left,
+                Hand::Right,
+                Hand::Right,
+                Hand::Left,
+                Hand::Right
+            ]
+        );
+    }
+
+    fn chord(delta: f32, keys: &[u8]) -> Vec<NoteEvent> {
+        keys.iter()
+            .enumerate()
+            .map(|(i, key)| NoteEvent::new(if i == 0 { delta } else { 0.0 }, *key, true))
+            .collect()
+    }
+
+    #[test]
+    fn wide_chord_is_split() {
+        // two octaves apart can't be reached by one hand
+        let events: Vec<NoteEvent> = chord(0.0, &[48, 55, 64, 67, 72]);
+        assert_eq!(
+            separate_hands(&events, &HandConfig::default()),
+            vec![
+                Hand::Left,
+                Hand::Left,
+                Hand::Right,
+                Hand::Right,
+                Hand::Right
+            ]
+        );
+    }
+
+    #[test]
+    fn held_notes_limit_reach() {
+        // the left hand holds a low bass note, so the next low-middle note goes right
+        let mut events: Vec<NoteEvent> = chord(0.0, &[36, 76]);
+        events.extend(chord(100.0, &[57]));
+        events.push(NoteEvent::new(200.0, 36, false));
+        events.extend(chord(0.0, &[57]));
+
+        let hands: Vec<Hand> = separate_hands(&events, &HandConfig::default());
+        assert_eq!(hands[2], Hand::Right);
+        assert_eq!(hands[3], Hand::Left);
+        assert_eq!(hands[4], Hand::Left);
+    }
+
+    #[test]
+    fn presses_keep_their_order() {
+        // the higher key is played first within the chord window
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 84, true),
+            NoteEvent::new(10.0, 40, true),
+            NoteEvent::new(100.0, 84, false),
+            NoteEvent::new(0.0, 40, false),
+        ];
+        assert_eq!(
+            separate_hands(&events, &HandConfig::default()),
+            vec![Hand::Right, Hand::Left, Hand::Right, Hand::Left]
+        );
+    }
+
+    #[test]
+    fn hand_events_keep_timing() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 40, true),
+            NoteEvent::new(50.0, 76, true),
+            NoteEvent::pedal(25.0, true),
+            NoteEvent::new(25.0, 40, false),
+        ];
+        let hands: Vec<Hand> = split_at_key(&events, 60);
+
+        assert_eq!(
+            hand_events(&events, &hands, Hand::Left),
+            vec![
+                NoteEvent::new(0.0, 40, true),
+                NoteEvent::pedal(75.0, true),
+                NoteEvent::new(25.0, 40, false),
+            ]
+        );
+        assert_eq!(
+            hand_events(&events, &hands, Hand::Right),
+            vec![NoteEvent::new(50.0, 76, true), NoteEvent::pedal(25.0, true)]
+        );
+    }
+
+    #[test]
+    fn pitch_heuristic_follows_hands() {
+        // a left hand line drifting up past middle C stays in the left hand
+        let keys: [u8; 6] = [50, 55, 59, 62, 79, 70];
+        let events: Vec<NoteEvent> = keys
+            .iter()
+            .map(|key| NoteEvent::new(100.0, *key, true))
+            .collect();
+
+        assert_eq!(
+            separate_hands(&events, &HandConfig::default()),
+            vec![
+                Hand::Left,
+                Hand::Left,
+                Hand::Left,
+                Hand::Left,
+                Hand::Right,
+                Hand::Right
+            ]
+        );
+    }
+}
@@ -3,74 +3,395 @@
 // external
 
 // internal
+pub mod abc;
+pub mod analysis;
 mod constants;
 mod encoder;
+pub mod encoding;
+pub mod error;
+pub mod evaluation;
+pub mod hands;
+pub mod lilypond;
 mod midi;
+pub mod musicxml;
+mod pedal;
+pub mod piano_roll;
+pub mod quantize;
+pub mod roll_decoder;
+pub mod score;
+pub mod span;
+pub mod spelling;
+pub mod tokens;
 pub mod types;
 use encoder::{decode, encode};
-use midi::{parse_midi, write_midi};
-use types::{EncodingData, MIDIEncoding, NoteEvent};
+use error::MidiError;
+use evaluation::{evaluate, Evaluation, MatchConfig};
+use midi::{parse_midi, write_midi, write_midi_with, write_quantized_midi};
+use pedal::apply_sustain;
+use piano_roll::PianoRoll;
+use quantize::{QuantizedNote, QuantizedPedal};
+use roll_decoder::{decode_roll, DecoderConfig};
+use span::{events_to_spans, NoteSpan};
+use tokens::{events_to_tokens, tokens_to_events, Token};
+use types::{
+    EncodingData, ExportOptions, MIDIEncoding, NoteEvent, OutOfRangePolicy, DEFAULT_QUANTIZE_MS,
+};
 
-pub fn generate_midi_encoding(path: &str) -> MIDIEncoding {
-    let events: Vec<NoteEvent> = parse_midi(path);
-    let data: EncodingData = EncodingData::new(events);
+// note and pedal events as they are in the file, for tools working on the events directly
+pub fn read_events(path: &str) -> Result<Vec<NoteEvent>, MidiError> {
+    parse_midi(path)
+}
+
+pub fn write_events(events: &[NoteEvent], file_path: &str) -> Result<(), MidiError> {
+    write_midi(events, file_path)
+}
+
+pub fn generate_midi_encoding(path: &str) -> Result<MIDIEncoding, MidiError> {
+    let events: Vec<NoteEvent> = parse_midi(path)?;
+    let mut data: EncodingData = EncodingData::new(events);
+    data.set_quantization(DEFAULT_QUANTIZE_MS);
+    encode(data)
+}
+
+pub fn generate_encoding_with_policy(
+    path: &str,
+    policy: OutOfRangePolicy,
+) -> Result<MIDIEncoding, MidiError> {
+    let events: Vec<NoteEvent> = parse_midi(path)?;
+    let mut data: EncodingData = EncodingData::new(events);
+    data.set_quantization(DEFAULT_QUANTIZE_MS);
+    data.set_key_policy(policy);
+    encode(data)
+}
+
+pub fn generate_sustained_encoding(path: &str) -> Result<MIDIEncoding, MidiError> {
+    let events: Vec<NoteEvent> = parse_midi(path)?;
+    let mut data: EncodingData = EncodingData::new(events);
+    data.set_sustain(true);
+    data.set_quantization(DEFAULT_QUANTIZE_MS);
     encode(data)
 }
 
-pub fn get_sample_encoding(path: &str, len_sec: f32) -> MIDIEncoding {
+pub fn get_sample_encoding(path: &str, len_sec: f32) -> Result<MIDIEncoding, MidiError> {
     let limit_ms: usize = (len_sec * 1000.0).floor() as usize;
-    let events: Vec<NoteEvent> = parse_midi(path);
-    let data: EncodingData = EncodingData::with_limit(events, limit_ms);
+    let events: Vec<NoteEvent> = parse_midi(path)?;
+    let mut data: EncodingData = EncodingData::with_limit(events, limit_ms);
+    data.set_quantization(DEFAULT_QUANTIZE_MS);
     encode(data)
 }
 
-pub fn decode_to_midi(midi: MIDIEncoding, file_path: &str) {
-    let events: Vec<NoteEvent> = decode(midi);
-    write_midi(&events, file_path);
+// audio keeps ringing while the pedal is down, so frames follow the sustained releases
+pub fn generate_piano_roll(
+    path: &str,
+    frame_ms: f64,
+    num_frames: usize,
+) -> Result<PianoRoll, MidiError> {
+    generate_piano_roll_window(path, 0.0, frame_ms, num_frames)
+}
+
+// frames starting offset_ms into the file, to line up with an audio window
+pub fn generate_piano_roll_window(
+    path: &str,
+    offset_ms: f64,
+    frame_ms: f64,
+    num_frames: usize,
+) -> Result<PianoRoll, MidiError> {
+    let events: Vec<NoteEvent> = apply_sustain(parse_midi(path)?);
+    let spans: Vec<NoteSpan> = events_to_spans(&events)
+        .iter()
+        .map(|span| {
+            NoteSpan::new(
+                span.get_key(),
+                span.get_onset_ms() - offset_ms,
+                span.get_offset_ms() - offset_ms,
+                span.get_velocity(),
+            )
+        })
+        .collect();
+    Ok(PianoRoll::from_spans(&spans, frame_ms, num_frames))
+}
+
+pub fn get_window_frame_encoding(
+    path: &str,
+    offset_ms: f64,
+    frame_ms: f64,
+    num_frames: usize,
+) -> Result<MIDIEncoding, MidiError> {
+    Ok(generate_piano_roll_window(path, offset_ms, frame_ms, num_frames)?.to_encoding())
+}
+
+// when the last note of the file ends, with the pedal held notes running on
+pub fn get_duration_ms(path: &str) -> Result<f64, MidiError> {
+    let events: Vec<NoteEvent> = apply_sustain(parse_midi(path)?);
+    Ok(events_to_spans(&events)
+        .iter()
+        .map(|span| span.get_offset_ms())
+        .fold(0.0, f64::max))
+}
+
+pub fn get_frame_encoding(
+    path: &str,
+    frame_ms: f64,
+    num_frames: usize,
+) -> Result<MIDIEncoding, MidiError> {
+    Ok(generate_piano_roll(path, frame_ms, num_frames)?.to_encoding())
+}
+
+pub fn decode_to_midi(midi: MIDIEncoding, file_path: &str) -> Result<(), MidiError> {
+    let events: Vec<NoteEvent> = decode(midi).into_events();
+    write_midi(&events, file_path)
+}
+
+pub fn export_midi(
+    midi: MIDIEncoding,
+    file_path: &str,
+    options: &ExportOptions,
+) -> Result<(), MidiError> {
+    let events: Vec<NoteEvent> = decode(midi).into_events();
+    write_midi_with(&events, file_path, options)
+}
+
+pub fn export_quantized_midi(
+    notes: &[QuantizedNote],
+    pedal: &[QuantizedPedal],
+    file_path: &str,
+    options: &ExportOptions,
+) -> Result<(), MidiError> {
+    write_quantized_midi(notes, pedal, file_path, options)
+}
+
+pub fn decode_roll_to_midi(
+    roll: &PianoRoll,
+    config: &DecoderConfig,
+    file_path: &str,
+) -> Result<(), MidiError> {
+    let events: Vec<NoteEvent> = decode_roll(roll, config);
+    write_midi(&events, file_path)
+}
+
+pub fn generate_tokens(path: &str) -> Result<Vec<Token>, MidiError> {
+    let events: Vec<NoteEvent> = parse_midi(path)?;
+    Ok(events_to_tokens(&events))
+}
+
+pub fn decode_tokens_to_midi(tokens: &[Token], file_path: &str) -> Result<(), MidiError> {
+    let events: Vec<NoteEvent> = tokens_to_events(tokens);
+    write_midi(&events, file_path)
+}
+
+pub fn evaluate_midi(
+    reference_path: &str,
+    estimate_path: &str,
+    config: &MatchConfig,
+) -> Result<Evaluation, MidiError> {
+    let reference: Vec<NoteEvent> = parse_midi(reference_path)?;
+    let estimate: Vec<NoteEvent> = parse_midi(estimate_path)?;
+    Ok(evaluate(&reference, &estimate, config))
+}
+
+// scores a model's decoded output against the file it should have transcribed
+pub fn evaluate_encoding(
+    reference_path: &str,
+    midi: MIDIEncoding,
+    config: &MatchConfig,
+) -> Result<Evaluation, MidiError> {
+    let reference: Vec<NoteEvent> = parse_midi(reference_path)?;
+    Ok(evaluate(&reference, decode(midi).get_events(), config))
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::span::encoding_to_spans;
 
     #[test]
     fn simple_midi_test() {
-        let midi: MIDIEncoding = generate_midi_encoding("./tests/Timing_Test.mid");
+        let midi: MIDIEncoding =
+            generate_midi_encoding("./tests/Timing_Test.mid").expect("Failed to encode MIDI file");
 
         println! {"{:?}", midi};
     }
 
     #[test]
     fn complex_midi_test() {
-        let midi: MIDIEncoding = generate_midi_encoding("./tests/Double_Note_Test.mid");
+        let midi: MIDIEncoding = generate_midi_encoding("./tests/Double_Note_Test.mid")
+            .expect("Failed to encode MIDI file");
 
         println!("{:?}", midi);
     }
 
     #[test]
     fn decode_test() {
-        let midi: MIDIEncoding = generate_midi_encoding("./tests/Double_Note_Test.mid");
-        decode_to_midi(midi, "./tests/output/MGROL.mid");
+        let midi: MIDIEncoding = generate_midi_encoding("./tests/Double_Note_Test.mid")
+            .expect("Failed to encode MIDI file");
+        decode_to_midi(midi, "./tests/output/MGROL.mid").expect("Failed to write MIDI file");
     }
 
     #[test]
     fn data_decode_test() {
-        let midi: MIDIEncoding = generate_midi_encoding("./tests/Data_Test.midi");
-        decode_to_midi(midi, "./tests/output/Data_Rewrite.mid");
+        let midi: MIDIEncoding =
+            generate_midi_encoding("./tests/Data_Test.midi").expect("Failed to encode MIDI file");
+        decode_to_midi(midi, "./tests/output/Data_Rewrite.mid").expect("Failed to write MIDI file");
+    }
+
+    #[test]
+    fn sustained_data_test() {
+        let midi: MIDIEncoding = generate_sustained_encoding("./tests/Data_Test.midi")
+            .expect("Failed to encode MIDI file");
+        let plain: MIDIEncoding =
+            generate_midi_encoding("./tests/Data_Test.midi").expect("Failed to encode MIDI file");
+
+        let by_key = |midi: MIDIEncoding| {
+            let mut spans: Vec<NoteSpan> = encoding_to_spans(midi);
+            spans.sort_by(|a, b| {
+                a.get_key()
+                    .cmp(&b.get_key())
+                    .then(a.get_onset_ms().total_cmp(&b.get_onset_ms()))
+            });
+            spans
+        };
+        let sustained: Vec<NoteSpan> = by_key(midi);
+        let plain: Vec<NoteSpan> = by_key(plain);
+
+        /*
+         the pedal only ever holds notes longer, it never adds or moves one.
+         deltas are rounded one event at a time, so the extra pedal events can shift a time by a step.
+        */
+        let step: f64 = DEFAULT_QUANTIZE_MS as f64 + 1e-3;
+        assert_eq!(sustained.len(), plain.len());
+        for (held, played) in sustained.iter().zip(plain.iter()) {
+            assert_eq!(held.get_key(), played.get_key());
+            assert!((held.get_onset_ms() - played.get_onset_ms()).abs() <= step);
+            assert!(held.get_duration_ms() >= played.get_duration_ms() - 2.0 * step);
+        }
+
+        let longer: usize = sustained
+            .iter()
+            .zip(plain.iter())
+            .filter(|(held, played)| held.get_duration_ms() > played.get_duration_ms() + 2.0 * step)
+            .count();
+        assert!(
+            longer > plain.len() / 10,
+            "only {longer} notes were sustained"
+        );
+    }
+
+    #[test]
+    fn frame_encoding_test() {
+        let midi: MIDIEncoding = get_frame_encoding("./tests/Data_Test.midi", 11.6, 500)
+            .expect("Failed to encode MIDI file");
+        assert_eq!(midi.len(), 500);
+    }
+
+    #[test]
+    fn window_encoding_test() {
+        let roll: PianoRoll = generate_piano_roll("./tests/Data_Test.midi", 10.0, 600)
+            .expect("Failed to read MIDI file");
+        let window: PianoRoll =
+            generate_piano_roll_window("./tests/Data_Test.midi", 2000.0, 10.0, 400)
+                .expect("Failed to read MIDI file");
+        assert_eq!(&roll.get_frames()[200..], &window.get_frames()[..400]);
+
+        let duration: f64 =
+            get_duration_ms("./tests/Data_Test.midi").expect("Failed to read MIDI file");
+        assert!(duration > 6000.0);
+    }
+
+    #[test]
+    fn roll_decode_test() {
+        let roll: PianoRoll = generate_piano_roll("./tests/Data_Test.midi", 11.6, 500)
+            .expect("Failed to encode MIDI file");
+        decode_roll_to_midi(
+            &roll,
+            &DecoderConfig::default(),
+            "./tests/output/Data_roll.mid",
+        )
+        .expect("Failed to write MIDI file");
+    }
+
+    #[test]
+    fn token_decode_test() {
+        let tokens: Vec<Token> =
+            generate_tokens("./tests/Data_Test.midi").expect("Failed to encode MIDI file");
+        println!("Number of Tokens: {}", tokens.len());
+        decode_tokens_to_midi(&tokens, "./tests/output/Data_tokens.mid")
+            .expect("Failed to write MIDI file");
+    }
+
+    #[test]
+    fn encoding_evaluation_test() {
+        // quantizing to the encoder's grid should keep onsets well inside the tolerance
+        let midi: MIDIEncoding =
+            generate_midi_encoding("./tests/Data_Test.midi").expect("Failed to encode MIDI file");
+        let evaluation: Evaluation =
+            evaluate_encoding("./tests/Data_Test.midi", midi, &MatchConfig::default())
+                .expect("Failed to evaluate encoding");
+        println!("{}", evaluation.print());
+        assert!(evaluation.get_onsets().get_f1() > 0.99);
+    }
+
+    #[test]
+    fn export_hands_test() {
+        let midi: MIDIEncoding =
+            generate_midi_encoding("./tests/Data_Test.midi").expect("Failed to encode MIDI file");
+        let mut options: ExportOptions = ExportOptions::multi_track();
+        options.set_title("Data Test");
+        options.set_hand_split(types::HandSplit::Heuristic);
+        export_midi(midi, "./tests/output/Data_hands.mid", &options)
+            .expect("Failed to write MIDI file");
+    }
+
+    #[test]
+    fn quantized_export_test() {
+        use quantize::{quantize, quantize_pedal, BeatGrid, QuantizeConfig};
+
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let grid: BeatGrid = BeatGrid::constant(120.0, 0.0);
+        let config: QuantizeConfig = QuantizeConfig::default();
+        let notes: Vec<QuantizedNote> = quantize(&events, &grid, &config);
+        let pedal: Vec<QuantizedPedal> = quantize_pedal(&events, &grid, &config);
+        export_quantized_midi(
+            &notes,
+            &pedal,
+            "./tests/output/Data_quantized.mid",
+            &ExportOptions::new(),
+        )
+        .expect("Failed to write MIDI file");
+
+        // every event lands on a twelfth of a beat at 120 bpm
+        let step_ms: f64 = 500.0 / 12.0;
+        let mut time_ms: f64 = 0.0;
+        let exported: Vec<NoteEvent> =
+            parse_midi("./tests/output/Data_quantized.mid").expect("Failed to parse");
+        let pedal_count: usize = exported.iter().filter(|e| e.is_pedal()).count();
+        assert_eq!(pedal_count, events.iter().filter(|e| e.is_pedal()).count());
+        assert_eq!(pedal_count, pedal.len());
+        for event in exported {
+            time_ms += event.get_time_delta() as f64;
+            let steps: f64 = time_ms / step_ms;
+            assert!(
+                (steps - steps.round()).abs() < 1e-3,
+                "{time_ms} ms is off the grid"
+            );
+        }
     }
 
     #[test]
     fn data_test() {
-        let midi: MIDIEncoding = generate_midi_encoding("./tests/Data_Test.midi");
+        let midi: MIDIEncoding =
+            generate_midi_encoding("./tests/Data_Test.midi").expect("Failed to encode MIDI file");
         println!("Number of Encodings: {}", midi.len());
     }
 
     #[test]
     fn sample_data_test() {
-        let midi: MIDIEncoding = get_sample_encoding("./tests/Data_Test.midi", 5.0);
+        let midi: MIDIEncoding =
+            get_sample_encoding("./tests/Data_Test.midi", 5.0).expect("Failed to encode MIDI file");
         println!("Number of Encodings: {}", midi.len());
         println!("{}", midi.print());
-        decode_to_midi(midi, "./tests/output/Data_truncated.mid");
+        decode_to_midi(midi, "./tests/output/Data_truncated.mid")
+            .expect("Failed to write MIDI file");
     }
 }
@@ -0,0 +1,198 @@
+// builtin
+use std::fmt::Write;
+
+// external
+
+// internal
+use crate::error::MidiError;
+use crate::quantize::QuantizedNote;
+use crate::score::{Element, Measure, NoteValue, Score};
+use crate::spelling::Pitch;
+use crate::types::{ExportOptions, KeySignature};
+
+pub fn write_lilypond(
+    notes: &[QuantizedNote],
+    file_path: &str,
+    options: &ExportOptions,
+) -> Result<(), MidiError> {
+    std::fs::write(file_path, score_to_lilypond(&Score::new(notes, options)))?;
+    Ok(())
+}
+
+// a piano staff with one bar per line, so a changed note shows up as a changed bar
+pub fn score_to_lilypond(score: &Score) -> String {
+    let mut ly: String = String::from("\\version \"2.24.0\"\n\n");
+
+    if let Some(title) = score.title() {
+        let _ = writeln!(
+            ly,
+            "\\header {{ title = \"{}\" }}\n",
+            title.replace('"', "\\\"")
+        );
+    }
+
+    write_staff(&mut ly, score, "upper", "treble", score.get_upper());
+    write_staff(&mut ly, score, "lower", "bass", score.get_lower());
+
+    ly.push_str("\\score {\n");
+    ly.push_str("  \\new PianoStaff <<\n");
+    ly.push_str("    \\new Staff = \"upper\" \\upper\n");
+    ly.push_str("    \\new Staff = \"lower\" \\lower\n");
+    ly.push_str("  >>\n");
+    ly.push_str("  \\layout { }\n");
+    ly.push_str("  \\midi { }\n");
+    ly.push_str("}\n");
+    ly
+}
+
+fn write_staff(ly: &mut String, score: &Score, name: &str, clef: &str, measures: &[Measure]) {
+    let key: KeySignature = score.key_signature();
+    let mode: &str = if key.is_minor() { "minor" } else { "major" };
+
+    let _ = writeln!(ly, "{} = {{", name);
+    let _ = writeln!(ly, "  \\clef {}", clef);
+    let _ = writeln!(ly, "  \\key {} \\{}", pitch_name(&Pitch::tonic(&key)), mode);
+    let _ = writeln!(
+        ly,
+        "  \\time {}/{}",
+        score.time_signature().get_numerator(),
+        score.time_signature().get_denominator()
+    );
+    let _ = writeln!(ly, "  \\tempo 4 = {}", score.tempo_bpm().round());
+
+    for measure in measures {
+        let _ = writeln!(ly, "  {} |", measure_text(score, measure));
+    }
+    ly.push_str("}\n\n");
+}
+
+// runs of triplets share one tuplet bracket
+fn measure_text(score: &Score, measure: &Measure) -> String {
+    let mut items: Vec<String> = Vec::new();
+    let mut in_tuplet: bool = false;
+
+    for element in measure.get_elements() {
+        let triplet: bool = match element {
+            Element::Chord { value, .. } | Element::Rest(value) => value.is_triplet(),
+            Element::MeasureRest => false,
+        };
+        if triplet && !in_tuplet {
+            items.push(String::from("\\tuplet 3/2 {"));
+        } else if !triplet && in_tuplet {
+            items.push(String::from("}"));
+        }
+        in_tuplet = triplet;
+
+        items.push(match element {
+            Element::MeasureRest => {
+                let time = score.time_signature();
+                format!("R1*{}/{}", time.get_numerator(), time.get_denominator())
+            }
+            Element::Rest(value) => format!("r{}", duration(value)),
+            Element::Chord {
+                pitches,
+                value,
+                tie_start,
+                ..
+            } => {
+                let pitches: Vec<String> = pitches.iter().map(absolute_pitch).collect();
+                let tie: &str = if *tie_start { "~" } else { "" };
+
+                if pitches.len() == 1 {
+                    format!("{}{}{}", pitches[0], duration(value), tie)
+                } else {
+                    format!("<{}>{}{}", pitches.join(" "), duration(value), tie)
+                }
+            }
+        });
+    }
+
+    if in_tuplet {
+        items.push(String::from("}"));
+    }
+    items.join(" ")
+}
+
+fn duration(value: &NoteValue) -> String {
+    format!(
+        "{}{}",
+        value.get_base().denominator(),
+        ".".repeat(value.get_dots() as usize)
+    )
+}
+
+fn pitch_name(pitch: &Pitch) -> String {
+    let accidental: &str = match pitch.get_alter() {
+        a if a > 0 => "is",
+        a if a < 0 => "es",
+        _ => "",
+    };
+    format!("{}{}", pitch.get_step().to_ascii_lowercase(), accidental)
+}
+
+// absolute octaves: c is the octave below middle C and c' is middle C
+fn absolute_pitch(pitch: &Pitch) -> String {
+    let marks: i8 = pitch.get_octave() - 3;
+    let octave: String = if marks >= 0 {
+        "'".repeat(marks as usize)
+    } else {
+        ",".repeat(-marks as usize)
+    };
+    format!("{}{}", pitch_name(pitch), octave)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+    use crate::quantize::{quantize, BeatGrid, QuantizeConfig};
+    use crate::span::{spans_to_events, NoteSpan};
+    use crate::types::{HandSplit, NoteEvent};
+
+    #[test]
+    fn piano_staff_text() {
+        let spans: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 0.0, 500.0, 64),
+            NoteSpan::new(64, 0.0, 500.0, 64),
+            NoteSpan::new(70, 500.0, 500.0 + 1000.0 / 3.0, 64),
+            NoteSpan::new(72, 500.0 + 1000.0 / 3.0, 1000.0 + 1000.0 / 3.0, 64),
+            NoteSpan::new(36, 0.0, 2500.0, 64),
+        ];
+        let notes: Vec<QuantizedNote> = quantize(
+            &spans_to_events(&spans),
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        );
+        let mut options: ExportOptions = ExportOptions::multi_track();
+        options.set_key_signature(KeySignature::new(-1, false));
+        options.set_hand_split(HandSplit::SplitPoint(48));
+
+        let ly: String = score_to_lilypond(&Score::new(&notes, &options));
+        assert!(ly.contains("\\key f \\major"));
+        assert!(ly.contains("  <c' e'>4 \\tuplet 3/2 { bes'4 c''8~ c''4 r8 } r4 |\n  R1*4/4 |"));
+        assert!(ly.contains("  c,1~ |\n  c,4 r2. |"));
+    }
+
+    #[test]
+    fn data_test_lilypond() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let notes: Vec<QuantizedNote> = quantize(
+            &events,
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        );
+
+        let mut options: ExportOptions = ExportOptions::multi_track();
+        options.set_hand_split(HandSplit::Heuristic);
+        write_lilypond(&notes, "./tests/output/Data_score.ly", &options)
+            .expect("Failed to write LilyPond file");
+
+        let ly: String = score_to_lilypond(&Score::new(&notes, &options));
+        assert_eq!(
+            ly.matches(" |\n").count(),
+            2 * Score::new(&notes, &options).num_measures()
+        );
+        assert_eq!(ly.matches('{').count(), ly.matches('}').count());
+    }
+}
@@ -6,132 +6,402 @@ use midly::{
 };
 
 // internal
-use crate::types::NoteEvent;
+use crate::error::MidiError;
+use crate::hands::{separate_hands, split_at_key, Hand, HandConfig};
+use crate::quantize::{QuantizedNote, QuantizedPedal};
+use crate::span::event_times;
+use crate::types::{ExportOptions, HandSplit, NoteEvent, SUSTAIN_CONTROLLER, TICKS_PER_BEAT};
 
-pub fn parse_midi(file_path: &str) -> Vec<NoteEvent> {
-    let data = std::fs::read(file_path).expect("Failed to read MIDI file");
-    let smf = Smf::parse(&data).expect("Failed to parse MIDI file");
+pub fn parse_midi(file_path: &str) -> Result<Vec<NoteEvent>, MidiError> {
+    let data = std::fs::read(file_path)?;
+    let smf = Smf::parse(&data)?;
 
-    let tpq = match smf.header.timing {
-        Timing::Metrical(ticks_per_beat) => ticks_per_beat.as_int() as u32,
-        _ => panic!("Timing not in ticks per beat"),
+    let tempo = 500_000; //in microseconds per beat
+    let mut events = Vec::new();
+
+    // SMPTE timecode ticks at a fixed rate, so tempo changes only apply to metrical timing
+    let tpq: Option<f64> = match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => Some(ticks_per_beat.as_int() as f64),
+        Timing::Timecode(_, _) => None,
     };
 
-    let mut tempo = 500_000; //in microseconds per beat
-    let mut events = Vec::new();
+    let mut ms_per_tick: f64 = match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => {
+            metrical_ms_per_tick(tempo as f64, ticks_per_beat.as_int() as f64)
+        }
+        Timing::Timecode(fps, ticks_per_frame) => {
+            1_000.0 / (fps.as_f32() as f64 * ticks_per_frame as f64)
+        }
+    };
 
-    let mut last_time_ms: f32 = 0.0;
-    let mut time_ms: f32 = 0.0;
+    // ms are derived from absolute ticks so rounding never accumulates across events
+    let mut tempo_tick: u64 = 0;
+    let mut tempo_ms: f64 = 0.0;
+    let mut last_time_ms: f64 = 0.0;
+    let mut pedal_down: bool = false;
 
-    for track in smf.tracks {
-        for event in track {
-            time_ms += ticks_to_ms(event.delta.as_int() as u32, tempo as f64, tpq as f64);
+    for (tick, kind) in merge_tracks(&smf.tracks) {
+        let time_ms: f64 = tempo_ms + (tick - tempo_tick) as f64 * ms_per_tick;
 
-            if let TrackEventKind::Meta(midly::MetaMessage::Tempo(new_tempo)) = event.kind {
-                tempo = new_tempo.as_int();
-            }
+        if let (TrackEventKind::Meta(MetaMessage::Tempo(new_tempo)), Some(tpq)) = (kind, tpq) {
+            ms_per_tick = metrical_ms_per_tick(new_tempo.as_int() as f64, tpq);
+            tempo_tick = tick;
+            tempo_ms = time_ms;
+        }
 
-            if let TrackEventKind::Midi {
-                channel: _,
-                message,
-            } = event.kind
-            {
-                match message {
-                    midly::MidiMessage::NoteOn { key, vel } => {
-                        let time_delta = ((time_ms - last_time_ms) / 5.0).round() * 5.0;
-                        last_time_ms = time_ms;
-                        if vel.as_int() == 0 {
-                            events.push(NoteEvent::new(time_delta, key.as_int(), false));
-                        } else {
-                            events.push(NoteEvent::new(time_delta, key.as_int(), true));
-                        }
-                    }
-                    midly::MidiMessage::NoteOff { key, vel: _ } => {
-                        let time_delta = ((time_ms - last_time_ms) / 5.0).round() * 5.0;
+        if let TrackEventKind::Midi {
+            channel: _,
+            message,
+        } = kind
+        {
+            let time_delta: f32 = (time_ms - last_time_ms) as f32;
+
+            match message {
+                midly::MidiMessage::NoteOn { key, vel } => {
+                    last_time_ms = time_ms;
+                    let event: NoteEvent = if vel.as_int() == 0 {
+                        NoteEvent::new(time_delta, key.as_int(), false)
+                    } else {
+                        NoteEvent::with_velocity(time_delta, key.as_int(), true, vel.as_int())
+                    };
+                    events.push(event.at_time(time_ms));
+                }
+                midly::MidiMessage::NoteOff { key, vel: _ } => {
+                    last_time_ms = time_ms;
+                    events.push(NoteEvent::new(time_delta, key.as_int(), false).at_time(time_ms));
+                }
+                midly::MidiMessage::Controller { controller, value } => {
+                    // continuous pedal values only matter when they cross the half-way point
+                    let is_down: bool = value.as_int() >= 64;
+                    if controller.as_int() == SUSTAIN_CONTROLLER && is_down != pedal_down {
                         last_time_ms = time_ms;
-                        events.push(NoteEvent::new(time_delta, key.as_int(), false));
+                        pedal_down = is_down;
+                        events.push(NoteEvent::pedal(time_delta, pedal_down).at_time(time_ms));
                     }
-                    _ => {}
                 }
+                _ => {}
             }
         }
     }
 
-    events
+    Ok(events)
 }
 
-pub fn write_midi(events: &Vec<NoteEvent>, file_path: &str) {
-    let mut track = Track::new();
+// tracks play simultaneously, so events are ordered by absolute tick rather than track by track
+fn merge_tracks<'a>(tracks: &[Track<'a>]) -> Vec<(u64, TrackEventKind<'a>)> {
+    let mut timeline: Vec<(u64, TrackEventKind<'a>)> = Vec::new();
 
-    let tempo: u32 = 500_000; // microseconds
-    let tpq: u16 = 480;
+    for track in tracks {
+        let mut tick: u64 = 0;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            timeline.push((tick, event.kind));
+        }
+    }
 
-    track.push(TrackEvent {
-        delta: u28::new(0),
-        kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(tempo))),
-    });
+    timeline.sort_by_key(|(tick, _)| *tick);
+    timeline
+}
+
+pub fn write_midi(events: &[NoteEvent], file_path: &str) -> Result<(), MidiError> {
+    write_midi_with(events, file_path, &ExportOptions::new())
+}
+
+pub fn write_midi_with(
+    events: &[NoteEvent],
+    file_path: &str,
+    options: &ExportOptions,
+) -> Result<(), MidiError> {
+    let tempo: u32 = options.tempo(); // microseconds
+    let tpq: u16 = TICKS_PER_BEAT as u16;
+
+    // absolute ticks for every event, rounded once so no error builds up
+    let ticks: Vec<u64> = event_times(events)
+        .into_iter()
+        .map(|time_ms| ms_to_ticks(time_ms, tempo as f64, tpq as f64))
+        .collect();
+
+    write_at_ticks(events, &ticks, file_path, options)
+}
+
+// quantized notes and pedal changes go straight onto the tick grid, one beat per quarter note
+pub fn write_quantized_midi(
+    notes: &[QuantizedNote],
+    pedal: &[QuantizedPedal],
+    file_path: &str,
+    options: &ExportOptions,
+) -> Result<(), MidiError> {
+    let ms_per_tick: f64 = metrical_ms_per_tick(options.tempo() as f64, TICKS_PER_BEAT as f64);
+
+    // on a shared tick releases come first, then the pedal, then presses
+    let mut timeline: Vec<(u64, u8, NoteEvent)> = Vec::with_capacity(notes.len() * 2 + pedal.len());
+    for note in notes {
+        let (key, velocity) = (note.get_key(), note.get_velocity());
+        timeline.push((
+            note.get_onset_ticks(),
+            2,
+            NoteEvent::with_velocity(0.0, key, true, velocity),
+        ));
+        timeline.push((note.get_offset_ticks(), 0, NoteEvent::new(0.0, key, false)));
+    }
+    for change in pedal {
+        timeline.push((
+            change.get_ticks(),
+            1,
+            NoteEvent::pedal(0.0, change.is_down()),
+        ));
+    }
+    timeline.sort_by_key(|(tick, order, _)| (*tick, *order));
+
+    let mut last_tick: u64 = 0;
+    let mut ticks: Vec<u64> = Vec::with_capacity(timeline.len());
+    let events: Vec<NoteEvent> = timeline
+        .into_iter()
+        .map(|(tick, _, event)| {
+            let delta_ms: f32 = ((tick - last_tick) as f64 * ms_per_tick) as f32;
+            last_tick = tick;
+            ticks.push(tick);
+            event
+                .with_time_delta(delta_ms)
+                .at_time(tick as f64 * ms_per_tick)
+        })
+        .collect();
+
+    write_at_ticks(&events, &ticks, file_path, options)
+}
+
+fn write_at_ticks(
+    events: &[NoteEvent],
+    ticks: &[u64],
+    file_path: &str,
+    options: &ExportOptions,
+) -> Result<(), MidiError> {
+    let tempo: u32 = options.tempo();
+    let tpq: u16 = TICKS_PER_BEAT as u16;
+
+    let hands: Option<Vec<Hand>> = match options.hand_split() {
+        HandSplit::None => None,
+        HandSplit::SplitPoint(split_key) => Some(split_at_key(events, split_key)),
+        HandSplit::Heuristic => Some(separate_hands(events, &HandConfig::default())),
+    };
+
+    let ms_per_tick: f64 = metrical_ms_per_tick(tempo as f64, tpq as f64);
+    let meta: Vec<(u64, TrackEventKind)> =
+        meta_events(options).into_iter().map(|m| (0, m)).collect();
+    let mut tracks: Vec<Track> = Vec::new();
+
+    if !options.is_multi_track() {
+        let mut timeline: Vec<(u64, TrackEventKind)> = meta;
+        timeline.extend(piano_events(events, ticks, None, 0, options.program())?);
+        tracks.push(to_track(timeline, ms_per_tick)?);
+    } else {
+        tracks.push(to_track(meta, ms_per_tick)?);
+
+        // each hand gets its own channel so program changes and pedals stay per track
+        match hands {
+            None => {
+                let piano = piano_events(events, ticks, None, 0, options.program())?;
+                tracks.push(named_track(b"Piano", piano, ms_per_tick)?);
+            }
+            Some(hands) => {
+                let right = piano_events(
+                    events,
+                    ticks,
+                    Some((&hands, Hand::Right)),
+                    0,
+                    options.program(),
+                )?;
+                let left = piano_events(
+                    events,
+                    ticks,
+                    Some((&hands, Hand::Left)),
+                    1,
+                    options.program(),
+                )?;
+                tracks.push(named_track(b"Right Hand", right, ms_per_tick)?);
+                tracks.push(named_track(b"Left Hand", left, ms_per_tick)?);
+            }
+        }
+    }
+
+    let format: midly::Format = if options.is_multi_track() {
+        midly::Format::Parallel
+    } else {
+        midly::Format::SingleTrack
+    };
+
+    let smf = Smf {
+        header: Header::new(format, midly::Timing::Metrical(u15::new(tpq))),
+        tracks,
+    };
+
+    smf.save(file_path)?;
+
+    Ok(())
+}
+
+fn meta_events(options: &ExportOptions) -> Vec<TrackEventKind<'_>> {
+    let mut meta: Vec<TrackEventKind> = Vec::new();
+
+    if let Some(title) = options.title() {
+        meta.push(TrackEventKind::Meta(MetaMessage::TrackName(
+            title.as_bytes(),
+        )));
+    }
+
+    meta.push(TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+        options.tempo(),
+    ))));
+
+    if let Some(signature) = options.time_signature() {
+        // 24 clocks per metronome click and 8 32nd notes per beat are the usual values
+        let denominator: u8 = signature.get_denominator().trailing_zeros() as u8;
+        meta.push(TrackEventKind::Meta(MetaMessage::TimeSignature(
+            signature.get_numerator(),
+            denominator,
+            24,
+            8,
+        )));
+    }
+
+    if let Some(signature) = options.key_signature() {
+        meta.push(TrackEventKind::Meta(MetaMessage::KeySignature(
+            signature.get_fifths(),
+            signature.is_minor(),
+        )));
+    }
+
+    meta
+}
 
-    for event in events {
-        let tick_delta: u32 = ms_to_ticks(event.get_time_delta(), tempo as f64, tpq as f64);
+// pedal events go to every hand, notes only to the hand they were assigned
+fn piano_events<'a>(
+    events: &[NoteEvent],
+    ticks: &[u64],
+    hand: Option<(&[Hand], Hand)>,
+    channel: u8,
+    program: Option<u8>,
+) -> Result<Vec<(u64, TrackEventKind<'a>)>, MidiError> {
+    let channel: u4 = u4::new(channel);
+    let mut timeline: Vec<(u64, TrackEventKind)> = Vec::new();
 
-        let delta: u28 = u28::from(tick_delta);
-        let kind = if event.get_note_ref().is_note_on() {
+    if let Some(program) = program {
+        let program: u7 = u7::new(program.min(127));
+        timeline.push((
+            0,
             TrackEventKind::Midi {
-                channel: u4::new(0),
-                message: MidiMessage::NoteOn {
-                    key: u7::new(event.get_note_ref().get_key()),
-                    vel: u7::new(64),
-                },
+                channel,
+                message: MidiMessage::ProgramChange { program },
+            },
+        ));
+    }
+
+    for (i, event) in events.iter().enumerate() {
+        if let Some((hands, hand)) = hand {
+            if !event.is_pedal() && hands[i] != hand {
+                continue;
+            }
+        }
+
+        let key: u7 = u7::try_from(event.get_note_ref().get_key())
+            .ok_or(MidiError::KeyOutOfRange(event.get_note_ref().get_key()))?;
+
+        let message = if event.is_pedal() {
+            let value: u8 = if event.get_note_ref().is_note_on() {
+                127
+            } else {
+                0
+            };
+            MidiMessage::Controller {
+                controller: u7::new(SUSTAIN_CONTROLLER),
+                value: u7::new(value),
+            }
+        } else if event.get_note_ref().is_note_on() {
+            MidiMessage::NoteOn {
+                key,
+                // a zero velocity note on would read back as a release
+                vel: u7::new(event.get_note_ref().get_velocity().clamp(1, 127)),
             }
         } else {
-            TrackEventKind::Midi {
-                channel: u4::new(0),
-                message: MidiMessage::NoteOff {
-                    key: u7::new(event.get_note_ref().get_key()),
-                    vel: u7::new(64),
-                },
+            MidiMessage::NoteOff {
+                key,
+                vel: u7::new(64),
             }
         };
 
-        track.push(TrackEvent { delta, kind });
+        timeline.push((ticks[i], TrackEventKind::Midi { channel, message }));
     }
 
-    let smf = Smf {
-        header: Header::new(
-            midly::Format::SingleTrack,
-            midly::Timing::Metrical(u15::new(tpq)),
-        ),
-        tracks: vec![track],
-    };
+    Ok(timeline)
+}
 
-    let mut buffer = Vec::new();
-    smf.write(&mut buffer).expect("Failed to write MIDI data");
+fn named_track<'a>(
+    name: &'a [u8],
+    mut timeline: Vec<(u64, TrackEventKind<'a>)>,
+    ms_per_tick: f64,
+) -> Result<Track<'a>, MidiError> {
+    timeline.insert(0, (0, TrackEventKind::Meta(MetaMessage::TrackName(name))));
+    to_track(timeline, ms_per_tick)
+}
+
+fn to_track(timeline: Vec<(u64, TrackEventKind)>, ms_per_tick: f64) -> Result<Track, MidiError> {
+    let mut track: Track = Track::new();
+    let mut last_tick: u64 = 0;
+
+    for (tick, kind) in timeline {
+        let tick_delta: u64 = tick.saturating_sub(last_tick);
+        last_tick = tick;
+
+        let delta: u28 = u32::try_from(tick_delta)
+            .ok()
+            .and_then(u28::try_from)
+            .ok_or(MidiError::TimeOutOfRange(
+                (tick_delta as f64 * ms_per_tick) as f32,
+            ))?;
+        track.push(TrackEvent { delta, kind });
+    }
 
-    std::fs::write(file_path, buffer).expect("Failed to write MIDI file");
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    Ok(track)
 }
 
-fn ticks_to_ms(ticks: u32, tempo: f64, tpq: f64) -> f32 {
-    (ticks as f64 * tempo / tpq / 1_000.0) as f32
+fn metrical_ms_per_tick(tempo: f64, tpq: f64) -> f64 {
+    tempo / tpq / 1_000.0
 }
 
-fn ms_to_ticks(ms: f32, tempo: f64, tpq: f64) -> u32 {
-    (ms as f64 * tpq * 1_000.0 / tempo) as u32
+fn ms_to_ticks(ms: f64, tempo: f64, tpq: f64) -> u64 {
+    (ms * tpq * 1_000.0 / tempo).round().max(0.0) as u64
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{KeySignature, TimeSignature};
 
     #[test]
     fn test_parse_midi() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Timing_Test.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
         println!("{:?}", events);
         assert!(!events.is_empty());
+
+        // every parsed event keeps the absolute time its delta came from
+        let mut last_ms: f64 = 0.0;
+        for event in &events {
+            let time_ms: f64 = event.get_time_ms().expect("Parsed events know their time");
+            assert_eq!(event.get_time_delta(), (time_ms - last_ms) as f32);
+            last_ms = time_ms;
+        }
     }
 
     #[test]
     fn training_data_test() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Data_Test.midi");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
         let events_head: Vec<NoteEvent> = events.iter().take(100).cloned().collect();
         println!("{:#?}", events_head);
         assert!(!events.is_empty());
@@ -139,7 +409,8 @@ mod tests {
 
     #[test]
     fn written_data_test() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/output/Data_test.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/output/Data_test.mid").expect("Failed to parse MIDI file");
         let events_head: Vec<NoteEvent> = events.iter().take(100).cloned().collect();
         println!("{:#?}", events_head);
         assert!(!events.is_empty());
@@ -147,19 +418,166 @@ mod tests {
 
     #[test]
     fn test_write_midi() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Timing_Test.mid");
-        write_midi(&events, "./tests/output/C_only.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
+        write_midi(&events, "./tests/output/C_only.mid").expect("Failed to write MIDI file");
     }
 
     #[test]
     fn test_write_midi_complex() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Double_Note_Test.mid");
-        write_midi(&events, "./tests/output/Double_note.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
+        write_midi(&events, "./tests/output/Double_note.mid").expect("Failed to write MIDI file");
+    }
+
+    #[test]
+    fn test_pedal_round_trip() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(500.0, 60, false),
+            NoteEvent::pedal(500.0, false),
+        ];
+        write_midi(&events, "./tests/output/Pedal_test.mid").expect("Failed to write MIDI file");
+
+        let parsed: Vec<NoteEvent> =
+            parse_midi("./tests/output/Pedal_test.mid").expect("Failed to parse MIDI file");
+        assert_eq!(parsed, events);
+    }
+
+    #[test]
+    fn test_pedal_parse_data() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let pedal: Vec<bool> = events
+            .iter()
+            .filter(|e| e.is_pedal())
+            .map(|e| e.get_note_ref().is_note_on())
+            .collect();
+        assert!(!pedal.is_empty());
+
+        // only changes of the pedal are kept, so downs and ups take turns starting with a down
+        for (i, down) in pedal.iter().enumerate() {
+            assert_eq!(*down, i % 2 == 0);
+        }
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let result = parse_midi("./tests/Missing.mid");
+        assert!(matches!(result, Err(MidiError::Io(_))));
+    }
+
+    #[test]
+    fn test_write_invalid_key() {
+        let events: Vec<NoteEvent> = vec![NoteEvent::new(0.0, 130, true)];
+        let result = write_midi(&events, "./tests/output/Invalid_key.mid");
+        assert!(matches!(result, Err(MidiError::KeyOutOfRange(130))));
+    }
+
+    #[test]
+    fn test_parse_timecode() {
+        // 25 fps with 40 ticks per frame gives exactly one tick per ms
+        let mut track = Track::new();
+        for (delta, on) in [(0, true), (250, false), (750, true), (1000, false)] {
+            let message = if on {
+                MidiMessage::NoteOn {
+                    key: u7::new(60),
+                    vel: u7::new(64),
+                }
+            } else {
+                MidiMessage::NoteOff {
+                    key: u7::new(60),
+                    vel: u7::new(64),
+                }
+            };
+            track.push(TrackEvent {
+                delta: u28::new(delta),
+                kind: TrackEventKind::Midi {
+                    channel: u4::new(0),
+                    message,
+                },
+            });
+        }
+
+        let smf = Smf {
+            header: Header::new(
+                midly::Format::SingleTrack,
+                Timing::Timecode(midly::Fps::Fps25, 40),
+            ),
+            tracks: vec![track],
+        };
+        smf.save("./tests/output/Timecode_test.mid")
+            .expect("Failed to write MIDI file");
+
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/output/Timecode_test.mid").expect("Failed to parse MIDI file");
+        let expected: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(250.0, 60, false),
+            NoteEvent::new(750.0, 60, true),
+            NoteEvent::new(1000.0, 60, false),
+        ];
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn test_multi_track_export() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 48, true),
+            NoteEvent::new(0.0, 72, true),
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(500.0, 48, false),
+            NoteEvent::new(0.0, 72, false),
+            NoteEvent::pedal(0.0, false),
+        ];
+        let mut options: ExportOptions = ExportOptions::multi_track();
+        options.set_title("Hands");
+        options.set_time_signature(TimeSignature::new(3, 4).expect("3/4 is a valid meter"));
+        options.set_key_signature(KeySignature::new(-2, true));
+        options.set_hand_split(HandSplit::SplitPoint(60));
+        write_midi_with(&events, "./tests/output/Hands_test.mid", &options)
+            .expect("Failed to write MIDI file");
+
+        let data = std::fs::read("./tests/output/Hands_test.mid").expect("Failed to read file");
+        let smf = Smf::parse(&data).expect("Failed to parse MIDI file");
+        assert_eq!(smf.header.format, midly::Format::Parallel);
+        assert_eq!(smf.tracks.len(), 3);
+
+        let kinds: Vec<TrackEventKind> = smf.tracks[0].iter().map(|e| e.kind).collect();
+        assert!(
+            kinds.contains(&TrackEventKind::Meta(MetaMessage::TimeSignature(
+                3, 2, 24, 8
+            )))
+        );
+        assert!(kinds.contains(&TrackEventKind::Meta(MetaMessage::KeySignature(-2, true))));
+        assert!(kinds.contains(&TrackEventKind::Meta(MetaMessage::TrackName(b"Hands"))));
+
+        let names: Vec<TrackEventKind> = smf.tracks[1..].iter().map(|t| t[0].kind).collect();
+        assert_eq!(
+            names,
+            vec![
+                TrackEventKind::Meta(MetaMessage::TrackName(b"Right Hand")),
+                TrackEventKind::Meta(MetaMessage::TrackName(b"Left Hand")),
+            ]
+        );
+
+        // tracks merge back by tick, both hands keep the pedal but notes only show up once
+        let parsed: Vec<NoteEvent> =
+            parse_midi("./tests/output/Hands_test.mid").expect("Failed to parse MIDI file");
+        let keys: Vec<(u8, bool)> = parsed
+            .iter()
+            .filter(|e| !e.is_pedal())
+            .map(|e| (e.get_note_ref().get_key(), e.get_note_ref().is_note_on()))
+            .collect();
+        assert_eq!(keys, vec![(72, true), (48, true), (72, false), (48, false)]);
+        assert_eq!(parsed.iter().filter(|e| e.is_pedal()).count(), 2);
     }
 
     #[test]
     fn test_write_midi_data() {
-        let events: Vec<NoteEvent> = parse_midi("./tests/Data_Test.midi");
-        write_midi(&events, "./tests/output/Data_test.mid");
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        write_midi(&events, "./tests/output/Data_test.mid").expect("Failed to write MIDI file");
     }
 }
@@ -0,0 +1,270 @@
+// builtin
+use std::fmt::Write;
+
+// external
+
+// internal
+use crate::error::MidiError;
+use crate::quantize::QuantizedNote;
+use crate::score::{BaseValue, Element, Measure, NoteValue, Score};
+use crate::types::{ExportOptions, KeySignature, TICKS_PER_BEAT};
+
+pub fn write_musicxml(
+    notes: &[QuantizedNote],
+    file_path: &str,
+    options: &ExportOptions,
+) -> Result<(), MidiError> {
+    std::fs::write(file_path, score_to_musicxml(&Score::new(notes, options)))?;
+    Ok(())
+}
+
+// a partwise score with a single piano part on two staves
+pub fn score_to_musicxml(score: &Score) -> String {
+    let mut xml: String = String::new();
+
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    xml.push_str("<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \"http://www.musicxml.org/dtds/partwise.dtd\">\n");
+    xml.push_str("<score-partwise version=\"4.0\">\n");
+
+    if let Some(title) = score.title() {
+        let _ = writeln!(
+            xml,
+            "  <work><work-title>{}</work-title></work>",
+            escape(title)
+        );
+    }
+    xml.push_str("  <part-list>\n");
+    xml.push_str("    <score-part id=\"P1\"><part-name>Piano</part-name></score-part>\n");
+    xml.push_str("  </part-list>\n");
+    xml.push_str("  <part id=\"P1\">\n");
+
+    for i in 0..score.num_measures() {
+        let _ = writeln!(xml, "    <measure number=\"{}\">", i + 1);
+        if i == 0 {
+            write_attributes(&mut xml, score);
+        }
+
+        write_staff(&mut xml, score, &score.get_upper()[i], 1);
+        let _ = writeln!(
+            xml,
+            "      <backup><duration>{}</duration></backup>",
+            score.measure_ticks()
+        );
+        write_staff(&mut xml, score, &score.get_lower()[i], 2);
+
+        if i + 1 == score.num_measures() {
+            xml.push_str(
+                "      <barline location=\"right\"><bar-style>light-heavy</bar-style></barline>\n",
+            );
+        }
+        xml.push_str("    </measure>\n");
+    }
+
+    xml.push_str("  </part>\n");
+    xml.push_str("</score-partwise>\n");
+    xml
+}
+
+fn write_attributes(xml: &mut String, score: &Score) {
+    let key: KeySignature = score.key_signature();
+    let mode: &str = if key.is_minor() { "minor" } else { "major" };
+
+    xml.push_str("      <attributes>\n");
+    let _ = writeln!(xml, "        <divisions>{}</divisions>", TICKS_PER_BEAT);
+    let _ = writeln!(
+        xml,
+        "        <key><fifths>{}</fifths><mode>{}</mode></key>",
+        key.get_fifths(),
+        mode
+    );
+    let _ = writeln!(
+        xml,
+        "        <time><beats>{}</beats><beat-type>{}</beat-type></time>",
+        score.time_signature().get_numerator(),
+        score.time_signature().get_denominator()
+    );
+    xml.push_str("        <staves>2</staves>\n");
+    xml.push_str("        <clef number=\"1\"><sign>G</sign><line>2</line></clef>\n");
+    xml.push_str("        <clef number=\"2\"><sign>F</sign><line>4</line></clef>\n");
+    xml.push_str("      </attributes>\n");
+
+    let bpm: f64 = (score.tempo_bpm() * 100.0).round() / 100.0;
+    xml.push_str("      <direction placement=\"above\">\n");
+    let _ = writeln!(
+        xml,
+        "        <direction-type><metronome><beat-unit>quarter</beat-unit><per-minute>{}</per-minute></metronome></direction-type>",
+        bpm
+    );
+    xml.push_str("        <staff>1</staff>\n");
+    let _ = writeln!(xml, "        <sound tempo=\"{}\"/>", bpm);
+    xml.push_str("      </direction>\n");
+}
+
+// the upper staff is voice 1 and the lower staff voice 5, as notation programs number them
+fn write_staff(xml: &mut String, score: &Score, measure: &Measure, staff: u8) {
+    let voice: u8 = if staff == 1 { 1 } else { 5 };
+
+    for element in measure.get_elements() {
+        match element {
+            Element::MeasureRest => {
+                let _ = writeln!(
+                    xml,
+                    "      <note><rest measure=\"yes\"/><duration>{}</duration><voice>{}</voice><staff>{}</staff></note>",
+                    score.measure_ticks(),
+                    voice,
+                    staff
+                );
+            }
+            Element::Rest(value) => {
+                let _ = writeln!(
+                    xml,
+                    "      <note><rest/><duration>{}</duration><voice>{}</voice>{}<staff>{}</staff></note>",
+                    value.ticks(),
+                    voice,
+                    value_tags(value),
+                    staff
+                );
+            }
+            Element::Chord {
+                pitches,
+                value,
+                tie_start,
+                tie_stop,
+                ..
+            } => {
+                for (i, pitch) in pitches.iter().enumerate() {
+                    let mut note: String = String::from("      <note>");
+                    if i > 0 {
+                        note.push_str("<chord/>");
+                    }
+                    note.push_str("<pitch>");
+                    let _ = write!(note, "<step>{}</step>", pitch.get_step());
+                    if pitch.get_alter() != 0 {
+                        let _ = write!(note, "<alter>{}</alter>", pitch.get_alter());
+                    }
+                    let _ = write!(note, "<octave>{}</octave></pitch>", pitch.get_octave());
+                    let _ = write!(note, "<duration>{}</duration>", value.ticks());
+
+                    let mut tied: String = String::new();
+                    if *tie_stop {
+                        note.push_str("<tie type=\"stop\"/>");
+                        tied.push_str("<tied type=\"stop\"/>");
+                    }
+                    if *tie_start {
+                        note.push_str("<tie type=\"start\"/>");
+                        tied.push_str("<tied type=\"start\"/>");
+                    }
+
+                    let _ = write!(
+                        note,
+                        "<voice>{}</voice>{}<staff>{}</staff>",
+                        voice,
+                        value_tags(value),
+                        staff
+                    );
+                    if !tied.is_empty() {
+                        let _ = write!(note, "<notations>{}</notations>", tied);
+                    }
+                    note.push_str("</note>");
+
+                    xml.push_str(&note);
+                    xml.push('\n');
+                }
+            }
+        }
+    }
+}
+
+// type, dots and the triplet ratio, in the order MusicXML expects them
+fn value_tags(value: &NoteValue) -> String {
+    let name: &str = match value.get_base() {
+        BaseValue::Whole => "whole",
+        BaseValue::Half => "half",
+        BaseValue::Quarter => "quarter",
+        BaseValue::Eighth => "eighth",
+        BaseValue::Sixteenth => "16th",
+        BaseValue::ThirtySecond => "32nd",
+    };
+
+    let mut tags: String = format!("<type>{}</type>", name);
+    for _ in 0..value.get_dots() {
+        tags.push_str("<dot/>");
+    }
+    if value.is_triplet() {
+        tags.push_str("<time-modification><actual-notes>3</actual-notes><normal-notes>2</normal-notes></time-modification>");
+    }
+    tags
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+    use crate::quantize::{quantize, BeatGrid, QuantizeConfig};
+    use crate::span::{spans_to_events, NoteSpan};
+    use crate::types::{HandSplit, NoteEvent};
+
+    #[test]
+    fn grand_staff_measures() {
+        let spans: Vec<NoteSpan> = vec![
+            NoteSpan::new(48, 0.0, 2000.0, 64),
+            NoteSpan::new(72, 0.0, 500.0, 64),
+            NoteSpan::new(76, 0.0, 500.0, 64),
+            NoteSpan::new(79, 1500.0, 2500.0, 64),
+        ];
+        let notes: Vec<QuantizedNote> = quantize(
+            &spans_to_events(&spans),
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        );
+        let mut options: ExportOptions = ExportOptions::multi_track();
+        options.set_title("Tom & Jerry");
+        options.set_hand_split(HandSplit::SplitPoint(60));
+
+        let xml: String = score_to_musicxml(&Score::new(&notes, &options));
+        assert_eq!(xml.matches("<measure ").count(), 2);
+        assert!(xml.contains("<work-title>Tom &amp; Jerry</work-title>"));
+        assert_eq!(xml.matches("<chord/>").count(), 1);
+        assert_eq!(xml.matches("<tie type=\"start\"/>").count(), 1);
+        assert_eq!(xml.matches("<tie type=\"stop\"/>").count(), 1);
+        assert!(xml.contains("<step>C</step><octave>3</octave></pitch><duration>1920</duration>"));
+        assert!(xml.contains("<rest measure=\"yes\"/>"));
+    }
+
+    #[test]
+    fn data_test_score() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let notes: Vec<QuantizedNote> = quantize(
+            &events,
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        );
+
+        let mut options: ExportOptions = ExportOptions::multi_track();
+        options.set_hand_split(HandSplit::Heuristic);
+        write_musicxml(&notes, "./tests/output/Data_score.musicxml", &options)
+            .expect("Failed to write MusicXML");
+
+        // every measure on each staff has to add up to the time signature
+        let score: Score = Score::new(&notes, &options);
+        for measure in score.get_upper().iter().chain(score.get_lower()) {
+            let ticks: u64 = measure
+                .get_elements()
+                .iter()
+                .map(|e| match e {
+                    Element::Chord { value, .. } | Element::Rest(value) => value.ticks(),
+                    Element::MeasureRest => score.measure_ticks(),
+                })
+                .sum();
+            assert_eq!(ticks, score.measure_ticks());
+        }
+    }
+}
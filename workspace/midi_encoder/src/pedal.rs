@@ -0,0 +1,122 @@
+// builtin
+
+// external
+
+// internal
+use crate::span::event_times;
+use crate::types::NoteEvent;
+
+const KEYS: usize = 128;
+
+pub fn apply_sustain(events: Vec<NoteEvent>) -> Vec<NoteEvent> {
+    let mut sustained: Vec<NoteEvent> = Vec::with_capacity(events.len());
+
+    let mut pedal_down: bool = false;
+    let mut held: [bool; KEYS] = [false; KEYS];
+
+    let times: Vec<f64> = event_times(&events);
+    let mut time: f64 = 0.0;
+    let mut last_time: f64 = 0.0;
+
+    for (event, event_time) in events.into_iter().zip(times) {
+        time = event_time;
+        let delta: f32 = (time - last_time) as f32;
+        let key: u8 = event.get_note_ref().get_key();
+
+        if event.is_pedal() {
+            pedal_down = event.get_note_ref().is_note_on();
+            sustained.push(NoteEvent::pedal(delta, pedal_down).at_time(time));
+            last_time = time;
+
+            if !pedal_down {
+                for (k, is_held) in held.iter_mut().enumerate() {
+                    if *is_held {
+                        sustained.push(NoteEvent::new(0.0, k as u8, false).at_time(time));
+                        *is_held = false;
+                    }
+                }
+            }
+        } else if event.get_note_ref().is_note_on() {
+            // a re-strike cuts off the sustained copy of the same key
+            let mut delta: f32 = delta;
+            if held[key as usize] {
+                sustained.push(NoteEvent::new(delta, key, false).at_time(time));
+                delta = 0.0;
+                held[key as usize] = false;
+            }
+            sustained.push(NoteEvent::from_note(delta, event.get_note()).at_time(time));
+            last_time = time;
+        } else if pedal_down {
+            held[key as usize] = true;
+        } else {
+            sustained.push(NoteEvent::new(delta, key, false).at_time(time));
+            last_time = time;
+        }
+    }
+
+    for (k, is_held) in held.iter().enumerate() {
+        if *is_held {
+            sustained.push(NoteEvent::new((time - last_time) as f32, k as u8, false).at_time(time));
+            last_time = time;
+        }
+    }
+
+    sustained
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_waits_for_pedal() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::pedal(100.0, true),
+            NoteEvent::new(100.0, 60, false),
+            NoteEvent::pedal(300.0, false),
+        ];
+
+        let expected: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::pedal(100.0, true),
+            NoteEvent::pedal(400.0, false),
+            NoteEvent::new(0.0, 60, false),
+        ];
+
+        assert_eq!(apply_sustain(events), expected);
+    }
+
+    #[test]
+    fn restrike_ends_sustained_note() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(100.0, 60, false),
+            NoteEvent::new(100.0, 60, true),
+            NoteEvent::new(100.0, 60, false),
+        ];
+
+        let expected: Vec<NoteEvent> = vec![
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(200.0, 60, false),
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(100.0, 60, false),
+        ];
+
+        assert_eq!(apply_sustain(events), expected);
+    }
+
+    #[test]
+    fn releases_without_pedal_unchanged() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(50.0, 64, true),
+            NoteEvent::new(50.0, 60, false),
+            NoteEvent::new(50.0, 64, false),
+        ];
+
+        assert_eq!(apply_sustain(events.clone()), events);
+    }
+}
@@ -0,0 +1,232 @@
+// builtin
+
+// external
+
+// internal
+use crate::span::{events_to_spans, NoteSpan};
+use crate::types::{Chord, EncodingIndex, MIDIEncoding, Note, NoteEvent, HIGHEST_KEY, LOWEST_KEY};
+
+pub const ROLL_KEYS: usize = 88;
+pub const ROLL_LENGTH: usize = 3 * ROLL_KEYS;
+
+pub struct RollIndex;
+
+impl RollIndex {
+    /*
+     one plane of 88 keys each:
+     [0, 88) onsets, [88, 176) sustains, [176, 264) offsets
+    */
+    pub fn onset_index(key: u8) -> usize {
+        (key - LOWEST_KEY) as usize
+    }
+
+    pub fn sustain_index(key: u8) -> usize {
+        ROLL_KEYS + (key - LOWEST_KEY) as usize
+    }
+
+    pub fn offset_index(key: u8) -> usize {
+        2 * ROLL_KEYS + (key - LOWEST_KEY) as usize
+    }
+
+    pub fn key_of(index: usize) -> u8 {
+        (index % ROLL_KEYS) as u8 + LOWEST_KEY
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PianoRoll {
+    frame_ms: f64,
+    frames: Vec<Vec<f32>>,
+}
+
+impl PianoRoll {
+    pub fn new(frame_ms: f64, frames: Vec<Vec<f32>>) -> PianoRoll {
+        PianoRoll { frame_ms, frames }
+    }
+
+    /*
+     frame i covers [i * frame_ms, (i + 1) * frame_ms). a note sustains in every frame it
+     sounds during, and marks an onset and an offset in the frames its ends fall in.
+     notes past the last frame are cut off and keys outside the piano are left out.
+     notes already sounding at time zero sustain from the first frame without an onset.
+    */
+    pub fn from_spans(spans: &[NoteSpan], frame_ms: f64, num_frames: usize) -> PianoRoll {
+        let mut frames: Vec<Vec<f32>> = vec![vec![0.0; ROLL_LENGTH]; num_frames];
+
+        for span in spans {
+            let key: u8 = span.get_key();
+            if !EncodingIndex::in_range(key) || span.get_offset_ms() <= 0.0 {
+                continue;
+            }
+
+            let onset_frame: usize = (span.get_onset_ms().max(0.0) / frame_ms).floor() as usize;
+            if onset_frame >= num_frames {
+                continue;
+            }
+
+            let offset_frame: usize = (span.get_offset_ms() / frame_ms).floor() as usize;
+            let last_frame: usize = ((span.get_offset_ms() / frame_ms).ceil() as usize)
+                .saturating_sub(1)
+                .max(onset_frame);
+
+            if span.get_onset_ms() >= 0.0 {
+                frames[onset_frame][RollIndex::onset_index(key)] = 1.0;
+            }
+            for frame in frames
+                .iter_mut()
+                .take(last_frame.min(num_frames - 1) + 1)
+                .skip(onset_frame)
+            {
+                frame[RollIndex::sustain_index(key)] = 1.0;
+            }
+            if offset_frame < num_frames {
+                frames[offset_frame][RollIndex::offset_index(key)] = 1.0;
+            }
+        }
+
+        PianoRoll { frame_ms, frames }
+    }
+
+    pub fn from_events(events: &[NoteEvent], frame_ms: f64, num_frames: usize) -> PianoRoll {
+        PianoRoll::from_spans(&events_to_spans(events), frame_ms, num_frames)
+    }
+
+    // output in the chord layout has no offsets, only onsets and sounding keys
+    pub fn from_chord_vectors(vectors: &[Vec<f32>], frame_ms: f64) -> PianoRoll {
+        let frames: Vec<Vec<f32>> = vectors
+            .iter()
+            .map(|v| {
+                let mut frame: Vec<f32> = vec![0.0; ROLL_LENGTH];
+                for key in LOWEST_KEY..=HIGHEST_KEY {
+                    frame[RollIndex::onset_index(key)] = v[EncodingIndex::on_off_index(key)];
+                    frame[RollIndex::sustain_index(key)] = v[EncodingIndex::key_index(key)];
+                }
+                frame
+            })
+            .collect();
+
+        PianoRoll { frame_ms, frames }
+    }
+
+    pub fn get_frames(&self) -> &Vec<Vec<f32>> {
+        &self.frames
+    }
+
+    pub fn frame_ms(&self) -> f64 {
+        self.frame_ms
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn is_onset(&self, frame: usize, key: u8) -> bool {
+        self.frames[frame][RollIndex::onset_index(key)] > 0.0
+    }
+
+    pub fn is_sustained(&self, frame: usize, key: u8) -> bool {
+        self.frames[frame][RollIndex::sustain_index(key)] > 0.0
+    }
+
+    pub fn is_offset(&self, frame: usize, key: u8) -> bool {
+        self.frames[frame][RollIndex::offset_index(key)] > 0.0
+    }
+
+    // one chord per frame holding the sounding keys, with onsets marked as note on
+    pub fn to_encoding(&self) -> MIDIEncoding {
+        let mut chords: Vec<Chord> = Vec::with_capacity(self.frames.len());
+
+        for frame in self.frames.iter() {
+            let notes: Vec<Note> = (ROLL_KEYS..2 * ROLL_KEYS)
+                .filter(|i| frame[*i] > 0.0)
+                .map(|i| {
+                    let key: u8 = RollIndex::key_of(i);
+                    Note::new(key, frame[RollIndex::onset_index(key)] > 0.0)
+                })
+                .collect();
+
+            chords.push(Chord::frame(notes, self.frame_ms as f32));
+        }
+
+        MIDIEncoding::new(chords)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+
+    #[test]
+    fn spans_to_frames() {
+        let spans: Vec<NoteSpan> = vec![
+            NoteSpan::new(60, 5.0, 35.0, 64),
+            NoteSpan::new(64, 20.0, 20.0, 64),
+            NoteSpan::new(67, 90.0, 200.0, 64),
+        ];
+        let roll: PianoRoll = PianoRoll::from_spans(&spans, 10.0, 10);
+        assert_eq!(roll.len(), 10);
+
+        let sustained: Vec<bool> = (0..10).map(|i| roll.is_sustained(i, 60)).collect();
+        assert_eq!(
+            sustained,
+            vec![true, true, true, true, false, false, false, false, false, false]
+        );
+        assert!(roll.is_onset(0, 60) && !roll.is_onset(1, 60));
+        assert!(roll.is_offset(3, 60));
+
+        // zero length notes still get a frame
+        assert!(roll.is_onset(2, 64) && roll.is_sustained(2, 64) && roll.is_offset(2, 64));
+
+        // notes running past the end are cut off without an offset
+        assert!(roll.is_onset(9, 67) && roll.is_sustained(9, 67));
+        assert!(!(0..10).any(|i| roll.is_offset(i, 67)));
+    }
+
+    #[test]
+    fn one_chord_per_frame() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
+        let roll: PianoRoll = PianoRoll::from_events(&events, 11.6, 200);
+        let encoding: MIDIEncoding = roll.to_encoding();
+
+        assert_eq!(encoding.len(), 200);
+
+        let vectors: Vec<Vec<f32>> = encoding
+            .get_encoding()
+            .iter()
+            .map(|chord| chord.get_encoding())
+            .collect();
+        let rebuilt: PianoRoll = PianoRoll::from_chord_vectors(&vectors, 11.6);
+        for (i, frame) in rebuilt.get_frames().iter().enumerate() {
+            for key in LOWEST_KEY..=HIGHEST_KEY {
+                assert_eq!(
+                    frame[RollIndex::onset_index(key)],
+                    roll.get_frames()[i][RollIndex::onset_index(key)]
+                );
+                assert_eq!(
+                    frame[RollIndex::sustain_index(key)],
+                    roll.get_frames()[i][RollIndex::sustain_index(key)]
+                );
+            }
+        }
+
+        for (frame, chord) in roll.get_frames().iter().zip(encoding.get_encoding()) {
+            let vector: Vec<f32> = chord.get_encoding();
+            for key in LOWEST_KEY..LOWEST_KEY + ROLL_KEYS as u8 {
+                assert_eq!(
+                    vector[EncodingIndex::key_index(key)],
+                    frame[RollIndex::sustain_index(key)]
+                );
+                assert_eq!(
+                    vector[EncodingIndex::on_off_index(key)],
+                    frame[RollIndex::onset_index(key)]
+                );
+            }
+        }
+    }
+}
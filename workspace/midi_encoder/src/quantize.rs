@@ -0,0 +1,519 @@
+// builtin
+
+// external
+
+// internal
+use crate::error::MidiError;
+use crate::span::{event_times, events_to_spans, NoteSpan};
+use crate::types::{NoteEvent, TimeSignature, TICKS_PER_BEAT};
+
+// where beats fall in the performance, in ms
+#[derive(Debug, Clone, PartialEq)]
+pub enum BeatGrid {
+    Tempo { bpm: f64, offset_ms: f64 },
+    Beats(Vec<f64>),
+}
+
+impl BeatGrid {
+    pub fn constant(bpm: f64, offset_ms: f64) -> BeatGrid {
+        BeatGrid::Tempo { bpm, offset_ms }
+    }
+
+    // tracked beat times, at least two of them, finite and strictly increasing
+    pub fn from_beats(beats_ms: Vec<f64>) -> Result<BeatGrid, MidiError> {
+        if beats_ms.len() < 2 {
+            return Err(MidiError::TooFewBeats(beats_ms.len()));
+        }
+        for (i, beat) in beats_ms.iter().enumerate() {
+            if !beat.is_finite() || (i > 0 && *beat <= beats_ms[i - 1]) {
+                return Err(MidiError::InvalidBeat(i, *beat));
+            }
+        }
+
+        Ok(BeatGrid::Beats(beats_ms))
+    }
+
+    // fractional beats since the first beat, the grid carries on at its edge tempo outside
+    pub fn beat_at(&self, time_ms: f64) -> f64 {
+        match self {
+            BeatGrid::Tempo { bpm, offset_ms } => (time_ms - offset_ms) * bpm / 60_000.0,
+            BeatGrid::Beats(beats) => {
+                if beats.len() < 2 {
+                    return 0.0;
+                }
+
+                let next: usize = beats
+                    .partition_point(|beat| *beat <= time_ms)
+                    .clamp(1, beats.len() - 1);
+                let (start, end) = (beats[next - 1], beats[next]);
+                (next - 1) as f64 + (time_ms - start) / (end - start)
+            }
+        }
+    }
+
+    // the other way round, ms at a fractional beat
+    pub fn time_at(&self, beat: f64) -> f64 {
+        match self {
+            BeatGrid::Tempo { bpm, offset_ms } => offset_ms + beat * 60_000.0 / bpm,
+            BeatGrid::Beats(beats) => {
+                if beats.len() < 2 {
+                    return beats.first().copied().unwrap_or(0.0);
+                }
+
+                let start: usize = (beat.floor().max(0.0) as usize).min(beats.len() - 2);
+                let (from, to) = (beats[start], beats[start + 1]);
+                from + (beat - start as f64) * (to - from)
+            }
+        }
+    }
+}
+
+// straight and triplet splits of a beat down to 32nds, every one of them can be written in a score
+pub(crate) const NOTATABLE_DIVISIONS: [u32; 7] = [1, 2, 3, 4, 6, 8, 12];
+
+pub struct QuantizeConfig {
+    divisions: Vec<u32>,
+    swing_tolerance: f64,
+    time_signature: TimeSignature,
+}
+
+impl QuantizeConfig {
+    /*
+     each beat is snapped to whichever of the divisions fits its notes best, so
+     [2, 3, 4] allows eighths, eighth triplets and sixteenths.
+     divisions that can't be notated, like quintuplets, are left out.
+    */
+    pub fn new(divisions: &[u32]) -> QuantizeConfig {
+        let mut divisions: Vec<u32> = divisions
+            .iter()
+            .copied()
+            .filter(|d| NOTATABLE_DIVISIONS.contains(d))
+            .collect();
+        divisions.sort();
+        divisions.dedup();
+        if divisions.is_empty() {
+            divisions.push(1);
+        }
+
+        QuantizeConfig {
+            divisions,
+            swing_tolerance: 0.0,
+            time_signature: TimeSignature::default(),
+        }
+    }
+
+    // off-beats up to this many beats late still count as straight eighths
+    pub fn set_swing_tolerance(&mut self, swing_tolerance: f64) {
+        self.swing_tolerance = swing_tolerance;
+    }
+
+    // positions are counted in the signature's measures and beats
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    pub fn divisions(&self) -> &Vec<u32> {
+        &self.divisions
+    }
+
+    pub fn swing_tolerance(&self) -> f64 {
+        self.swing_tolerance
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+}
+
+impl Default for QuantizeConfig {
+    fn default() -> QuantizeConfig {
+        QuantizeConfig::new(&[1, 2, 3, 4, 6])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MusicalPosition {
+    measure: u32,
+    beat: u32,
+    tick: u32,
+}
+
+impl MusicalPosition {
+    pub fn from_ticks(ticks: u64, time_signature: &TimeSignature) -> MusicalPosition {
+        let measure_ticks: u64 = time_signature.measure_ticks();
+        let beat_ticks: u64 = time_signature.beat_ticks();
+        let into_measure: u64 = ticks % measure_ticks;
+
+        MusicalPosition {
+            measure: (ticks / measure_ticks) as u32,
+            beat: (into_measure / beat_ticks) as u32,
+            tick: (into_measure % beat_ticks) as u32,
+        }
+    }
+
+    // counted from 0
+    pub fn get_measure(&self) -> u32 {
+        self.measure
+    }
+
+    pub fn get_beat(&self) -> u32 {
+        self.beat
+    }
+
+    // ticks into the beat, out of the time signature's beat_ticks
+    pub fn get_tick(&self) -> u32 {
+        self.tick
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuantizedNote {
+    key: u8,
+    velocity: u8,
+    onset_ticks: u64,
+    duration_ticks: u64,
+    position: MusicalPosition,
+}
+
+impl QuantizedNote {
+    pub fn get_key(&self) -> u8 {
+        self.key
+    }
+
+    pub fn get_velocity(&self) -> u8 {
+        self.velocity
+    }
+
+    pub fn get_onset_ticks(&self) -> u64 {
+        self.onset_ticks
+    }
+
+    pub fn get_duration_ticks(&self) -> u64 {
+        self.duration_ticks
+    }
+
+    pub fn get_offset_ticks(&self) -> u64 {
+        self.onset_ticks + self.duration_ticks
+    }
+
+    pub fn get_position(&self) -> MusicalPosition {
+        self.position
+    }
+
+    // the duration in beats
+    pub fn get_beats(&self) -> f64 {
+        self.duration_ticks as f64 / TICKS_PER_BEAT as f64
+    }
+}
+
+// a sustain pedal change on the tick grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizedPedal {
+    ticks: u64,
+    down: bool,
+}
+
+impl QuantizedPedal {
+    pub fn get_ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn is_down(&self) -> bool {
+        self.down
+    }
+}
+
+// finer divisions have to fit noticeably better to be picked over coarser ones
+const DIVISION_COST: f64 = 0.01;
+
+pub fn quantize(
+    events: &[NoteEvent],
+    grid: &BeatGrid,
+    config: &QuantizeConfig,
+) -> Vec<QuantizedNote> {
+    let spans: Vec<NoteSpan> = events_to_spans(events);
+    let onsets: Vec<f64> = spans
+        .iter()
+        .map(|s| grid.beat_at(s.get_onset_ms()).max(0.0))
+        .collect();
+    let offsets: Vec<f64> = spans
+        .iter()
+        .map(|s| grid.beat_at(s.get_offset_ms()).max(0.0))
+        .collect();
+
+    // every beat picks its own division from the onsets and offsets inside it
+    let last_beat: usize = offsets
+        .iter()
+        .chain(onsets.iter())
+        .fold(0.0, |a: f64, b| a.max(*b)) as usize;
+    let mut points: Vec<Vec<f64>> = vec![Vec::new(); last_beat + 1];
+    for beat in onsets.iter().chain(offsets.iter()) {
+        points[beat.floor() as usize].push(beat.fract());
+    }
+    let divisions: Vec<u32> = points
+        .iter()
+        .map(|fractions| best_division(fractions, config))
+        .collect();
+
+    let mut notes: Vec<QuantizedNote> = spans
+        .iter()
+        .zip(onsets.iter().zip(offsets.iter()))
+        .map(|(span, (onset, offset))| {
+            let onset_division: u32 = divisions[onset.floor() as usize];
+            let onset_ticks: u64 = snap(*onset, onset_division, config.swing_tolerance);
+            let offset_ticks: u64 = snap(
+                *offset,
+                divisions[offset.floor() as usize],
+                config.swing_tolerance,
+            );
+            let step: u64 = TICKS_PER_BEAT / onset_division as u64;
+
+            QuantizedNote {
+                key: span.get_key(),
+                velocity: span.get_velocity(),
+                onset_ticks,
+                duration_ticks: offset_ticks.saturating_sub(onset_ticks).max(step),
+                position: MusicalPosition::from_ticks(onset_ticks, &config.time_signature),
+            }
+        })
+        .collect();
+
+    notes.sort_by_key(|note| (note.onset_ticks, note.key));
+    notes
+}
+
+// pedal changes snap to the finest division, they take no part in picking a beat's grid
+pub fn quantize_pedal(
+    events: &[NoteEvent],
+    grid: &BeatGrid,
+    config: &QuantizeConfig,
+) -> Vec<QuantizedPedal> {
+    let division: u32 = *config.divisions.last().unwrap_or(&1);
+    events
+        .iter()
+        .zip(event_times(events))
+        .filter_map(|(event, time)| {
+            if !event.is_pedal() {
+                return None;
+            }
+
+            let beat: f64 = grid.beat_at(time).max(0.0);
+            Some(QuantizedPedal {
+                ticks: snap(beat, division, config.swing_tolerance),
+                down: event.get_note_ref().is_note_on(),
+            })
+        })
+        .collect()
+}
+
+fn best_division(fractions: &[f64], config: &QuantizeConfig) -> u32 {
+    let mut best: (f64, u32) = (f64::INFINITY, config.divisions[0]);
+
+    for division in config.divisions.iter() {
+        let error: f64 = fractions
+            .iter()
+            .map(|f| {
+                let position: f64 =
+                    unswing(*f, *division, config.swing_tolerance) * *division as f64;
+                (position - position.round()).abs() / *division as f64
+            })
+            .sum();
+        let cost: f64 = error + DIVISION_COST * *division as f64;
+
+        if cost < best.0 {
+            best = (cost, *division);
+        }
+    }
+
+    best.1
+}
+
+// a late off-beat is pulled back to the middle of the beat on grids that have one
+fn unswing(fraction: f64, division: u32, swing_tolerance: f64) -> f64 {
+    if division.is_multiple_of(2) && fraction > 0.5 && fraction <= 0.5 + swing_tolerance {
+        0.5
+    } else {
+        fraction
+    }
+}
+
+fn snap(beat: f64, division: u32, swing_tolerance: f64) -> u64 {
+    let fraction: f64 = unswing(beat.fract(), division, swing_tolerance);
+    let steps: u64 = (fraction * division as f64).round() as u64;
+    beat.floor() as u64 * TICKS_PER_BEAT + steps * TICKS_PER_BEAT / division as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEAT_MS: f64 = 500.0;
+
+    // (key, onset, offset) in beats at 120 bpm, nudged off the grid by a few ms
+    fn played(notes: &[(u8, f64, f64)]) -> Vec<NoteEvent> {
+        let spans: Vec<NoteSpan> = notes
+            .iter()
+            .enumerate()
+            .map(|(i, (key, onset, offset))| {
+                let nudge: f64 = if i % 2 == 0 { 12.0 } else { -9.0 };
+                NoteSpan::new(*key, onset * BEAT_MS + nudge, offset * BEAT_MS - nudge, 64)
+            })
+            .collect();
+        crate::span::spans_to_events(&spans)
+    }
+
+    fn ticks(beats: f64) -> u64 {
+        (beats * TICKS_PER_BEAT as f64).round() as u64
+    }
+
+    #[test]
+    fn eighths_and_triplets() {
+        let events: Vec<NoteEvent> = played(&[
+            (60, 0.0, 0.5),
+            (62, 0.5, 1.0),
+            (64, 1.0, 1.0 + 1.0 / 3.0),
+            (65, 1.0 + 1.0 / 3.0, 1.0 + 2.0 / 3.0),
+            (67, 1.0 + 2.0 / 3.0, 2.0),
+            (72, 2.0, 4.0),
+        ]);
+        let notes: Vec<QuantizedNote> = quantize(
+            &events,
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        );
+
+        let onsets: Vec<u64> = notes.iter().map(|n| n.get_onset_ticks()).collect();
+        assert_eq!(onsets, vec![0, 240, 480, 640, 800, 960]);
+
+        let durations: Vec<u64> = notes.iter().map(|n| n.get_duration_ticks()).collect();
+        assert_eq!(durations, vec![240, 240, 160, 160, 160, 960]);
+    }
+
+    #[test]
+    fn swing_reads_as_eighths() {
+        // off-beats played two thirds through the beat
+        let events: Vec<NoteEvent> = played(&[
+            (60, 0.0, 0.6),
+            (62, 2.0 / 3.0, 1.0),
+            (64, 1.0, 1.6),
+            (65, 5.0 / 3.0, 2.0),
+        ]);
+
+        let mut config: QuantizeConfig = QuantizeConfig::new(&[2, 3]);
+        let straight: Vec<QuantizedNote> =
+            quantize(&events, &BeatGrid::constant(120.0, 0.0), &config);
+        assert_eq!(straight[1].get_onset_ticks(), ticks(2.0 / 3.0));
+
+        config.set_swing_tolerance(0.2);
+        let swung: Vec<QuantizedNote> = quantize(&events, &BeatGrid::constant(120.0, 0.0), &config);
+        let onsets: Vec<u64> = swung.iter().map(|n| n.get_onset_ticks()).collect();
+        assert_eq!(onsets, vec![0, 240, 480, 720]);
+    }
+
+    #[test]
+    fn positions_follow_time_signature() {
+        let events: Vec<NoteEvent> = played(&[(60, 0.0, 1.0), (62, 3.0, 4.0), (64, 4.5, 5.0)]);
+        let mut config: QuantizeConfig = QuantizeConfig::default();
+        config.set_time_signature(TimeSignature::new(3, 4).expect("3/4 is a valid meter"));
+
+        let positions: Vec<(u32, u32, u32)> =
+            quantize(&events, &BeatGrid::constant(120.0, 0.0), &config)
+                .iter()
+                .map(|n| {
+                    let position: MusicalPosition = n.get_position();
+                    (
+                        position.get_measure(),
+                        position.get_beat(),
+                        position.get_tick(),
+                    )
+                })
+                .collect();
+        assert_eq!(positions, vec![(0, 0, 0), (1, 0, 0), (1, 1, 240)]);
+    }
+
+    #[test]
+    fn compound_meter_positions() {
+        let events: Vec<NoteEvent> = played(&[(60, 0.0, 1.0), (62, 2.5, 3.0), (64, 3.5, 4.0)]);
+        let mut config: QuantizeConfig = QuantizeConfig::default();
+        config.set_time_signature(TimeSignature::new(6, 8).expect("6/8 is a valid meter"));
+
+        // measures are three quarters long and beats are eighths
+        let positions: Vec<(u32, u32, u32)> =
+            quantize(&events, &BeatGrid::constant(120.0, 0.0), &config)
+                .iter()
+                .map(|n| {
+                    let position: MusicalPosition = n.get_position();
+                    (
+                        position.get_measure(),
+                        position.get_beat(),
+                        position.get_tick(),
+                    )
+                })
+                .collect();
+        assert_eq!(positions, vec![(0, 0, 0), (0, 5, 0), (1, 1, 0)]);
+    }
+
+    #[test]
+    fn unnotatable_divisions_are_left_out() {
+        let config: QuantizeConfig = QuantizeConfig::new(&[5, 4, 10, 16, 3, 15]);
+        assert_eq!(config.divisions(), &vec![3, 4]);
+        assert_eq!(QuantizeConfig::new(&[5]).divisions(), &vec![1]);
+    }
+
+    #[test]
+    fn beat_grids_need_increasing_beats() {
+        // a repeated last beat would put every later time infinitely many beats in
+        assert!(matches!(
+            BeatGrid::from_beats(vec![0.0, 500.0, 1000.0, 1000.0]),
+            Err(MidiError::InvalidBeat(3, _))
+        ));
+        assert!(matches!(
+            BeatGrid::from_beats(vec![0.0, 1000.0, 500.0]),
+            Err(MidiError::InvalidBeat(2, _))
+        ));
+        assert!(BeatGrid::from_beats(vec![0.0, f64::NAN]).is_err());
+        assert!(matches!(
+            BeatGrid::from_beats(vec![0.0]),
+            Err(MidiError::TooFewBeats(1))
+        ));
+    }
+
+    #[test]
+    fn tracked_beats_bend_the_grid() {
+        // the second beat comes late, the notes on it are still on the beat
+        let grid: BeatGrid =
+            BeatGrid::from_beats(vec![0.0, 600.0, 1000.0, 1400.0]).expect("Beats are increasing");
+        assert_eq!(grid.beat_at(600.0), 1.0);
+        assert_eq!(grid.time_at(1.5), 800.0);
+        assert_eq!(grid.time_at(4.0), 1800.0);
+        assert_eq!(grid.beat_at(800.0), 1.5);
+        assert_eq!(grid.beat_at(1800.0), 4.0);
+
+        let events: Vec<NoteEvent> = crate::span::spans_to_events(&[
+            NoteSpan::new(60, 0.0, 590.0, 64),
+            NoteSpan::new(62, 605.0, 1000.0, 64),
+        ]);
+        let notes: Vec<QuantizedNote> = quantize(&events, &grid, &QuantizeConfig::default());
+        assert_eq!(notes[1].get_onset_ticks(), TICKS_PER_BEAT);
+        assert_eq!(notes[1].get_duration_ticks(), TICKS_PER_BEAT);
+    }
+
+    #[test]
+    fn pedal_snaps_to_finest_division() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::pedal(10.0, true),
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(480.0, 60, false),
+            NoteEvent::pedal(140.0, false),
+        ];
+        let pedal: Vec<QuantizedPedal> = quantize_pedal(
+            &events,
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::new(&[1, 2, 4]),
+        );
+
+        let changes: Vec<(u64, bool)> =
+            pedal.iter().map(|p| (p.get_ticks(), p.is_down())).collect();
+        assert_eq!(changes, vec![(0, true), (ticks(1.25), false)]);
+    }
+}
@@ -0,0 +1,287 @@
+// builtin
+
+// external
+
+// internal
+use crate::piano_roll::{PianoRoll, RollIndex, ROLL_KEYS};
+use crate::span::{spans_to_events, NoteSpan};
+use crate::types::{NoteEvent, DEFAULT_VELOCITY, LOWEST_KEY};
+
+pub struct DecoderConfig {
+    onset_threshold: f32,
+    frame_threshold: f32,
+    min_note_ms: f64,
+    merge_gap_ms: f64,
+}
+
+impl DecoderConfig {
+    // notes start above onset_threshold and keep sounding until they fall below frame_threshold
+    pub fn new(onset_threshold: f32, frame_threshold: f32) -> DecoderConfig {
+        DecoderConfig {
+            onset_threshold,
+            frame_threshold,
+            min_note_ms: 0.0,
+            merge_gap_ms: 0.0,
+        }
+    }
+
+    // shorter notes are dropped after merging
+    pub fn set_min_note_ms(&mut self, min_note_ms: f64) {
+        self.min_note_ms = min_note_ms;
+    }
+
+    // notes that come back within the gap without a new onset are joined to the one before
+    pub fn set_merge_gap_ms(&mut self, merge_gap_ms: f64) {
+        self.merge_gap_ms = merge_gap_ms;
+    }
+
+    pub fn onset_threshold(&self) -> f32 {
+        self.onset_threshold
+    }
+
+    pub fn frame_threshold(&self) -> f32 {
+        self.frame_threshold
+    }
+
+    pub fn min_note_ms(&self) -> f64 {
+        self.min_note_ms
+    }
+
+    pub fn merge_gap_ms(&self) -> f64 {
+        self.merge_gap_ms
+    }
+}
+
+impl Default for DecoderConfig {
+    fn default() -> DecoderConfig {
+        DecoderConfig {
+            onset_threshold: 0.5,
+            frame_threshold: 0.3,
+            min_note_ms: 30.0,
+            merge_gap_ms: 30.0,
+        }
+    }
+}
+
+struct FrameNote {
+    start: usize,
+    end: usize,
+    struck: bool,
+}
+
+pub fn decode_roll(roll: &PianoRoll, config: &DecoderConfig) -> Vec<NoteEvent> {
+    let frame_ms: f64 = roll.frame_ms();
+    let mut spans: Vec<NoteSpan> = Vec::new();
+
+    for k in 0..ROLL_KEYS {
+        let key: u8 = LOWEST_KEY + k as u8;
+
+        for note in merge_gaps(key_notes(roll, key, config), config, frame_ms) {
+            let onset_ms: f64 = note.start as f64 * frame_ms;
+            let offset_ms: f64 = note.end as f64 * frame_ms;
+
+            if offset_ms - onset_ms >= config.min_note_ms {
+                spans.push(NoteSpan::new(key, onset_ms, offset_ms, DEFAULT_VELOCITY));
+            }
+        }
+    }
+
+    spans.sort_by(|a, b| a.get_onset_ms().total_cmp(&b.get_onset_ms()));
+    spans_to_events(&spans)
+}
+
+fn key_notes(roll: &PianoRoll, key: u8, config: &DecoderConfig) -> Vec<FrameNote> {
+    let frames: &Vec<Vec<f32>> = roll.get_frames();
+    let onsets: Vec<f32> = frames
+        .iter()
+        .map(|f| f[RollIndex::onset_index(key)])
+        .collect();
+    let offsets: Vec<f32> = frames
+        .iter()
+        .map(|f| f[RollIndex::offset_index(key)])
+        .collect();
+
+    let mut notes: Vec<FrameNote> = Vec::new();
+    let mut active: Option<FrameNote> = None;
+
+    for (i, frame) in frames.iter().enumerate() {
+        let sustain: f32 = frame[RollIndex::sustain_index(key)];
+        let onset_peak: bool = is_peak(&onsets, i, config.onset_threshold);
+
+        if let Some(mut note) = active.take() {
+            if onset_peak && i > note.start {
+                // re-strike while the key is still sounding
+                note.end = i;
+                notes.push(note);
+                active = Some(FrameNote {
+                    start: i,
+                    end: i,
+                    struck: true,
+                });
+            } else if sustain < config.frame_threshold {
+                note.end = i;
+                notes.push(note);
+            } else if is_peak(&offsets, i, config.onset_threshold) {
+                note.end = i + 1;
+                notes.push(note);
+            } else {
+                active = Some(note);
+            }
+        } else if onset_peak || sustain >= config.onset_threshold {
+            active = Some(FrameNote {
+                start: i,
+                end: i,
+                struck: onset_peak,
+            });
+        }
+    }
+
+    if let Some(mut note) = active {
+        note.end = frames.len();
+        notes.push(note);
+    }
+
+    notes
+}
+
+fn merge_gaps(notes: Vec<FrameNote>, config: &DecoderConfig, frame_ms: f64) -> Vec<FrameNote> {
+    let mut merged: Vec<FrameNote> = Vec::with_capacity(notes.len());
+
+    for note in notes {
+        if let Some(last) = merged.last_mut() {
+            let gap_ms: f64 = (note.start - last.end) as f64 * frame_ms;
+            if !note.struck && gap_ms <= config.merge_gap_ms {
+                last.end = note.end;
+                continue;
+            }
+        }
+        merged.push(note);
+    }
+
+    merged
+}
+
+// the first frame of a rise that clears the threshold, plateaus only count once
+fn is_peak(values: &[f32], i: usize, threshold: f32) -> bool {
+    let value: f32 = values[i];
+    let rising: bool = i == 0 || value > values[i - 1];
+    let falling: bool = i + 1 == values.len() || value >= values[i + 1];
+
+    value >= threshold && rising && falling
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+    use crate::piano_roll::ROLL_LENGTH;
+    use crate::span::events_to_spans;
+
+    fn roll_from(key: u8, onsets: &[f32], sustains: &[f32]) -> PianoRoll {
+        let frames: Vec<Vec<f32>> = onsets
+            .iter()
+            .zip(sustains.iter())
+            .map(|(onset, sustain)| {
+                let mut frame: Vec<f32> = vec![0.0; ROLL_LENGTH];
+                frame[RollIndex::onset_index(key)] = *onset;
+                frame[RollIndex::sustain_index(key)] = *sustain;
+                frame
+            })
+            .collect();
+        PianoRoll::new(10.0, frames)
+    }
+
+    #[test]
+    fn hysteresis_holds_note() {
+        let roll: PianoRoll = roll_from(
+            60,
+            &[0.9, 0.1, 0.0, 0.0, 0.0, 0.0],
+            &[0.9, 0.6, 0.4, 0.35, 0.2, 0.0],
+        );
+        let events: Vec<NoteEvent> = decode_roll(&roll, &DecoderConfig::new(0.5, 0.3));
+
+        assert_eq!(
+            events,
+            vec![
+                NoteEvent::new(0.0, 60, true),
+                NoteEvent::new(40.0, 60, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn flicker_is_merged() {
+        let sustains: [f32; 8] = [0.9, 0.8, 0.2, 0.7, 0.8, 0.0, 0.0, 0.0];
+        let onsets: [f32; 8] = [0.9, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+
+        let mut config: DecoderConfig = DecoderConfig::new(0.5, 0.3);
+        let split: Vec<NoteEvent> = decode_roll(&roll_from(60, &onsets, &sustains), &config);
+        assert_eq!(split.len(), 4);
+
+        config.set_merge_gap_ms(10.0);
+        let merged: Vec<NoteEvent> = decode_roll(&roll_from(60, &onsets, &sustains), &config);
+        assert_eq!(
+            merged,
+            vec![
+                NoteEvent::new(0.0, 60, true),
+                NoteEvent::new(50.0, 60, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn restrike_is_kept() {
+        let roll: PianoRoll = roll_from(
+            60,
+            &[0.9, 0.2, 0.1, 0.8, 0.1, 0.0],
+            &[0.9, 0.9, 0.9, 0.9, 0.9, 0.0],
+        );
+        let mut config: DecoderConfig = DecoderConfig::new(0.5, 0.3);
+        config.set_merge_gap_ms(50.0);
+
+        let events: Vec<NoteEvent> = decode_roll(&roll, &config);
+        assert_eq!(
+            events,
+            vec![
+                NoteEvent::new(0.0, 60, true),
+                NoteEvent::new(30.0, 60, false),
+                NoteEvent::new(0.0, 60, true),
+                NoteEvent::new(20.0, 60, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn short_notes_are_dropped() {
+        let roll: PianoRoll = roll_from(
+            60,
+            &[0.0, 0.9, 0.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.9, 0.0, 0.0, 0.0, 0.0],
+        );
+        let mut config: DecoderConfig = DecoderConfig::new(0.5, 0.3);
+        config.set_min_note_ms(20.0);
+
+        assert!(decode_roll(&roll, &config).is_empty());
+    }
+
+    #[test]
+    fn decode_ground_truth_roll() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+        let frame_ms: f64 = 10.0;
+        let last_ms: f64 = spans.iter().map(|s| s.get_offset_ms()).fold(0.0, f64::max);
+
+        let roll: PianoRoll =
+            PianoRoll::from_spans(&spans, frame_ms, (last_ms / frame_ms).ceil() as usize);
+        let decoded: Vec<NoteSpan> =
+            events_to_spans(&decode_roll(&roll, &DecoderConfig::default()));
+
+        assert_eq!(decoded.len(), spans.len());
+        for span in spans.iter() {
+            assert!(decoded.iter().any(|d| d.get_key() == span.get_key()
+                && (d.get_onset_ms() - span.get_onset_ms()).abs() < frame_ms
+                && (d.get_offset_ms() - span.get_offset_ms()).abs() < frame_ms));
+        }
+    }
+}
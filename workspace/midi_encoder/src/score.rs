@@ -0,0 +1,552 @@
+// builtin
+
+// external
+
+// internal
+use crate::hands::{quantized_hands, Hand};
+use crate::quantize::{BeatGrid, QuantizedNote};
+use crate::spelling::{Pitch, Speller};
+use crate::types::{ExportOptions, KeySignature, TimeSignature, TICKS_PER_BEAT};
+
+// one beat of the quantizer is a quarter note in the score
+const QUARTER: u64 = TICKS_PER_BEAT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseValue {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+impl BaseValue {
+    pub fn ticks(&self) -> u64 {
+        QUARTER * 4 / self.denominator() as u64
+    }
+
+    // 1 for a whole note, 4 for a quarter
+    pub fn denominator(&self) -> u32 {
+        match self {
+            BaseValue::Whole => 1,
+            BaseValue::Half => 2,
+            BaseValue::Quarter => 4,
+            BaseValue::Eighth => 8,
+            BaseValue::Sixteenth => 16,
+            BaseValue::ThirtySecond => 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteValue {
+    base: BaseValue,
+    dots: u8,
+    triplet: bool,
+}
+
+impl NoteValue {
+    pub fn new(base: BaseValue, dots: u8, triplet: bool) -> NoteValue {
+        NoteValue {
+            base,
+            dots,
+            triplet,
+        }
+    }
+
+    pub fn get_base(&self) -> BaseValue {
+        self.base
+    }
+
+    pub fn get_dots(&self) -> u8 {
+        self.dots
+    }
+
+    // three in the time of two
+    pub fn is_triplet(&self) -> bool {
+        self.triplet
+    }
+
+    pub fn ticks(&self) -> u64 {
+        let base: u64 = self.base.ticks();
+        let dotted: u64 = base * 2 - base / 2u64.pow(self.dots as u32);
+        if self.triplet {
+            dotted * 2 / 3
+        } else {
+            dotted
+        }
+    }
+
+    /*
+     splits a length into values tied together, longest first. straight values are tried
+     before triplets of the same length, and a value is only taken if what's left can still
+     be written. whatever can't be written with values down to a 32nd triplet is left out.
+    */
+    pub fn split(ticks: u64) -> Vec<NoteValue> {
+        let bases: [BaseValue; 6] = [
+            BaseValue::Whole,
+            BaseValue::Half,
+            BaseValue::Quarter,
+            BaseValue::Eighth,
+            BaseValue::Sixteenth,
+            BaseValue::ThirtySecond,
+        ];
+        let mut values: Vec<NoteValue> = Vec::new();
+        for base in bases {
+            values.push(NoteValue::new(base, 1, false));
+            values.push(NoteValue::new(base, 0, false));
+            values.push(NoteValue::new(base, 0, true));
+        }
+        values.sort_by_key(|value| std::cmp::Reverse(value.ticks()));
+
+        // writable[t] is whether t ticks can be made up of the values
+        let mut writable: Vec<bool> = vec![false; ticks as usize + 1];
+        writable[0] = true;
+        for t in 1..writable.len() {
+            writable[t] = values.iter().any(|value| {
+                let value_ticks: usize = value.ticks() as usize;
+                value_ticks <= t && writable[t - value_ticks]
+            });
+        }
+
+        let mut remaining: usize = (0..writable.len())
+            .rev()
+            .find(|t| writable[*t])
+            .unwrap_or(0);
+        let mut split: Vec<NoteValue> = Vec::new();
+        while remaining > 0 {
+            let value: Option<NoteValue> = values.iter().copied().find(|value| {
+                let value_ticks: usize = value.ticks() as usize;
+                value_ticks <= remaining && writable[remaining - value_ticks]
+            });
+            match value {
+                Some(value) => {
+                    remaining -= value.ticks() as usize;
+                    split.push(value);
+                }
+                None => break,
+            }
+        }
+
+        split
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    Chord {
+        keys: Vec<u8>,
+        // one per key, spelled for the key signature and the line around the chord
+        pitches: Vec<Pitch>,
+        value: NoteValue,
+        tie_start: bool,
+        tie_stop: bool,
+    },
+    Rest(NoteValue),
+    // a rest filling the whole measure, whatever its length
+    MeasureRest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Measure {
+    elements: Vec<Element>,
+}
+
+impl Measure {
+    pub fn get_elements(&self) -> &Vec<Element> {
+        &self.elements
+    }
+}
+
+/*
+ a grand staff, right hand on the upper staff and left hand on the lower.
+ each staff is a single voice: notes starting together form a chord, and a chord is cut
+ short by the next onset on its staff. notes running over a barline are tied, and so are
+ off-beat notes running over the next beat.
+*/
+pub struct Score {
+    title: Option<String>,
+    tempo_bpm: f64,
+    time_signature: TimeSignature,
+    key_signature: KeySignature,
+    upper: Vec<Measure>,
+    lower: Vec<Measure>,
+}
+
+impl Score {
+    pub fn new(notes: &[QuantizedNote], options: &ExportOptions) -> Score {
+        // the notes are laid back out at the export tempo to find the hands
+        let grid: BeatGrid = BeatGrid::constant(60_000_000.0 / options.tempo() as f64, 0.0);
+        Score::with_hands(
+            notes,
+            &quantized_hands(notes, options.hand_split(), &grid),
+            options,
+        )
+    }
+
+    // every note on the upper staff, for single line notation
+    pub fn melody(notes: &[QuantizedNote], options: &ExportOptions) -> Score {
+        Score::with_hands(notes, &vec![Hand::Right; notes.len()], options)
+    }
+
+    fn with_hands(notes: &[QuantizedNote], hands: &[Hand], options: &ExportOptions) -> Score {
+        let time_signature: TimeSignature = options.time_signature().unwrap_or_default();
+        let measure_ticks: u64 = time_signature.measure_ticks();
+
+        let last_tick: u64 = notes
+            .iter()
+            .map(|n| n.get_offset_ticks())
+            .max()
+            .unwrap_or(0);
+        let num_measures: usize = (last_tick.div_ceil(measure_ticks) as usize).max(1);
+
+        let staff_notes = |hand: Hand| -> Vec<&QuantizedNote> {
+            notes
+                .iter()
+                .zip(hands.iter())
+                .filter(|(_, h)| **h == hand)
+                .map(|(note, _)| note)
+                .collect()
+        };
+
+        let key_signature: KeySignature = options
+            .key_signature()
+            .unwrap_or(KeySignature::new(0, false));
+        let staff = |hand: Hand| -> Vec<Measure> {
+            build_staff(
+                &staff_notes(hand),
+                &time_signature,
+                &key_signature,
+                num_measures,
+            )
+        };
+
+        Score {
+            title: options.title().map(|title| title.to_string()),
+            tempo_bpm: 60_000_000.0 / options.tempo() as f64,
+            time_signature,
+            key_signature,
+            upper: staff(Hand::Right),
+            lower: staff(Hand::Left),
+        }
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn tempo_bpm(&self) -> f64 {
+        self.tempo_bpm
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    pub fn key_signature(&self) -> KeySignature {
+        self.key_signature
+    }
+
+    pub fn measure_ticks(&self) -> u64 {
+        self.time_signature.measure_ticks()
+    }
+
+    pub fn get_upper(&self) -> &Vec<Measure> {
+        &self.upper
+    }
+
+    pub fn get_lower(&self) -> &Vec<Measure> {
+        &self.lower
+    }
+
+    pub fn num_measures(&self) -> usize {
+        self.upper.len()
+    }
+}
+
+fn build_staff(
+    notes: &[&QuantizedNote],
+    time_signature: &TimeSignature,
+    key_signature: &KeySignature,
+    num_measures: usize,
+) -> Vec<Measure> {
+    let measure_ticks: u64 = time_signature.measure_ticks();
+    let beat_ticks: u64 = time_signature.beat_ticks();
+
+    // (onset, length, keys) with rests left as gaps
+    let mut chords: Vec<(u64, u64, Vec<u8>)> = Vec::new();
+    let mut onsets: Vec<u64> = notes.iter().map(|n| n.get_onset_ticks()).collect();
+    onsets.sort();
+    onsets.dedup();
+
+    for (i, onset) in onsets.iter().enumerate() {
+        let mut keys: Vec<u8> = Vec::new();
+        let mut length: u64 = 0;
+        for note in notes.iter().filter(|n| n.get_onset_ticks() == *onset) {
+            keys.push(note.get_key());
+            length = length.max(note.get_duration_ticks());
+        }
+        keys.sort();
+        keys.dedup();
+
+        if let Some(next) = onsets.get(i + 1) {
+            length = length.min(next - onset);
+        }
+        chords.push((*onset, length, keys));
+    }
+
+    // each chord is spelled against the top notes of its neighbours
+    let mut speller: Speller = Speller::new(*key_signature);
+    let tops: Vec<Option<u8>> = chords
+        .iter()
+        .map(|(_, _, keys)| keys.last().copied())
+        .collect();
+    let spelled: Vec<Vec<Pitch>> = chords
+        .iter()
+        .enumerate()
+        .map(|(i, (_, _, keys))| {
+            let previous: Option<u8> = if i > 0 { tops[i - 1] } else { None };
+            let next: Option<u8> = tops.get(i + 1).copied().flatten();
+            speller.spell(keys, previous, next)
+        })
+        .collect();
+
+    let mut measures: Vec<Measure> = vec![Measure::default(); num_measures];
+    let mut cursor: u64 = 0;
+
+    for ((onset, length, keys), pitches) in chords.into_iter().zip(spelled) {
+        if onset > cursor {
+            place(
+                &mut measures,
+                cursor,
+                onset - cursor,
+                None,
+                measure_ticks,
+                beat_ticks,
+            );
+        }
+        place(
+            &mut measures,
+            onset,
+            length,
+            Some((&keys, &pitches)),
+            measure_ticks,
+            beat_ticks,
+        );
+        cursor = onset + length;
+    }
+
+    let end: u64 = num_measures as u64 * measure_ticks;
+    if end > cursor {
+        place(
+            &mut measures,
+            cursor,
+            end - cursor,
+            None,
+            measure_ticks,
+            beat_ticks,
+        );
+    }
+
+    for measure in measures.iter_mut() {
+        let all_rests: bool = measure
+            .elements
+            .iter()
+            .all(|e| matches!(e, Element::Rest(_)));
+        if all_rests {
+            measure.elements = vec![Element::MeasureRest];
+        }
+    }
+
+    measures
+}
+
+// lays a chord or rest across as many measures as it runs over, tying each piece to the next
+fn place(
+    measures: &mut [Measure],
+    start: u64,
+    length: u64,
+    keys: Option<(&Vec<u8>, &Vec<Pitch>)>,
+    measure_ticks: u64,
+    beat_ticks: u64,
+) {
+    let mut pieces: Vec<(usize, NoteValue)> = Vec::new();
+    let mut tick: u64 = start;
+    let end: u64 = start + length;
+
+    while tick < end {
+        let measure: usize = (tick / measure_ticks) as usize;
+        let bar_end: u64 = (measure as u64 + 1) * measure_ticks;
+        // off-beat starts run to the next beat first, so the beat stays visible
+        let beat_end: u64 = if tick.is_multiple_of(beat_ticks) {
+            bar_end
+        } else {
+            (tick / beat_ticks + 1) * beat_ticks
+        };
+        let piece: u64 = end.min(bar_end).min(beat_end) - tick;
+
+        for value in NoteValue::split(piece) {
+            pieces.push((measure, value));
+        }
+        tick += piece;
+    }
+
+    let last: usize = pieces.len().saturating_sub(1);
+    for (i, (measure, value)) in pieces.into_iter().enumerate() {
+        let Some(measure) = measures.get_mut(measure) else {
+            break;
+        };
+        measure.elements.push(match keys {
+            Some((keys, pitches)) => Element::Chord {
+                keys: keys.clone(),
+                pitches: pitches.clone(),
+                value,
+                tie_start: i < last,
+                tie_stop: i > 0,
+            },
+            None => Element::Rest(value),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quantize::{quantize, BeatGrid, QuantizeConfig, NOTATABLE_DIVISIONS};
+    use crate::span::{spans_to_events, NoteSpan};
+    use crate::types::HandSplit;
+
+    fn notes(spans: &[(u8, f64, f64)]) -> Vec<QuantizedNote> {
+        let spans: Vec<NoteSpan> = spans
+            .iter()
+            .map(|(key, onset, offset)| NoteSpan::new(*key, onset * 500.0, offset * 500.0, 64))
+            .collect();
+        quantize(
+            &spans_to_events(&spans),
+            &BeatGrid::constant(120.0, 0.0),
+            &QuantizeConfig::default(),
+        )
+    }
+
+    #[test]
+    fn values_split_into_writable_lengths() {
+        let quarter: NoteValue = NoteValue::new(BaseValue::Quarter, 0, false);
+        assert_eq!(NoteValue::split(480), vec![quarter]);
+        assert_eq!(NoteValue::new(BaseValue::Quarter, 1, false).ticks(), 720);
+        assert_eq!(NoteValue::new(BaseValue::Eighth, 0, true).ticks(), 160);
+
+        // five eighths become a half tied to an eighth
+        assert_eq!(
+            NoteValue::split(1200),
+            vec![
+                NoteValue::new(BaseValue::Half, 0, false),
+                NoteValue::new(BaseValue::Eighth, 0, false),
+            ]
+        );
+        assert_eq!(
+            NoteValue::split(160),
+            vec![NoteValue::new(BaseValue::Eighth, 0, true)]
+        );
+    }
+
+    #[test]
+    fn quantized_lengths_are_writable() {
+        // each beat is on a single division, a length ends on the same one or in a later beat
+        let points = |beat: u64, division: u32| -> Vec<u64> {
+            (0..division as u64)
+                .map(|i| beat * QUARTER + i * QUARTER / division as u64)
+                .collect()
+        };
+        for first in NOTATABLE_DIVISIONS {
+            for last in NOTATABLE_DIVISIONS {
+                let ends: Vec<u64> = [points(0, first), points(1, last), points(2, last)].concat();
+                for start in points(0, first) {
+                    for end in ends.iter().copied().filter(|end| *end > start) {
+                        let split: Vec<NoteValue> = NoteValue::split(end - start);
+                        assert_eq!(
+                            split.iter().map(|value| value.ticks()).sum::<u64>(),
+                            end - start,
+                            "{start} to {end} can't be written"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn notes_tie_over_barlines() {
+        // a half note starting on beat four of a 4/4 bar
+        let mut options: ExportOptions = ExportOptions::multi_track();
+        options.set_hand_split(HandSplit::SplitPoint(60));
+        let score: Score = Score::new(&notes(&[(72, 3.0, 5.0)]), &options);
+
+        assert_eq!(score.num_measures(), 2);
+        let quarter: NoteValue = NoteValue::new(BaseValue::Quarter, 0, false);
+        assert_eq!(
+            score.get_upper()[0].get_elements(),
+            &vec![
+                Element::Rest(NoteValue::new(BaseValue::Half, 1, false)),
+                Element::Chord {
+                    keys: vec![72],
+                    pitches: vec![Pitch::new('C', 0, 5)],
+                    value: quarter,
+                    tie_start: true,
+                    tie_stop: false
+                },
+            ]
+        );
+        assert_eq!(
+            score.get_upper()[1].get_elements()[0],
+            Element::Chord {
+                keys: vec![72],
+                pitches: vec![Pitch::new('C', 0, 5)],
+                value: quarter,
+                tie_start: false,
+                tie_stop: true
+            }
+        );
+
+        // the left hand has nothing to play
+        assert!(score
+            .get_lower()
+            .iter()
+            .all(|m| m.get_elements() == &vec![Element::MeasureRest]));
+    }
+
+    #[test]
+    fn chords_are_cut_by_next_onset() {
+        let score: Score = Score::new(
+            &notes(&[(60, 0.0, 2.0), (64, 0.0, 1.0), (67, 1.0, 2.0)]),
+            &ExportOptions::multi_track(),
+        );
+        let quarter: NoteValue = NoteValue::new(BaseValue::Quarter, 0, false);
+        let elements: &Vec<Element> = score.get_upper()[0].get_elements();
+
+        assert_eq!(
+            elements[0],
+            Element::Chord {
+                keys: vec![60, 64],
+                pitches: vec![Pitch::new('C', 0, 4), Pitch::new('E', 0, 4)],
+                value: quarter,
+                tie_start: false,
+                tie_stop: false
+            }
+        );
+        assert_eq!(
+            elements[1],
+            Element::Chord {
+                keys: vec![67],
+                pitches: vec![Pitch::new('G', 0, 4)],
+                value: quarter,
+                tie_start: false,
+                tie_stop: false
+            }
+        );
+        assert_eq!(
+            elements[2],
+            Element::Rest(NoteValue::new(BaseValue::Half, 0, false))
+        );
+    }
+}
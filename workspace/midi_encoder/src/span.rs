@@ -0,0 +1,324 @@
+// builtin
+use std::collections::VecDeque;
+
+// external
+
+// internal
+use crate::encoder::{decode, encode};
+use crate::error::MidiError;
+use crate::types::{EncodingData, MIDIEncoding, NoteEvent, OutOfRangePolicy};
+
+pub(crate) const KEYS: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct NoteSpan {
+    key: u8,
+    onset_ms: f64,
+    offset_ms: f64,
+    velocity: u8,
+}
+
+impl NoteSpan {
+    pub fn new(key: u8, onset_ms: f64, offset_ms: f64, velocity: u8) -> NoteSpan {
+        NoteSpan {
+            key,
+            onset_ms,
+            offset_ms,
+            velocity,
+        }
+    }
+
+    pub fn get_key(&self) -> u8 {
+        self.key
+    }
+
+    pub fn get_onset_ms(&self) -> f64 {
+        self.onset_ms
+    }
+
+    pub fn get_offset_ms(&self) -> f64 {
+        self.offset_ms
+    }
+
+    pub fn get_duration_ms(&self) -> f64 {
+        self.offset_ms - self.onset_ms
+    }
+
+    pub fn get_velocity(&self) -> u8 {
+        self.velocity
+    }
+
+    pub fn overlaps(&self, other: &NoteSpan) -> bool {
+        self.onset_ms < other.offset_ms && other.onset_ms < self.offset_ms
+    }
+}
+
+// absolute times, taken from the events that know theirs and summed in f64 from the deltas otherwise
+pub fn event_times(events: &[NoteEvent]) -> Vec<f64> {
+    let mut time: f64 = 0.0;
+    events
+        .iter()
+        .map(|event| {
+            time = event
+                .get_time_ms()
+                .unwrap_or(time + event.get_time_delta() as f64);
+            time
+        })
+        .collect()
+}
+
+/*
+ releases are matched to the earliest open onset of the same key, so an overlapping
+ re-strike keeps its own release. releases without an onset are ignored and onsets
+ that are never released end with the last event. pedal events carry no notes and
+ are skipped, apply sustain first to keep their effect.
+*/
+pub fn events_to_spans(events: &[NoteEvent]) -> Vec<NoteSpan> {
+    let mut spans: Vec<NoteSpan> = Vec::new();
+    let mut open: Vec<VecDeque<usize>> = vec![VecDeque::new(); KEYS];
+
+    let mut time: f64 = 0.0;
+
+    for (event, event_time) in events.iter().zip(event_times(events)) {
+        time = event_time;
+
+        if event.is_pedal() {
+            continue;
+        }
+
+        let note = event.get_note_ref();
+        let key: usize = note.get_key() as usize;
+
+        if note.is_note_on() {
+            open[key].push_back(spans.len());
+            spans.push(NoteSpan::new(
+                note.get_key(),
+                time,
+                time,
+                note.get_velocity(),
+            ));
+        } else if let Some(index) = open[key].pop_front() {
+            spans[index].offset_ms = time;
+        }
+    }
+
+    for index in open.iter().flatten() {
+        spans[*index].offset_ms = time;
+    }
+
+    spans
+}
+
+// keys pressed more often than released, once per missing release
+pub fn stuck_notes(events: &[NoteEvent]) -> Vec<u8> {
+    let mut sounding: [u32; KEYS] = [0; KEYS];
+
+    for event in events.iter().filter(|e| !e.is_pedal()) {
+        let note = event.get_note_ref();
+        let key: usize = note.get_key() as usize;
+        if note.is_note_on() {
+            sounding[key] += 1;
+        } else {
+            sounding[key] = sounding[key].saturating_sub(1);
+        }
+    }
+
+    let mut stuck: Vec<u8> = Vec::new();
+    for (key, count) in sounding.iter().enumerate() {
+        for _ in 0..*count {
+            stuck.push(key as u8);
+        }
+    }
+
+    stuck
+}
+
+// events sharing a time may come back in a different order than they were parsed in
+pub fn spans_to_events(spans: &[NoteSpan]) -> Vec<NoteEvent> {
+    // at equal times releases come first, except for zero length notes which need their onset
+    let mut timeline: Vec<(f64, u8, NoteEvent)> = Vec::with_capacity(spans.len() * 2);
+
+    for span in spans {
+        let release_order: u8 = if span.get_duration_ms() > 0.0 { 0 } else { 2 };
+        timeline.push((
+            span.onset_ms,
+            1,
+            NoteEvent::with_velocity(0.0, span.key, true, span.velocity),
+        ));
+        timeline.push((
+            span.offset_ms,
+            release_order,
+            NoteEvent::new(0.0, span.key, false),
+        ));
+    }
+
+    timeline.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    let mut events: Vec<NoteEvent> = Vec::with_capacity(timeline.len());
+    let mut last_time: f64 = 0.0;
+
+    for (time, _, event) in timeline {
+        events
+            .push(NoteEvent::from_note((time - last_time) as f32, event.get_note()).at_time(time));
+        last_time = time;
+    }
+
+    events
+}
+
+pub fn encoding_to_spans(midi: MIDIEncoding) -> Vec<NoteSpan> {
+    events_to_spans(decode(midi).get_events())
+}
+
+// unquantized and strict about the key range, so nothing is lost on the way in
+pub fn spans_to_encoding(spans: &[NoteSpan]) -> Result<MIDIEncoding, MidiError> {
+    let mut data: EncodingData = EncodingData::new(spans_to_events(spans));
+    data.set_key_policy(OutOfRangePolicy::Error);
+    encode(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+
+    #[test]
+    fn absolute_times_outlast_deltas() {
+        // six minutes of notes a third of a millisecond apart, which f32 deltas can't hold exactly
+        let times: Vec<f64> = (1..=1_080_000).map(|i| i as f64 / 3.0).collect();
+        let events: Vec<NoteEvent> = times
+            .iter()
+            .map(|time| NoteEvent::new((1.0 / 3.0) as f32, 60, true).at_time(*time))
+            .collect();
+        assert_eq!(event_times(&events), times);
+
+        let summed: Vec<NoteEvent> = events
+            .iter()
+            .map(|e| e.clone().with_time_delta(e.get_time_delta()))
+            .collect();
+        assert_eq!(summed[0].get_time_ms(), None);
+        assert!((event_times(&summed)[times.len() - 1] - 360_000.0).abs() > 1e-3);
+
+        // spans keep their times through events and back
+        let spans: Vec<NoteSpan> = vec![NoteSpan::new(60, 3_599_999.9, 3_600_000.3, 80)];
+        assert_eq!(events_to_spans(&spans_to_events(&spans)), spans);
+    }
+
+    #[test]
+    fn timing_test_round_trip() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+        println!("{:#?}", spans);
+
+        assert_eq!(spans_to_events(&spans), events);
+    }
+
+    #[test]
+    fn double_note_round_trip() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+        let rebuilt: Vec<NoteEvent> = spans_to_events(&spans);
+
+        assert_eq!(rebuilt.len(), events.len());
+        assert_eq!(events_to_spans(&rebuilt), spans);
+    }
+
+    #[test]
+    fn encoding_round_trip() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+
+        let encoding: MIDIEncoding = spans_to_encoding(&spans).expect("Failed to encode spans");
+        assert_eq!(encoding_to_spans(encoding), spans);
+    }
+
+    #[test]
+    fn data_spans() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Data_Test.midi").expect("Failed to parse MIDI file");
+        let onsets: usize = events
+            .iter()
+            .filter(|e| !e.is_pedal() && e.get_note_ref().is_note_on())
+            .count();
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+
+        assert_eq!(spans.len(), onsets);
+        assert!(spans.iter().all(|span| span.get_duration_ms() >= 0.0));
+    }
+
+    #[test]
+    fn overlapping_restrike() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::with_velocity(0.0, 60, true, 80),
+            NoteEvent::with_velocity(100.0, 60, true, 40),
+            NoteEvent::new(100.0, 60, false),
+            NoteEvent::new(100.0, 60, false),
+        ];
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+
+        assert_eq!(
+            spans,
+            vec![
+                NoteSpan::new(60, 0.0, 200.0, 80),
+                NoteSpan::new(60, 100.0, 300.0, 40),
+            ]
+        );
+        assert!(spans[0].overlaps(&spans[1]));
+        assert_eq!(spans_to_events(&spans), events);
+    }
+
+    #[test]
+    fn unmatched_events() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 62, false),
+            NoteEvent::new(50.0, 60, true),
+            NoteEvent::new(100.0, 64, true),
+            NoteEvent::new(100.0, 64, false),
+        ];
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+
+        assert_eq!(
+            spans,
+            vec![
+                NoteSpan::new(60, 50.0, 250.0, 64),
+                NoteSpan::new(64, 150.0, 250.0, 64),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_stuck_notes() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(100.0, 60, false),
+            NoteEvent::new(0.0, 64, true),
+            NoteEvent::new(0.0, 67, false),
+        ];
+
+        assert_eq!(stuck_notes(&events), vec![60, 64]);
+    }
+
+    #[test]
+    fn zero_length_and_sequential_notes() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(100.0, 60, false),
+            NoteEvent::new(0.0, 60, true),
+            NoteEvent::new(0.0, 60, false),
+        ];
+        let spans: Vec<NoteSpan> = events_to_spans(&events);
+
+        assert_eq!(
+            spans,
+            vec![
+                NoteSpan::new(60, 0.0, 100.0, 64),
+                NoteSpan::new(60, 100.0, 100.0, 64),
+            ]
+        );
+        assert_eq!(spans_to_events(&spans), events);
+    }
+}
@@ -0,0 +1,286 @@
+// builtin
+use std::collections::VecDeque;
+
+// external
+
+// internal
+use crate::types::KeySignature;
+
+// letters in circle of fifths order, F is one flat of C
+const LETTERS: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+
+// up to double sharps and double flats
+const LOWEST_POSITION: i32 = -15;
+const HIGHEST_POSITION: i32 = 19;
+
+// how many spelled notes make up the local context, and how much it counts against the key
+const CONTEXT_NOTES: usize = 8;
+const CONTEXT_WEIGHT: f32 = 0.4;
+
+// how far a rising or falling line pulls chromatic notes towards sharps or flats
+const DIRECTION_PULL: f32 = 2.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pitch {
+    step: char,
+    alter: i8,
+    octave: i8,
+}
+
+impl Pitch {
+    pub fn new(step: char, alter: i8, octave: i8) -> Pitch {
+        Pitch {
+            step,
+            alter,
+            octave,
+        }
+    }
+
+    // the key's tonic, which the letter follows from walking the circle of fifths
+    pub fn tonic(key_signature: &KeySignature) -> Pitch {
+        let minor: i32 = if key_signature.is_minor() { 3 } else { 0 };
+        let mut tonic: Pitch = Pitch::from_position(key_signature.get_fifths() as i32 + minor, 0);
+        tonic.octave = 4;
+        tonic
+    }
+
+    // the spelling closest to the key on the line of fifths, without any melodic context
+    pub fn spell(key: u8, key_signature: &KeySignature) -> Pitch {
+        spell_with_center(key, key_center(key_signature))
+    }
+
+    /*
+     a pitch at a place on the line of fifths, C at 0, G at 1 and F at -1.
+     every seven places further adds a sharp, every seven back a flat.
+    */
+    fn from_position(position: i32, key: u8) -> Pitch {
+        let step: char = LETTERS[(position + 1).rem_euclid(7) as usize];
+        let alter: i8 = (position + 1).div_euclid(7) as i8;
+
+        // B sharp belongs to the octave below the C it sounds as, C flat to the one above
+        let natural: i32 = key as i32 - alter as i32;
+        Pitch {
+            step,
+            alter,
+            octave: natural.div_euclid(12) as i8 - 1,
+        }
+    }
+
+    fn position(&self) -> i32 {
+        let letter: i32 = LETTERS.iter().position(|l| *l == self.step).unwrap_or(1) as i32;
+        letter - 1 + 7 * self.alter as i32
+    }
+
+    pub fn get_step(&self) -> char {
+        self.step
+    }
+
+    pub fn get_alter(&self) -> i8 {
+        self.alter
+    }
+
+    pub fn get_octave(&self) -> i8 {
+        self.octave
+    }
+
+    // scientific pitch notation, like C#4 for the key above middle C
+    pub fn name(&self) -> String {
+        let accidental: String = if self.alter >= 0 {
+            "#".repeat(self.alter as usize)
+        } else {
+            "b".repeat(-self.alter as usize)
+        };
+        format!("{}{}{}", self.step, accidental, self.octave)
+    }
+}
+
+// the middle of the key's scale on the line of fifths, minor keys lean towards their leading tone
+fn key_center(key_signature: &KeySignature) -> f32 {
+    let center: f32 = key_signature.get_fifths() as f32 + 2.0;
+    if key_signature.is_minor() {
+        center + 1.5
+    } else {
+        center
+    }
+}
+
+// scale notes of the key itself, including the raised sixth and seventh of minor keys
+fn in_key(position: i32, key_signature: &KeySignature) -> bool {
+    let fifths: i32 = key_signature.get_fifths() as i32;
+    let highest: i32 = if key_signature.is_minor() {
+        fifths + 8
+    } else {
+        fifths + 5
+    };
+    (fifths - 1..=highest).contains(&position)
+}
+
+fn candidates(key: u8) -> impl Iterator<Item = i32> {
+    // seven is its own inverse modulo twelve, so this is the position of the sharp spelling
+    let base: i32 = (key as i32 % 12) * 7 % 12;
+    [base - 12, base, base + 12]
+        .into_iter()
+        .filter(|p| (LOWEST_POSITION..=HIGHEST_POSITION).contains(p))
+}
+
+fn spell_with_center(key: u8, center: f32) -> Pitch {
+    let position: i32 = candidates(key)
+        .min_by(|a, b| {
+            let distance_a: f32 = (*a as f32 - center).abs();
+            let distance_b: f32 = (*b as f32 - center).abs();
+            distance_a.total_cmp(&distance_b)
+        })
+        .unwrap_or(0);
+    Pitch::from_position(position, key)
+}
+
+/*
+ spells notes one after another, so that each note is named to fit both the key and the
+ notes just before it. chromatic notes lean to sharps in a rising line and flats in a
+ falling one, so C to D passes through C# and D to C through Db.
+*/
+pub struct Speller {
+    key_signature: KeySignature,
+    recent: VecDeque<i32>,
+}
+
+impl Speller {
+    pub fn new(key_signature: KeySignature) -> Speller {
+        Speller {
+            key_signature,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn key_signature(&self) -> KeySignature {
+        self.key_signature
+    }
+
+    // previous and next are the neighbouring keys of the melody, if there are any
+    pub fn spell(&mut self, keys: &[u8], previous: Option<u8>, next: Option<u8>) -> Vec<Pitch> {
+        let key_center: f32 = key_center(&self.key_signature);
+        let center: f32 = if self.recent.is_empty() {
+            key_center
+        } else {
+            let context: f32 = self.recent.iter().sum::<i32>() as f32 / self.recent.len() as f32;
+            key_center * (1.0 - CONTEXT_WEIGHT) + context * CONTEXT_WEIGHT
+        };
+
+        let top: u8 = keys.iter().copied().max().unwrap_or(0);
+        let direction: i32 = match (previous, next) {
+            (Some(previous), Some(next)) => (next as i32 - previous as i32).signum(),
+            (Some(previous), None) => (top as i32 - previous as i32).signum(),
+            (None, Some(next)) => (next as i32 - top as i32).signum(),
+            (None, None) => 0,
+        };
+
+        let pitches: Vec<Pitch> = keys
+            .iter()
+            .map(|key| {
+                let plain: Pitch = spell_with_center(*key, center);
+                if in_key(plain.position(), &self.key_signature) {
+                    plain
+                } else {
+                    spell_with_center(*key, center + direction as f32 * DIRECTION_PULL)
+                }
+            })
+            .collect();
+
+        for pitch in pitches.iter() {
+            self.recent.push_back(pitch.position());
+            if self.recent.len() > CONTEXT_NOTES {
+                self.recent.pop_front();
+            }
+        }
+        pitches
+    }
+}
+
+// a single line of notes, each spelled with the ones around it
+pub fn spell_melody(keys: &[u8], key_signature: &KeySignature) -> Vec<Pitch> {
+    let mut speller: Speller = Speller::new(*key_signature);
+    keys.iter()
+        .enumerate()
+        .flat_map(|(i, key)| {
+            let previous: Option<u8> = i.checked_sub(1).map(|p| keys[p]);
+            speller.spell(&[*key], previous, keys.get(i + 1).copied())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(pitches: &[Pitch]) -> Vec<String> {
+        pitches.iter().map(|p| p.name()).collect()
+    }
+
+    #[test]
+    fn spelling_follows_key() {
+        let sharp: Pitch = Pitch::spell(61, &KeySignature::new(2, false));
+        assert_eq!(
+            (sharp.get_step(), sharp.get_alter(), sharp.get_octave()),
+            ('C', 1, 4)
+        );
+
+        let flat: Pitch = Pitch::spell(70, &KeySignature::new(-1, false));
+        assert_eq!(
+            (flat.get_step(), flat.get_alter(), flat.get_octave()),
+            ('B', -1, 4)
+        );
+
+        // the leading tone of A minor and the flat seventh in C major
+        assert_eq!(Pitch::spell(68, &KeySignature::new(0, true)).name(), "G#4");
+        assert_eq!(Pitch::spell(70, &KeySignature::new(0, false)).name(), "Bb4");
+    }
+
+    #[test]
+    fn enharmonics_keep_their_octave() {
+        // E# and B# in C# major, C flat in G flat major
+        let c_sharp: KeySignature = KeySignature::new(7, false);
+        assert_eq!(Pitch::spell(65, &c_sharp).name(), "E#4");
+        assert_eq!(Pitch::spell(60, &c_sharp).name(), "B#3");
+        assert_eq!(
+            Pitch::spell(59, &KeySignature::new(-6, false)).name(),
+            "Cb4"
+        );
+        assert_eq!(Pitch::tonic(&KeySignature::new(-6, false)).name(), "Gb4");
+        assert_eq!(Pitch::tonic(&KeySignature::new(3, true)).name(), "F#4");
+    }
+
+    #[test]
+    fn chromatic_lines() {
+        let c_major: KeySignature = KeySignature::new(0, false);
+        assert_eq!(
+            names(&spell_melody(&[60, 61, 62, 63, 64], &c_major)),
+            vec!["C4", "C#4", "D4", "D#4", "E4"]
+        );
+        assert_eq!(
+            names(&spell_melody(&[64, 63, 62, 61, 60], &c_major)),
+            vec!["E4", "Eb4", "D4", "Db4", "C4"]
+        );
+
+        // a neighbour note going back where it came from follows the key
+        assert_eq!(
+            names(&spell_melody(&[67, 68, 67], &c_major)),
+            names(&[
+                Pitch::spell(67, &c_major),
+                Pitch::spell(68, &c_major),
+                Pitch::spell(67, &c_major)
+            ])
+        );
+    }
+
+    #[test]
+    fn context_shifts_spelling() {
+        // after a run of flats a note between keys is read as a flat too
+        let mut speller: Speller = Speller::new(KeySignature::new(0, false));
+        speller.spell(&[70, 63, 68], None, None);
+        assert_eq!(names(&speller.spell(&[61], None, None)), vec!["Db4"]);
+
+        let mut speller: Speller = Speller::new(KeySignature::new(0, false));
+        speller.spell(&[66, 61, 66], None, None);
+        assert_eq!(names(&speller.spell(&[63], None, None)), vec!["D#4"]);
+    }
+}
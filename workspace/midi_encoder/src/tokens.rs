@@ -0,0 +1,228 @@
+// builtin
+
+// external
+
+// internal
+use crate::error::MidiError;
+use crate::span::event_times;
+use crate::types::{NoteEvent, DEFAULT_VELOCITY};
+
+pub const TIME_SHIFT_MS: f64 = 10.0;
+pub const MAX_SHIFT_STEPS: u8 = 100;
+
+/*
+ vocabulary:
+ [0, 128) note on, [128, 256) note off,
+ [256, 356) time shift of 1 to 100 steps, [356, 484) velocity,
+ 484 pedal down, 485 pedal up
+*/
+const NOTE_ON_OFFSET: usize = 0;
+const NOTE_OFF_OFFSET: usize = 128;
+const TIME_SHIFT_OFFSET: usize = 256;
+const VELOCITY_OFFSET: usize = TIME_SHIFT_OFFSET + MAX_SHIFT_STEPS as usize;
+const PEDAL_DOWN_INDEX: usize = VELOCITY_OFFSET + 128;
+const PEDAL_UP_INDEX: usize = PEDAL_DOWN_INDEX + 1;
+
+pub const VOCAB_SIZE: usize = PEDAL_UP_INDEX + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    NoteOn(u8),
+    NoteOff(u8),
+    TimeShift(u8),
+    Velocity(u8),
+    PedalDown,
+    PedalUp,
+}
+
+impl Token {
+    // keys and velocities above 127 or shifts outside 1 to MAX_SHIFT_STEPS have no index
+    pub fn index(&self) -> Result<usize, MidiError> {
+        match self {
+            Token::NoteOn(key) if *key < 128 => Ok(NOTE_ON_OFFSET + *key as usize),
+            Token::NoteOff(key) if *key < 128 => Ok(NOTE_OFF_OFFSET + *key as usize),
+            Token::TimeShift(steps) if (1..=MAX_SHIFT_STEPS).contains(steps) => {
+                Ok(TIME_SHIFT_OFFSET + *steps as usize - 1)
+            }
+            Token::Velocity(velocity) if *velocity < 128 => {
+                Ok(VELOCITY_OFFSET + *velocity as usize)
+            }
+            Token::PedalDown => Ok(PEDAL_DOWN_INDEX),
+            Token::PedalUp => Ok(PEDAL_UP_INDEX),
+            _ => Err(MidiError::InvalidToken(*self)),
+        }
+    }
+
+    pub fn from_index(index: usize) -> Option<Token> {
+        match index {
+            i if i < NOTE_OFF_OFFSET => Some(Token::NoteOn((i - NOTE_ON_OFFSET) as u8)),
+            i if i < TIME_SHIFT_OFFSET => Some(Token::NoteOff((i - NOTE_OFF_OFFSET) as u8)),
+            i if i < VELOCITY_OFFSET => Some(Token::TimeShift((i - TIME_SHIFT_OFFSET + 1) as u8)),
+            i if i < PEDAL_DOWN_INDEX => Some(Token::Velocity((i - VELOCITY_OFFSET) as u8)),
+            PEDAL_DOWN_INDEX => Some(Token::PedalDown),
+            PEDAL_UP_INDEX => Some(Token::PedalUp),
+            _ => None,
+        }
+    }
+}
+
+// times snap to TIME_SHIFT_MS steps, velocity is only written when it changes
+pub fn events_to_tokens(events: &[NoteEvent]) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut velocity: Option<u8> = None;
+
+    // steps come from absolute time so rounding never accumulates
+    let mut last_step: u64 = 0;
+
+    for (event, time) in events.iter().zip(event_times(events)) {
+        let step: u64 = (time / TIME_SHIFT_MS).round() as u64;
+
+        let mut remaining: u64 = step.saturating_sub(last_step);
+        while remaining > 0 {
+            let shift: u8 = remaining.min(MAX_SHIFT_STEPS as u64) as u8;
+            tokens.push(Token::TimeShift(shift));
+            remaining -= shift as u64;
+        }
+        last_step = last_step.max(step);
+
+        let note = event.get_note_ref();
+        if event.is_pedal() {
+            tokens.push(if note.is_note_on() {
+                Token::PedalDown
+            } else {
+                Token::PedalUp
+            });
+        } else if note.is_note_on() {
+            if velocity != Some(note.get_velocity()) {
+                velocity = Some(note.get_velocity());
+                tokens.push(Token::Velocity(note.get_velocity()));
+            }
+            tokens.push(Token::NoteOn(note.get_key()));
+        } else {
+            tokens.push(Token::NoteOff(note.get_key()));
+        }
+    }
+
+    tokens
+}
+
+pub fn tokens_to_events(tokens: &[Token]) -> Vec<NoteEvent> {
+    let mut events: Vec<NoteEvent> = Vec::new();
+    let mut velocity: u8 = DEFAULT_VELOCITY;
+    let mut pending_steps: u64 = 0;
+
+    for token in tokens {
+        let time_delta: f32 = (pending_steps as f64 * TIME_SHIFT_MS) as f32;
+
+        let event: NoteEvent = match token {
+            Token::TimeShift(steps) => {
+                pending_steps += *steps as u64;
+                continue;
+            }
+            Token::Velocity(new_velocity) => {
+                velocity = *new_velocity;
+                continue;
+            }
+            Token::NoteOn(key) => NoteEvent::with_velocity(time_delta, *key, true, velocity),
+            Token::NoteOff(key) => NoteEvent::new(time_delta, *key, false),
+            Token::PedalDown => NoteEvent::pedal(time_delta, true),
+            Token::PedalUp => NoteEvent::pedal(time_delta, false),
+        };
+
+        events.push(event);
+        pending_steps = 0;
+    }
+
+    events
+}
+
+pub fn tokens_to_indices(tokens: &[Token]) -> Result<Vec<usize>, MidiError> {
+    tokens.iter().map(|token| token.index()).collect()
+}
+
+// indices outside the vocabulary are skipped
+pub fn indices_to_tokens(indices: &[usize]) -> Vec<Token> {
+    indices
+        .iter()
+        .filter_map(|index| Token::from_index(*index))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::parse_midi;
+
+    #[test]
+    fn vocabulary_round_trip() {
+        for index in 0..VOCAB_SIZE {
+            let token: Token = Token::from_index(index).expect("Index should be in vocabulary");
+            assert_eq!(token.index().expect("Token should have an index"), index);
+        }
+        assert_eq!(Token::from_index(VOCAB_SIZE), None);
+    }
+
+    #[test]
+    fn tokens_outside_vocabulary() {
+        for token in [
+            Token::TimeShift(0),
+            Token::TimeShift(MAX_SHIFT_STEPS + 1),
+            Token::NoteOn(128),
+            Token::NoteOff(200),
+            Token::Velocity(255),
+        ] {
+            assert!(matches!(token.index(), Err(MidiError::InvalidToken(t)) if t == token));
+        }
+
+        assert!(tokens_to_indices(&[Token::NoteOn(60), Token::TimeShift(0)]).is_err());
+    }
+
+    #[test]
+    fn timing_test_round_trip() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Timing_Test.mid").expect("Failed to parse MIDI file");
+        let tokens: Vec<Token> = events_to_tokens(&events);
+
+        assert_eq!(tokens_to_events(&tokens), events);
+    }
+
+    #[test]
+    fn double_note_round_trip() {
+        let events: Vec<NoteEvent> =
+            parse_midi("./tests/Double_Note_Test.mid").expect("Failed to parse MIDI file");
+        let indices: Vec<usize> =
+            tokens_to_indices(&events_to_tokens(&events)).expect("Failed to index tokens");
+
+        assert!(indices.iter().all(|i| *i < VOCAB_SIZE));
+        assert_eq!(tokens_to_events(&indices_to_tokens(&indices)), events);
+    }
+
+    #[test]
+    fn long_gaps_and_pedal() {
+        let events: Vec<NoteEvent> = vec![
+            NoteEvent::pedal(0.0, true),
+            NoteEvent::with_velocity(0.0, 60, true, 90),
+            NoteEvent::new(2500.0, 60, false),
+            NoteEvent::with_velocity(10.0, 62, true, 90),
+            NoteEvent::pedal(0.0, false),
+        ];
+        let tokens: Vec<Token> = events_to_tokens(&events);
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::PedalDown,
+                Token::Velocity(90),
+                Token::NoteOn(60),
+                Token::TimeShift(100),
+                Token::TimeShift(100),
+                Token::TimeShift(50),
+                Token::NoteOff(60),
+                Token::TimeShift(1),
+                Token::NoteOn(62),
+                Token::PedalUp,
+            ]
+        );
+        assert_eq!(tokens_to_events(&tokens), events);
+    }
+}
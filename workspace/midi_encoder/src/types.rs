@@ -5,16 +5,26 @@ use std::{mem::take, ops::Range};
 
 // internal
 pub use crate::constants::*;
+use crate::encoding::Encoding;
+use crate::error::MidiError;
+use crate::spelling::Pitch;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Note {
     key: u8,
     on: bool,
+    velocity: u8,
 }
 
 impl Note {
+    // releases carry no velocity, onsets default to a medium strike
     pub fn new(key: u8, on: bool) -> Note {
-        Note { key, on }
+        let velocity: u8 = if on { DEFAULT_VELOCITY } else { 0 };
+        Note { key, on, velocity }
+    }
+
+    pub fn with_velocity(key: u8, on: bool, velocity: u8) -> Note {
+        Note { key, on, velocity }
     }
 
     pub fn get_key(&self) -> u8 {
@@ -24,26 +34,88 @@ impl Note {
     pub fn is_note_on(&self) -> bool {
         self.on
     }
+
+    pub fn get_velocity(&self) -> u8 {
+        self.velocity
+    }
+
+    // the key's name as in C major, like C#4 for the key above middle C
+    pub fn name(&self) -> String {
+        Pitch::spell(self.key, &KeySignature::new(0, false)).name()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+pub enum EventType {
+    Note,
+    Pedal,
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Clone)]
+/*
+ events are written as deltas, but an event that knows its absolute time from the start
+ keeps it too, so long pieces don't pick up f32 rounding from summing deltas back up.
+ the absolute time isn't part of what makes two events equal.
+*/
+#[derive(Debug, Clone)]
 pub struct NoteEvent {
     time_delta_ms: f32,
+    time_ms: Option<f64>,
     note: Note,
+    event_type: EventType,
 }
 
 impl NoteEvent {
     pub fn new(time_delta_ms: f32, key: u8, note_on: bool) -> NoteEvent {
+        NoteEvent::from_note(time_delta_ms, Note::new(key, note_on))
+    }
+
+    pub fn with_velocity(time_delta_ms: f32, key: u8, note_on: bool, velocity: u8) -> NoteEvent {
+        NoteEvent::from_note(time_delta_ms, Note::with_velocity(key, note_on, velocity))
+    }
+
+    pub fn from_note(time_delta_ms: f32, note: Note) -> NoteEvent {
+        NoteEvent {
+            time_delta_ms,
+            time_ms: None,
+            note,
+            event_type: EventType::Note,
+        }
+    }
+
+    // pedal events reuse the note slot: key is the controller number, on is pedal down
+    pub fn pedal(time_delta_ms: f32, pedal_down: bool) -> NoteEvent {
         NoteEvent {
             time_delta_ms,
-            note: Note::new(key, note_on),
+            time_ms: None,
+            note: Note::new(SUSTAIN_CONTROLLER, pedal_down),
+            event_type: EventType::Pedal,
         }
     }
 
+    // the absolute time of the event, which has to agree with the deltas before it
+    pub fn at_time(self, time_ms: f64) -> NoteEvent {
+        NoteEvent {
+            time_ms: Some(time_ms),
+            ..self
+        }
+    }
+
+    pub fn is_pedal(&self) -> bool {
+        self.event_type == EventType::Pedal
+    }
+
+    pub fn get_event_type(&self) -> EventType {
+        self.event_type
+    }
+
     pub fn get_time_delta(&self) -> f32 {
         self.time_delta_ms
     }
 
+    pub fn get_time_ms(&self) -> Option<f64> {
+        self.time_ms
+    }
+
     pub fn get_note_ref(&self) -> &Note {
         &self.note
     }
@@ -51,30 +123,240 @@ impl NoteEvent {
     pub fn get_note(self) -> Note {
         self.note
     }
+
+    pub fn with_key(self, key: u8) -> NoteEvent {
+        NoteEvent {
+            note: Note { key, ..self.note },
+            ..self
+        }
+    }
+
+    // a new delta moves the event, so the absolute time it had no longer holds
+    pub fn with_time_delta(self, time_delta_ms: f32) -> NoteEvent {
+        NoteEvent {
+            time_delta_ms,
+            time_ms: None,
+            ..self
+        }
+    }
+
+    fn sort_key(&self) -> (f32, &Note, EventType) {
+        (self.time_delta_ms, &self.note, self.event_type)
+    }
+}
+
+impl PartialEq for NoteEvent {
+    fn eq(&self, other: &NoteEvent) -> bool {
+        self.sort_key() == other.sort_key()
+    }
+}
+
+impl PartialOrd for NoteEvent {
+    fn partial_cmp(&self, other: &NoteEvent) -> Option<std::cmp::Ordering> {
+        self.sort_key().partial_cmp(&other.sort_key())
+    }
+}
+
+// what to do with keys the 88 key encoding has no slot for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfRangePolicy {
+    #[default]
+    Drop,
+    Fold,
+    Error,
+}
+
+impl OutOfRangePolicy {
+    // Ok(None) means the note should be left out
+    pub fn apply(&self, key: u8) -> Result<Option<u8>, MidiError> {
+        if EncodingIndex::in_range(key) {
+            return Ok(Some(key));
+        }
+
+        match self {
+            OutOfRangePolicy::Drop => Ok(None),
+            OutOfRangePolicy::Fold => {
+                let mut folded: u8 = key;
+                while folded < LOWEST_KEY {
+                    folded += 12;
+                }
+                while folded > HIGHEST_KEY {
+                    folded -= 12;
+                }
+                Ok(Some(folded))
+            }
+            OutOfRangePolicy::Error => Err(MidiError::KeyOutOfRange(key)),
+        }
+    }
+}
+
+// how a chord's time delta is written into its vector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeScale {
+    // milliseconds as they are
+    Raw,
+    // ln(1 + ms) scaled to [0, 1] at max_ms, TimeScale::log checks the range
+    Log { max_ms: f32 },
+    // one-hot log spaced bins appended after the chord, the time slot stays empty
+    Bins(TimeBins),
+}
+
+// log spaced bins for time deltas, at least two so that both ends of the range have one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeBins {
+    count: usize,
+    max_ms: f32,
+}
+
+impl TimeBins {
+    pub fn new(count: usize, max_ms: f32) -> Result<TimeBins, MidiError> {
+        if count < 2 {
+            return Err(MidiError::InvalidBinCount(count));
+        }
+
+        Ok(TimeBins {
+            count,
+            max_ms: checked_max_ms(max_ms)?,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn max_ms(&self) -> f32 {
+        self.max_ms
+    }
+}
+
+impl Default for TimeScale {
+    fn default() -> TimeScale {
+        TimeScale::Log {
+            max_ms: MAX_TIME_DELTA_MS,
+        }
+    }
+}
+
+impl TimeScale {
+    // the log scale only inverts for a positive finite range
+    pub fn log(max_ms: f32) -> Result<TimeScale, MidiError> {
+        Ok(TimeScale::Log {
+            max_ms: checked_max_ms(max_ms)?,
+        })
+    }
+
+    pub fn vector_length(&self) -> usize {
+        match self {
+            TimeScale::Bins(bins) => ENCODING_LENGTH + bins.count,
+            _ => ENCODING_LENGTH,
+        }
+    }
+
+    pub fn write(&self, time_delta_ms: f32, v: &mut [f32]) {
+        match *self {
+            TimeScale::Raw => v[EncodingIndex::time_delta_index()] = time_delta_ms,
+            TimeScale::Log { max_ms } => {
+                v[EncodingIndex::time_delta_index()] = log_scale(time_delta_ms, max_ms)
+            }
+            TimeScale::Bins(TimeBins { count, max_ms }) => {
+                let bin: usize =
+                    (log_scale(time_delta_ms, max_ms) * (count - 1) as f32).round() as usize;
+                v[ENCODING_LENGTH + bin] = 1.0;
+            }
+        }
+    }
+
+    pub fn read(&self, v: &[f32]) -> f32 {
+        match *self {
+            TimeScale::Raw => v[EncodingIndex::time_delta_index()],
+            TimeScale::Log { max_ms } => {
+                inverse_log_scale(v[EncodingIndex::time_delta_index()], max_ms)
+            }
+            TimeScale::Bins(TimeBins { count, max_ms }) => {
+                let bin: usize = v[ENCODING_LENGTH..ENCODING_LENGTH + count]
+                    .iter()
+                    .enumerate()
+                    .fold((0, f32::MIN), |best, (i, value)| {
+                        if *value > best.1 {
+                            (i, *value)
+                        } else {
+                            best
+                        }
+                    })
+                    .0;
+                inverse_log_scale(bin as f32 / (count - 1) as f32, max_ms)
+            }
+        }
+    }
+}
+
+fn checked_max_ms(max_ms: f32) -> Result<f32, MidiError> {
+    if max_ms > 0.0 && max_ms.is_finite() {
+        Ok(max_ms)
+    } else {
+        Err(MidiError::InvalidTimeRange(max_ms))
+    }
+}
+
+fn log_scale(ms: f32, max_ms: f32) -> f32 {
+    ((1.0 + ms.max(0.0)).ln() / (1.0 + max_ms).ln()).min(1.0)
+}
+
+fn inverse_log_scale(value: f32, max_ms: f32) -> f32 {
+    ((1.0 + max_ms).ln() * value.clamp(0.0, 1.0)).exp() - 1.0
 }
 
 //TODO: change this implementation to be event based rather than time based,
 #[derive(Debug, Clone)]
 pub struct MIDIEncoding {
     encoding: Vec<Chord>,
+    dropped_notes: usize,
+    folded_notes: usize,
 }
 
 impl MIDIEncoding {
     pub fn new(encoding: Vec<Chord>) -> MIDIEncoding {
-        MIDIEncoding { encoding }
+        MIDIEncoding {
+            encoding,
+            dropped_notes: 0,
+            folded_notes: 0,
+        }
     }
 
-    pub fn from_vector(output_vecs: Vec<Vec<f32>>, cutoff: f32) -> MIDIEncoding {
+    pub fn with_counts(
+        encoding: Vec<Chord>,
+        dropped_notes: usize,
+        folded_notes: usize,
+    ) -> MIDIEncoding {
+        MIDIEncoding {
+            encoding,
+            dropped_notes,
+            folded_notes,
+        }
+    }
+
+    pub fn from_vector(
+        output_vecs: Vec<Vec<f32>>,
+        cutoff: f32,
+        layout: &dyn Encoding,
+    ) -> MIDIEncoding {
         let mut chords: Vec<Chord> = Vec::new();
 
         for vec in output_vecs.iter() {
-            let chord = Chord::from_vec(vec, cutoff);
+            let chord = layout.decode_vector(vec, cutoff);
             chords.push(chord);
         }
 
         MIDIEncoding::new(chords)
     }
 
+    pub fn to_vectors(&self, layout: &dyn Encoding) -> Vec<Vec<f32>> {
+        self.encoding
+            .iter()
+            .map(|chord| layout.encode_chord(chord))
+            .collect()
+    }
+
     pub fn get_encoding(&self) -> &Vec<Chord> {
         &self.encoding
     }
@@ -83,11 +365,37 @@ impl MIDIEncoding {
         self.encoding.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.encoding.is_empty()
+    }
+
+    // notes left out because they were outside the piano range
+    pub fn get_dropped_notes(&self) -> usize {
+        self.dropped_notes
+    }
+
+    pub fn get_folded_notes(&self) -> usize {
+        self.folded_notes
+    }
+
     pub fn print(&self) -> String {
         let mut result = String::new();
         for (i, chord) in self.encoding.iter().enumerate() {
             if !chord.is_none() {
-                result.push_str(&format!("Event: {}, Chord: {:?}\n", i, chord));
+                // presses marked with + and releases with -
+                let notes: Vec<String> = chord
+                    .get_notes_ref()
+                    .iter()
+                    .map(|n| format!("{}{}", if n.is_note_on() { "+" } else { "-" }, n.name()))
+                    .collect();
+                let pedal: &str = if chord.is_pedal_down() { ", pedal" } else { "" };
+                result.push_str(&format!(
+                    "Event: {}, Delta: {}, Notes: {}{}\n",
+                    i,
+                    chord.get_time_delta(),
+                    notes.join(" "),
+                    pedal
+                ));
             }
         }
 
@@ -95,14 +403,53 @@ impl MIDIEncoding {
             return "Empty Encoding".to_string();
         }
 
+        if self.dropped_notes > 0 {
+            result.push_str(&format!(
+                "Dropped {} out of range notes\n",
+                self.dropped_notes
+            ));
+        }
+
         result
     }
 }
 
+// the events decoded from an encoding, with the notes it never released
+#[derive(Debug, Clone)]
+pub struct DecodedEvents {
+    events: Vec<NoteEvent>,
+    stuck_notes: usize,
+}
+
+impl DecodedEvents {
+    pub fn new(events: Vec<NoteEvent>, stuck_notes: usize) -> DecodedEvents {
+        DecodedEvents {
+            events,
+            stuck_notes,
+        }
+    }
+
+    pub fn get_events(&self) -> &Vec<NoteEvent> {
+        &self.events
+    }
+
+    pub fn into_events(self) -> Vec<NoteEvent> {
+        self.events
+    }
+
+    // presses still sounding at the end, released after the last chord
+    pub fn get_stuck_notes(&self) -> usize {
+        self.stuck_notes
+    }
+}
+
 pub struct EncodingData {
     events: Vec<NoteEvent>,
     has_limit: bool,
     time_limit_ms: usize,
+    sustain: bool,
+    quantize_ms: f32,
+    key_policy: OutOfRangePolicy,
 }
 
 impl EncodingData {
@@ -111,6 +458,9 @@ impl EncodingData {
             events,
             has_limit: false,
             time_limit_ms: 0,
+            sustain: false,
+            quantize_ms: 0.0,
+            key_policy: OutOfRangePolicy::default(),
         }
     }
 
@@ -119,9 +469,38 @@ impl EncodingData {
             events,
             has_limit: true,
             time_limit_ms: limit_ms,
+            sustain: false,
+            quantize_ms: 0.0,
+            key_policy: OutOfRangePolicy::default(),
         }
     }
 
+    // extend note releases until the sustain pedal is lifted before encoding
+    pub fn set_sustain(&mut self, sustain: bool) {
+        self.sustain = sustain;
+    }
+
+    pub fn applies_sustain(&self) -> bool {
+        self.sustain
+    }
+
+    // snap event times to a grid of step_ms; 0 keeps the parsed timings untouched
+    pub fn set_quantization(&mut self, step_ms: f32) {
+        self.quantize_ms = step_ms;
+    }
+
+    pub fn quantization_ms(&self) -> f32 {
+        self.quantize_ms
+    }
+
+    pub fn set_key_policy(&mut self, policy: OutOfRangePolicy) {
+        self.key_policy = policy;
+    }
+
+    pub fn key_policy(&self) -> OutOfRangePolicy {
+        self.key_policy
+    }
+
     pub fn get_events(&mut self) -> Vec<NoteEvent> {
         take(&mut self.events)
     }
@@ -139,6 +518,180 @@ impl EncodingData {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    numerator: u8,
+    denominator: u8,
+}
+
+impl TimeSignature {
+    // the denominator has to be a power of two to be written to MIDI
+    pub fn new(numerator: u8, denominator: u8) -> Result<TimeSignature, MidiError> {
+        if numerator == 0 || !denominator.is_power_of_two() {
+            return Err(MidiError::InvalidTimeSignature(numerator, denominator));
+        }
+
+        Ok(TimeSignature {
+            numerator,
+            denominator,
+        })
+    }
+
+    pub fn get_numerator(&self) -> u8 {
+        self.numerator
+    }
+
+    pub fn get_denominator(&self) -> u8 {
+        self.denominator
+    }
+
+    // one beat is a 1/denominator note, with TICKS_PER_BEAT to the quarter
+    pub fn beat_ticks(&self) -> u64 {
+        TICKS_PER_BEAT * 4 / self.denominator as u64
+    }
+
+    pub fn measure_ticks(&self) -> u64 {
+        self.numerator as u64 * self.beat_ticks()
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> TimeSignature {
+        TimeSignature {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySignature {
+    fifths: i8,
+    minor: bool,
+}
+
+impl KeySignature {
+    // fifths counts sharps when positive and flats when negative
+    pub fn new(fifths: i8, minor: bool) -> KeySignature {
+        KeySignature { fifths, minor }
+    }
+
+    pub fn get_fifths(&self) -> i8 {
+        self.fifths
+    }
+
+    pub fn is_minor(&self) -> bool {
+        self.minor
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandSplit {
+    None,
+    SplitPoint(u8),
+    Heuristic,
+}
+
+pub struct ExportOptions {
+    multi_track: bool,
+    tempo: u32,
+    title: Option<String>,
+    time_signature: Option<TimeSignature>,
+    key_signature: Option<KeySignature>,
+    program: Option<u8>,
+    hand_split: HandSplit,
+}
+
+impl ExportOptions {
+    // a single track with only the tempo, as write_midi has always written
+    pub fn new() -> ExportOptions {
+        ExportOptions {
+            multi_track: false,
+            tempo: DEFAULT_TEMPO,
+            title: None,
+            time_signature: None,
+            key_signature: None,
+            program: None,
+            hand_split: HandSplit::None,
+        }
+    }
+
+    // format 1 with a meta track, 4/4 in C major and an acoustic grand piano
+    pub fn multi_track() -> ExportOptions {
+        ExportOptions {
+            multi_track: true,
+            tempo: DEFAULT_TEMPO,
+            title: None,
+            time_signature: Some(TimeSignature::default()),
+            key_signature: Some(KeySignature::new(0, false)),
+            program: Some(ACOUSTIC_GRAND),
+            hand_split: HandSplit::None,
+        }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = Some(title.to_string());
+    }
+
+    pub fn set_tempo(&mut self, tempo: u32) {
+        self.tempo = tempo;
+    }
+
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = Some(time_signature);
+    }
+
+    pub fn set_key_signature(&mut self, key_signature: KeySignature) {
+        self.key_signature = Some(key_signature);
+    }
+
+    pub fn set_program(&mut self, program: u8) {
+        self.program = Some(program);
+    }
+
+    // splitting into hands needs a track per hand, so it implies multiple tracks
+    pub fn set_hand_split(&mut self, hand_split: HandSplit) {
+        self.hand_split = hand_split;
+        if hand_split != HandSplit::None {
+            self.multi_track = true;
+        }
+    }
+
+    pub fn is_multi_track(&self) -> bool {
+        self.multi_track
+    }
+
+    pub fn tempo(&self) -> u32 {
+        self.tempo
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn time_signature(&self) -> Option<TimeSignature> {
+        self.time_signature
+    }
+
+    pub fn key_signature(&self) -> Option<KeySignature> {
+        self.key_signature
+    }
+
+    pub fn program(&self) -> Option<u8> {
+        self.program
+    }
+
+    pub fn hand_split(&self) -> HandSplit {
+        self.hand_split
+    }
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions::new()
+    }
+}
+
 pub struct EncodingIndex;
 
 impl EncodingIndex {
@@ -146,16 +699,20 @@ impl EncodingIndex {
      88 keys = [21, 108]
      indices 2x = on/off, 2x + 1 for note ID
     */
+    pub fn in_range(key: u8) -> bool {
+        (LOWEST_KEY..=HIGHEST_KEY).contains(&key)
+    }
+
     pub fn key_index(key: u8) -> usize {
-        (2 * (key - 21) + 1) as usize
+        (2 * (key - LOWEST_KEY) + 1) as usize
     }
 
     pub fn key_of(index: usize) -> u8 {
-        ((index - 1) / 2 + 21) as u8
+        ((index - 1) / 2) as u8 + LOWEST_KEY
     }
 
     pub fn on_off_index(key: u8) -> usize {
-        (2 * (key - 21)) as usize
+        (2 * (key - LOWEST_KEY)) as usize
     }
 
     pub fn key_range() -> Range<usize> {
@@ -173,6 +730,10 @@ impl EncodingIndex {
     pub fn end_index() -> usize {
         KEY_RANGE + END_INDEX
     }
+
+    pub fn pedal_index() -> usize {
+        KEY_RANGE + PEDAL_INDEX
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -181,6 +742,7 @@ pub struct Chord {
     is_none: bool,
     time_delta: f32,
     chord_type: ChordType,
+    pedal: bool,
 }
 
 impl Chord {
@@ -192,6 +754,7 @@ impl Chord {
             is_none: false,
             time_delta,
             chord_type: ChordType::Chord,
+            pedal: false,
         }
     }
 
@@ -201,6 +764,7 @@ impl Chord {
             is_none: true,
             time_delta: 0.0,
             chord_type: ChordType::None,
+            pedal: false,
         }
     }
 
@@ -210,6 +774,7 @@ impl Chord {
             is_none: true,
             time_delta: 0.0,
             chord_type: ChordType::Start,
+            pedal: false,
         }
     }
 
@@ -219,16 +784,41 @@ impl Chord {
             is_none: true,
             time_delta: 0.0,
             chord_type: ChordType::End,
+            pedal: false,
         }
     }
 
-    pub fn from_vec(v: &Vec<f32>, cutoff: f32) -> Chord {
-        if v.len() != ENCODING_LENGTH {
+    // a fixed length slice of time rather than a single event, empty frames still take up time
+    pub fn frame(notes: Vec<Note>, frame_ms: f32) -> Chord {
+        Chord {
+            is_none: notes.is_empty(),
+            notes,
+            time_delta: frame_ms,
+            chord_type: ChordType::Chord,
+            pedal: false,
+        }
+    }
+
+    pub fn from_vec(v: &[f32], cutoff: f32) -> Chord {
+        Chord::from_scaled_vec(v, cutoff, TimeScale::default())
+    }
+
+    pub fn from_scaled_vec(v: &[f32], cutoff: f32, time_scale: TimeScale) -> Chord {
+        if v.len() != time_scale.vector_length() {
             panic!("Not a valid note encoding");
         }
 
+        // sequence markers carry no notes of their own
+        if v[EncodingIndex::start_index()] >= cutoff {
+            return Chord::start();
+        }
+        if v[EncodingIndex::end_index()] >= cutoff {
+            return Chord::end();
+        }
+
         let mut notes: Vec<Note> = Vec::new();
-        let time_delta: f32 = v[EncodingIndex::time_delta_index()];
+        let time_delta: f32 = time_scale.read(v);
+        let pedal: bool = v[EncodingIndex::pedal_index()] >= cutoff;
 
         for i in EncodingIndex::key_range() {
             if v[i].is_nan() {
@@ -253,6 +843,7 @@ impl Chord {
             is_none: false,
             time_delta,
             chord_type: ChordType::Chord,
+            pedal,
         }
     }
 
@@ -266,16 +857,45 @@ impl Chord {
         self.is_none
     }
 
-    fn add_note(&mut self, note: Note, time_delta: f32) {
+    pub fn is_start(&self) -> bool {
+        matches!(self.chord_type, ChordType::Start)
+    }
+
+    pub fn is_end(&self) -> bool {
+        matches!(self.chord_type, ChordType::End)
+    }
+
+    pub fn is_pedal_down(&self) -> bool {
+        self.pedal
+    }
+
+    pub fn get_time_delta(&self) -> f32 {
+        self.time_delta
+    }
+
+    pub fn get_notes_ref(&self) -> &Vec<Note> {
+        &self.notes
+    }
+
+    fn activate(&mut self, time_delta: f32) {
         if self.is_none {
             self.time_delta = time_delta;
             self.is_none = false;
             self.chord_type = ChordType::Chord;
         }
+    }
 
+    fn add_note(&mut self, note: Note, time_delta: f32) {
+        self.activate(time_delta);
         self.notes.push(note);
     }
 
+    // pedal state carries over resets, so only changes need to be recorded
+    pub fn set_pedal(&mut self, pedal_down: bool, time_delta: f32) {
+        self.activate(time_delta);
+        self.pedal = pedal_down;
+    }
+
     pub fn try_add(&mut self, note: Note, time_delta: f32) -> AddNoteResult {
         let mut same_event: bool = false;
 
@@ -286,45 +906,95 @@ impl Chord {
         }
 
         if same_event {
-            return AddNoteResult::Duplicate;
+            AddNoteResult::Duplicate
         } else {
             self.add_note(note, time_delta);
-            return AddNoteResult::Ok;
+            AddNoteResult::Ok
         }
     }
 
-    pub fn get_events(&self) -> Vec<NoteEvent> {
+    /*
+     sounding holds how many times each key is held going into the chord and is updated.
+     releases of held keys come before presses so a re-strike never cancels itself,
+     and releases of keys pressed in this chord come last as zero length notes.
+    */
+    pub fn get_events(&self, pedal_before: bool, sounding: &mut [u32]) -> Vec<NoteEvent> {
         let mut events: Vec<NoteEvent> = Vec::new();
-        let mut added_delta: bool = false;
+        let mut zero_length: Vec<&Note> = Vec::new();
 
-        for note in self.notes.iter() {
-            let event: NoteEvent;
-            if !added_delta {
-                event = NoteEvent::new(self.time_delta, note.get_key(), note.is_note_on());
-                added_delta = true;
+        if self.pedal != pedal_before {
+            events.push(NoteEvent::pedal(0.0, self.pedal));
+        }
+
+        for note in self.notes.iter().filter(|n| !n.is_note_on()) {
+            let key: usize = note.get_key() as usize;
+            if sounding[key] > 0 {
+                sounding[key] -= 1;
+                events.push(NoteEvent::from_note(0.0, note.clone()));
             } else {
-                event = NoteEvent::new(0.0, note.get_key(), note.is_note_on());
+                zero_length.push(note);
             }
-            events.push(event);
+        }
+
+        for note in self.notes.iter().filter(|n| n.is_note_on()) {
+            sounding[note.get_key() as usize] += 1;
+            events.push(NoteEvent::from_note(0.0, note.clone()));
+        }
+
+        for note in zero_length {
+            let key: usize = note.get_key() as usize;
+            sounding[key] = sounding[key].saturating_sub(1);
+            events.push(NoteEvent::from_note(0.0, note.clone()));
+        }
+
+        if let Some(first) = events.first_mut() {
+            first.time_delta_ms = self.time_delta;
         }
 
         events
     }
 
+    // out of range notes are dropped, use get_encoding_with for another policy
     pub fn get_encoding(&self) -> Vec<f32> {
-        let mut note_encoding: Vec<f32> = vec![0.0; ENCODING_LENGTH];
+        self.get_encoding_with(OutOfRangePolicy::Drop)
+            .expect("Dropping out of range notes cannot fail")
+    }
+
+    pub fn get_encoding_with(&self, policy: OutOfRangePolicy) -> Result<Vec<f32>, MidiError> {
+        self.get_scaled_encoding(policy, TimeScale::default())
+    }
+
+    pub fn get_scaled_encoding(
+        &self,
+        policy: OutOfRangePolicy,
+        time_scale: TimeScale,
+    ) -> Result<Vec<f32>, MidiError> {
+        let mut note_encoding: Vec<f32> = vec![0.0; time_scale.vector_length()];
 
         for note in self.notes.iter() {
-            let key: u8 = note.get_key();
+            let key: u8 = match policy.apply(note.get_key())? {
+                Some(key) => key,
+                None => continue,
+            };
             note_encoding[EncodingIndex::key_index(key)] = 1.0;
             if note.is_note_on() {
                 note_encoding[EncodingIndex::on_off_index(key)] = 1.0;
             }
         }
 
-        note_encoding[EncodingIndex::time_delta_index()] = self.time_delta;
+        time_scale.write(self.time_delta, &mut note_encoding);
+
+        match self.chord_type {
+            ChordType::Start => note_encoding[EncodingIndex::start_index()] = 1.0,
+            ChordType::End => note_encoding[EncodingIndex::end_index()] = 1.0,
+            _ => {}
+        }
 
-        note_encoding
+        if self.pedal {
+            note_encoding[EncodingIndex::pedal_index()] = 1.0;
+        }
+
+        Ok(note_encoding)
     }
 
     pub fn get_notes(&mut self) -> Vec<Note> {
@@ -344,3 +1014,109 @@ pub enum AddNoteResult {
     Ok,
     Duplicate,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chord_encoding_skips_out_of_range_keys() {
+        let mut chord: Chord = Chord::new(NoteEvent::new(0.0, 60, true), 10.0);
+        chord.try_add(Note::new(12, true), 10.0);
+
+        let encoding: Vec<f32> = chord.get_encoding();
+        let active: usize = encoding[EncodingIndex::key_range()]
+            .iter()
+            .filter(|v| **v > 0.0)
+            .count();
+        assert_eq!(active, 2);
+
+        let folded: Vec<f32> = chord
+            .get_encoding_with(OutOfRangePolicy::Fold)
+            .expect("Folding should not fail");
+        assert_eq!(folded[EncodingIndex::key_index(24)], 1.0);
+
+        assert!(chord.get_encoding_with(OutOfRangePolicy::Error).is_err());
+    }
+
+    #[test]
+    fn bounded_time_delta() {
+        let scales: Vec<TimeScale> = vec![
+            TimeScale::log(10_000.0).expect("Range should be valid"),
+            TimeScale::Bins(TimeBins::new(64, 10_000.0).expect("Bin count should be valid")),
+        ];
+
+        for scale in scales {
+            for time_delta in [0.0, 12.0, 250.0, 1000.0, 9000.0] {
+                let chord: Chord = Chord::new(NoteEvent::new(0.0, 60, true), time_delta);
+                let encoding: Vec<f32> = chord
+                    .get_scaled_encoding(OutOfRangePolicy::Drop, scale)
+                    .expect("Dropping should not fail");
+                assert!(encoding.iter().all(|v| (0.0..=1.0).contains(v)));
+
+                let decoded: f32 = Chord::from_scaled_vec(&encoding, 0.5, scale).get_time_delta();
+                // bins are about 15% wide at this resolution
+                assert!((decoded - time_delta).abs() <= 0.08 * time_delta + 0.01);
+            }
+        }
+
+        let raw: Vec<f32> = Chord::new(NoteEvent::new(0.0, 60, true), 20_000.0)
+            .get_scaled_encoding(OutOfRangePolicy::Drop, TimeScale::default())
+            .expect("Dropping should not fail");
+        assert_eq!(raw[EncodingIndex::time_delta_index()], 1.0);
+    }
+
+    #[test]
+    fn time_signature_validation() {
+        assert!(matches!(
+            TimeSignature::new(6, 6),
+            Err(MidiError::InvalidTimeSignature(6, 6))
+        ));
+        assert!(TimeSignature::new(0, 4).is_err());
+        assert!(TimeSignature::new(3, 0).is_err());
+
+        let compound: TimeSignature = TimeSignature::new(6, 8).expect("6/8 is a valid meter");
+        assert_eq!(compound.get_denominator(), 8);
+        assert_eq!(compound.beat_ticks(), TICKS_PER_BEAT / 2);
+        assert_eq!(compound.measure_ticks(), TICKS_PER_BEAT * 3);
+        assert_eq!(TimeSignature::default(), TimeSignature::new(4, 4).unwrap());
+    }
+
+    #[test]
+    fn time_scale_validation() {
+        assert!(matches!(
+            TimeBins::new(0, 1000.0),
+            Err(MidiError::InvalidBinCount(0))
+        ));
+        assert!(TimeBins::new(1, 1000.0).is_err());
+        for max_ms in [0.0, -5.0, f32::NAN, f32::INFINITY] {
+            assert!(TimeBins::new(8, max_ms).is_err());
+            assert!(TimeScale::log(max_ms).is_err());
+        }
+        assert!(matches!(
+            TimeScale::log(-5.0),
+            Err(MidiError::InvalidTimeRange(_))
+        ));
+
+        let bins: TimeBins = TimeBins::new(2, 1000.0).expect("Two bins should be valid");
+        let chord: Chord = Chord::new(NoteEvent::new(0.0, 60, true), 1000.0);
+        let encoding: Vec<f32> = chord
+            .get_scaled_encoding(OutOfRangePolicy::Drop, TimeScale::Bins(bins))
+            .expect("Dropping should not fail");
+        assert_eq!(encoding[ENCODING_LENGTH + 1], 1.0);
+    }
+
+    #[test]
+    fn note_names_in_print() {
+        assert_eq!(Note::new(60, true).name(), "C4");
+        assert_eq!(Note::new(61, true).name(), "C#4");
+        assert_eq!(Note::new(21, true).name(), "A0");
+
+        let mut chord: Chord = Chord::new(NoteEvent::new(0.0, 61, true), 0.5);
+        chord.try_add(Note::new(64, false), 0.5);
+        let midi: MIDIEncoding = MIDIEncoding::new(vec![Chord::start(), chord]);
+        assert!(midi
+            .print()
+            .contains("Event: 1, Delta: 0.5, Notes: +C#4 -E4\n"));
+    }
+}
@@ -139,9 +139,9 @@ impl ActivationFunction for ReLU {
 
     fn d_of(&self, x: f32) -> f32 {
         if x > 0.0 {
-            return 1.0;
+            1.0
         } else {
-            return 0.0;
+            0.0
         }
     }
 
@@ -3,13 +3,13 @@
 // external
 
 // internal
-use ndarray::{Array1, Array2, ArrayView2, Axis};
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, Axis};
 use super::activation::Activation;
 use super::types::Weight;
 
 
 
-pub fn compute_output_grad(output: &Vec<f32>, answer: &Vec<f32>, raw: &Array2<f32>, end_actf: &Activation) -> Array1<f32> {
+pub fn compute_output_grad(output: &[f32], answer: &[f32], raw: &Array2<f32>, end_actf: &Activation) -> Array1<f32> {
     let mut loss_vec: Vec<f32> = Vec::new();
     for (i, out) in output.iter().enumerate() {
         let expected: &f32 = answer.get(i).expect("Failed to get vector value");
@@ -48,8 +48,7 @@ pub fn compute_recurrence_grad(
     prev_grad: &Array1<f32>, 
     prev_act: Option<&Array2<f32>>
 ) -> Array2<f32> {
-    if prev_act.is_some() {
-        let activation: &Array2<f32> = prev_act.expect("Failed to get previous activations");
+    if let Some(activation) = prev_act {
         let grad_matrix: ArrayView2<f32> = prev_grad.view().insert_axis(Axis(0));
         let grads: ArrayView2<f32> = grad_matrix.broadcast(dim)
             .expect("Failed to broadcast gradient vector");
@@ -59,9 +58,9 @@ pub fn compute_recurrence_grad(
 
         recurrence_update = recurrence_update * grads;
 
-        return recurrence_update;
+        recurrence_update
     } else {
-        return Array2::zeros(dim);
+        Array2::zeros(dim)
     }
 }
 
@@ -83,6 +82,65 @@ pub fn compute_backpropogated_grad(
     result * input_vec
 }
 
+/*
+ batched versions of the above, every row of a matrix is one sample of the batch.
+ the gradients are summed over the rows, so averaging is left to whoever scales the output gradient.
+*/
+pub fn to_batch_matrix(rows: &[&[f32]], width: usize) -> Array2<f32> {
+    let mut matrix: Array2<f32> = Array2::zeros((rows.len(), width));
+
+    for (i, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            panic!("Invalid input size of {}, expected {}", row.len(), width);
+        }
+        matrix.row_mut(i).assign(&ArrayView1::from(*row));
+    }
+
+    matrix
+}
+
+pub fn compute_batch_output_grad(output: &Array2<f32>, answer: &Array2<f32>, raw: &Array2<f32>, end_actf: &Activation) -> Array2<f32> {
+    if output.dim() != answer.dim() {
+        panic!("Mismatched output/answer dimensions!");
+    }
+
+    let residual: Array2<f32> = output - answer;
+    let scale: Array2<f32> = raw.mapv(|x| end_actf.deriv_of(x));
+
+    residual * scale
+}
+
+pub fn compute_batch_hidden_grad(prev_grad: &Array2<f32>, input_act: &Array2<f32>) -> Array2<f32> {
+    input_act.t().dot(prev_grad)
+}
+
+pub fn compute_batch_bias_grad(prev_grad: &Array2<f32>) -> Array1<f32> {
+    prev_grad.sum_axis(Axis(0))
+}
+
+pub fn compute_batch_recurrence_grad(
+    dim: (usize, usize),
+    prev_grad: &Array2<f32>,
+    prev_act: Option<&Array2<f32>>
+) -> Array2<f32> {
+    match prev_act {
+        Some(activation) => activation.t().dot(prev_grad),
+        None => Array2::zeros(dim)
+    }
+}
+
+pub fn compute_batch_backpropogated_grad(
+    hidden_layer_weights: &Weight,
+    hidden_actf: &Activation,
+    prev_grad: &Array2<f32>,
+    raw_input: &Array2<f32>,
+) -> Array2<f32> {
+    let scale: Array2<f32> = raw_input.mapv(|x| hidden_actf.deriv_of(x));
+    let weights: ArrayView2<f32> = hidden_layer_weights.get_weight_matrix();
+
+    prev_grad.dot(&weights.t()) * scale
+}
+
 #[cfg(test)]
 mod tests{
     use super::*;
@@ -194,4 +252,31 @@ mod tests{
         println!("{:?}", grad);
         assert_eq!(grad, ans);
     }
+
+    #[test]
+    fn batch_gradients_match_single() {
+        let weights: Weight = Weight::random((3, 2), -1.0, 1.0);
+        let actf: Activation = Activation::relu();
+
+        let first: Vec<f32> = vec![1.0, -2.0, 0.5];
+        let second: Vec<f32> = vec![-1.0, 3.0, 2.0];
+        let inputs: Array2<f32> = to_batch_matrix(&[&first, &second], 3);
+        let grads: Array2<f32> = to_batch_matrix(&[&[1.0, -1.0], &[0.5, 2.0]], 2);
+
+        let hidden: Array2<f32> = compute_batch_hidden_grad(&grads, &inputs);
+        let backprop: Array2<f32> = compute_batch_backpropogated_grad(&weights, &actf, &grads, &inputs);
+
+        let mut summed: Array2<f32> = Array2::zeros((3, 2));
+        for (i, input) in [&first, &second].iter().enumerate() {
+            let row: Array2<f32> = Array1::from_vec(input.to_vec()).insert_axis(Axis(0));
+            let grad: Array1<f32> = grads.row(i).to_owned();
+            summed += &compute_hidden_grad((3, 2), &grad, &row);
+
+            let single: Array1<f32> = compute_backpropogated_grad(&weights, &actf, grad, &row);
+            assert!(single.iter().zip(backprop.row(i).iter()).all(|(a, b)| (a - b).abs() < 1e-5));
+        }
+
+        assert!(summed.iter().zip(hidden.iter()).all(|(a, b)| (a - b).abs() < 1e-5));
+        assert_eq!(compute_batch_bias_grad(&grads), Array1::from_vec(vec![1.5, 1.0]));
+    }
 }
\ No newline at end of file
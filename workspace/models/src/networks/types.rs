@@ -1,4 +1,5 @@
 // builtin 
+use std::fmt::{self, Display, Formatter};
 
 // external
 use ndarray::{Array, Array1, Array2, ArrayView2, Axis};
@@ -43,7 +44,7 @@ impl Bias {
         Bias { array }
     }
 
-    pub fn get_row_vector(&self) -> ArrayView2<f32> {
+    pub fn get_row_vector(&self) -> ArrayView2<'_, f32> {
         self.array.view().insert_axis(Axis(0))
     }
     
@@ -57,8 +58,8 @@ impl Bias {
     }
 }
 
-impl ToString for Bias {
-    fn to_string(&self) -> String {
+impl Display for Bias {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let dim = self.array.dim();
 
         let mut formatted: String = format!("{}#", dim);
@@ -70,7 +71,7 @@ impl ToString for Bias {
 
         formatted.push_str(val_string);
 
-        formatted
+        write!(f, "{}", formatted)
     }
 }
 
@@ -111,7 +112,7 @@ impl Weight {
         Weight { array }
     }
 
-    pub fn get_weight_matrix(&self) -> ArrayView2<f32> {
+    pub fn get_weight_matrix(&self) -> ArrayView2<'_, f32> {
         self.array.view()
     }
 
@@ -125,8 +126,8 @@ impl Weight {
     }
 }
 
-impl ToString for Weight {
-    fn to_string(&self) -> String {
+impl Display for Weight {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let (dim1, dim2) = self.array.dim();
 
         let mut formatted: String = format!("{},{}#", dim1, dim2);
@@ -138,7 +139,7 @@ impl ToString for Weight {
 
         formatted.push_str(val_string);
 
-        formatted
+        write!(f, "{}", formatted)
     }
 }
 
@@ -154,7 +155,7 @@ impl Update {
     pub fn new(
         input_size: usize, 
         output_size: usize, 
-        units_by_layer: &Vec<usize>, 
+        units_by_layer: &[usize], 
         max_batch_size: usize
     ) -> Update {
         let layers: usize = units_by_layer.len();
@@ -261,7 +262,7 @@ impl RecurrentUpdate {
     pub fn new(
         input_size: usize, 
         output_size: usize, 
-        units_by_layer: &Vec<usize>, 
+        units_by_layer: &[usize], 
         max_batch_size: usize
     ) -> RecurrentUpdate {
         let layers: usize = units_by_layer.len();
@@ -188,6 +188,39 @@ impl NN {
         (output, activations, raw_nodes)
     }
 
+    // every row is one sample, so the whole batch goes through each layer in one product
+    fn feedforward_batch(
+        &self,
+        inputs: Array2<f32>,
+    ) -> (Array2<f32>, Vec<Array2<f32>>, Vec<Array2<f32>>) {
+        let mut arr: Array2<f32> = inputs;
+        let mut activations: Vec<Array2<f32>> = Vec::with_capacity(self.layers + 1);
+        let mut raw_nodes: Vec<Array2<f32>> = Vec::with_capacity(self.layers + 2);
+
+        activations.push(arr.clone());
+        raw_nodes.push(arr.clone());
+
+        for i in 0..=self.layers {
+            let hidden_weight: &Weight = self.hidden_weights.get(i).expect("Failed to get weight");
+            arr = arr.dot(&hidden_weight.get_weight_matrix());
+
+            let bias: &Bias = self.biases.get(i).expect("Failed to get bias");
+            arr = arr + bias.get_row_vector();
+
+            raw_nodes.push(arr.clone());
+
+            if i < self.layers {
+                arr.mapv_inplace(|x| self.hidden_activation.of(x));
+                activations.push(arr.clone());
+            }
+        }
+
+        arr.mapv_inplace(|x| self.end_activation.of(x));
+
+        // same layout as feedforward, with a row per sample
+        (arr, activations, raw_nodes)
+    }
+
     fn ff_single_layer(&mut self, v: Vec<f32>) -> Vec<f32> {
         if v.len() != self.input_size {
             panic!("Invalid input size!");
@@ -197,11 +230,11 @@ impl NN {
 
         let hidden_weight: &Weight = self
             .hidden_weights
-            .get(0)
+            .first()
             .expect("Failed to get hidden weight");
         arr = arr.dot(&hidden_weight.get_weight_matrix());
 
-        let bias: &Bias = self.biases.get(0).expect("Failed to get bias");
+        let bias: &Bias = self.biases.first().expect("Failed to get bias");
         arr = arr + bias.get_row_vector();
 
         arr.mapv_inplace(|x| self.hidden_activation.of(x));
@@ -210,7 +243,7 @@ impl NN {
     }
 
     fn ff_from_first(&mut self, v: Vec<f32>) -> Vec<f32> {
-        let expected_input_size: usize = *(self.units_by_layer.get(0).unwrap_or(&0));
+        let expected_input_size: usize = *(self.units_by_layer.first().unwrap_or(&0));
         if v.len() != expected_input_size {
             panic!(
                 "Invalid input size! Expected {} but got {}",
@@ -238,7 +271,7 @@ impl NN {
         arr.remove_axis(Axis(0)).to_vec()
     }
 
-    pub fn predict_and_update(&mut self, seq: Vec<Vec<f32>>, ans: &Vec<Vec<f32>>, batch: usize) {
+    pub fn predict_and_update(&mut self, seq: Vec<Vec<f32>>, ans: &[Vec<f32>], batch: usize) {
         let mut update: Update = Update::new(
             self.input_size,
             self.output_size,
@@ -257,13 +290,102 @@ impl NN {
         }
     }
 
+    pub fn predict_batch(&self, seqs: &[Vec<Vec<f32>>]) -> Vec<Vec<Vec<f32>>> {
+        let inputs: Array2<f32> = stack_sequences(seqs, self.input_size);
+        let (output, _activations, _raw) = self.feedforward_batch(inputs);
+
+        let mut rows = output.rows().into_iter().map(|row| row.to_vec());
+        seqs.iter()
+            .map(|seq| rows.by_ref().take(seq.len()).collect())
+            .collect()
+    }
+
+    /*
+     one update from a batch of sequences, with the gradients averaged over the sequences.
+     the samples of a sequence don't depend on each other here, so all of them are stacked
+     into a single matrix. returns the batch loss, the squared error summed over each
+     sequence and averaged over the batch.
+    */
+    pub fn predict_and_update_batch(
+        &mut self,
+        seqs: &[Vec<Vec<f32>>],
+        ans: &[Vec<Vec<f32>>],
+    ) -> f32 {
+        if seqs.len() != ans.len() {
+            panic!(
+                "Batch size mismatch: {} sequences but {} answers",
+                seqs.len(),
+                ans.len()
+            );
+        } else if seqs.iter().zip(ans.iter()).any(|(seq, answer)| seq.len() != answer.len()) {
+            panic!("Sequence length mismatch between inputs and answers");
+        } else if seqs.is_empty() {
+            return 0.0;
+        }
+
+        let inputs: Array2<f32> = stack_sequences(seqs, self.input_size);
+        let answers: Array2<f32> = stack_sequences(ans, self.output_size);
+        let (output, activations, raw) = self.feedforward_batch(inputs);
+
+        let loss: f32 = (&output - &answers).mapv(|x| x * x).sum() / seqs.len() as f32;
+
+        let mut update: Update = Update::new(
+            self.input_size,
+            self.output_size,
+            &self.units_by_layer,
+            1,
+        );
+        self.add_batch_update(&mut update, &output, &answers, &activations, &raw, seqs.len());
+        self.process_update(&mut update, LEARNING_RATE);
+
+        loss
+    }
+
+    fn add_batch_update(
+        &self,
+        grad: &mut Update,
+        output: &Array2<f32>,
+        answer: &Array2<f32>,
+        act: &[Array2<f32>],
+        raw: &[Array2<f32>],
+        sequences: usize,
+    ) {
+        let mut hidden_grads: Vec<Array2<f32>> = Vec::new();
+        let mut bias_grads: Vec<Array1<f32>> = Vec::new();
+
+        let raw_outputs: &Array2<f32> =
+            raw.get(self.layers + 1).expect("Failed to get raw outputs");
+
+        // scaling the output gradient averages every gradient behind it
+        let mut prev_grad: Array2<f32> =
+            compute_batch_output_grad(output, answer, raw_outputs, &self.end_activation);
+        prev_grad /= sequences as f32;
+
+        for i in (0..=self.layers).rev() {
+            let layer_activation: &Array2<f32> = act.get(i).expect("Failed to get activations");
+            let raw_input: &Array2<f32> = raw.get(i).expect("Failed to get raw outputs");
+
+            hidden_grads.insert(0, compute_batch_hidden_grad(&prev_grad, layer_activation));
+            bias_grads.insert(0, compute_batch_bias_grad(&prev_grad));
+
+            prev_grad = compute_batch_backpropogated_grad(
+                &self.hidden_weights[i],
+                &self.hidden_activation,
+                &prev_grad,
+                raw_input,
+            );
+        }
+
+        grad.combine_update(hidden_grads, bias_grads);
+    }
+
     fn add_update(
         &self,
         grad: &mut Update,
         output: Vec<f32>,
-        answer: &Vec<f32>,
-        act: &Vec<Array2<f32>>,
-        raw: &Vec<Array2<f32>>,
+        answer: &[f32],
+        act: &[Array2<f32>],
+        raw: &[Array2<f32>],
     ) {
         let mut hidden_grads: Vec<Array2<f32>> = Vec::new();
         let mut bias_grads: Vec<Array1<f32>> = Vec::new();
@@ -290,11 +412,11 @@ impl NN {
 
     fn get_output_grad(
         &self,
-        output: &Vec<f32>,
-        answer: &Vec<f32>,
+        output: &[f32],
+        answer: &[f32],
         raw: &Array2<f32>,
     ) -> Array1<f32> {
-        compute_output_grad(output, answer, raw, &self.hidden_activation)
+        compute_output_grad(output, answer, raw, &self.end_activation)
     }
 
     fn get_hidden_grad(
@@ -309,12 +431,11 @@ impl NN {
     }
 
     fn get_bias_grad(&self, layer: usize, prev_grad: &Array1<f32>) -> Array1<f32> {
-        let expected_dim: usize;
-        if layer == self.layers {
-            expected_dim = self.output_size;
+        let expected_dim: usize = if layer == self.layers {
+            self.output_size
         } else {
-            expected_dim = self.units_by_layer[layer];
-        }
+            self.units_by_layer[layer]
+        };
 
         if prev_grad.dim() != expected_dim {
             panic!("Mismatched bias/gradient vector dimensions!");
@@ -357,6 +478,12 @@ impl NN {
     }
 }
 
+// all samples of all sequences, one per row
+fn stack_sequences(seqs: &[Vec<Vec<f32>>], width: usize) -> Array2<f32> {
+    let rows: Vec<&[f32]> = seqs.iter().flatten().map(|v| v.as_slice()).collect();
+    to_batch_matrix(&rows, width)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::networks::activation::init_registry;
@@ -391,4 +518,96 @@ mod tests {
 
         println!("{:?}", update);
     }
+
+    #[test]
+    fn batch_update_averages_samples() {
+        init_registry();
+        let mut params: ParameterConfig = ParameterConfig::new(2, 2, 2, vec![3, 3]);
+        let weights: WeightConfig = WeightConfig::new(-1.0, 1.0, -0.1, 0.1);
+        let mut activations: ActivationConfig =
+            ActivationConfig::new(Activation::sigmoid(), Activation::none());
+        let mut nn: NN = NN::new(&mut params, weights, &mut activations);
+
+        let seqs: Vec<Vec<Vec<f32>>> = vec![
+            vec![vec![0.5, -1.0], vec![1.0, 2.0]],
+            vec![vec![-0.5, 0.0], vec![2.0, 1.0]],
+        ];
+        let ans: Vec<Vec<Vec<f32>>> = vec![
+            vec![vec![1.0, 0.0], vec![0.0, 1.0]],
+            vec![vec![0.5, 0.5], vec![1.0, -1.0]],
+        ];
+
+        let mut single: Update = Update::new(2, 2, &nn.units_by_layer, 4);
+        for (seq, answer) in seqs.iter().zip(ans.iter()) {
+            for (sample, expected) in seq.iter().zip(answer.iter()) {
+                let (output, activations, raw_nodes) = nn.feedforward(sample.clone());
+                nn.add_update(&mut single, output, expected, &activations, &raw_nodes);
+            }
+        }
+
+        let inputs: Array2<f32> = stack_sequences(&seqs, 2);
+        let answers: Array2<f32> = stack_sequences(&ans, 2);
+        let (output, activations, raw_nodes) = nn.feedforward_batch(inputs);
+        let mut batched: Update = Update::new(2, 2, &nn.units_by_layer, 1);
+        nn.add_batch_update(&mut batched, &output, &answers, &activations, &raw_nodes, 2);
+
+        let expected: Vec<Array2<f32>> = single
+            .get_hidden_update()
+            .iter()
+            .map(|grad| grad / 2.0)
+            .collect();
+        for (a, b) in expected.iter().zip(batched.get_hidden_update().iter()) {
+            assert!(a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-5));
+        }
+
+        assert_eq!(nn.predict_batch(&seqs)[1], nn.predict(seqs[1].clone()));
+    }
+
+    #[test]
+    fn batch_training_lowers_loss() {
+        init_registry();
+        let mut params: ParameterConfig = ParameterConfig::new(1, 1, 1, vec![4]);
+        let weights: WeightConfig = WeightConfig::new(0.1, 0.5, 0.0, 0.01);
+        let mut activations: ActivationConfig =
+            ActivationConfig::new(Activation::relu(), Activation::none());
+        let mut nn: NN = NN::new(&mut params, weights, &mut activations);
+
+        let seqs: Vec<Vec<Vec<f32>>> = (0..4)
+            .map(|s| (0..5).map(|t| vec![(s * 5 + t) as f32 / 10.0]).collect())
+            .collect();
+        let ans: Vec<Vec<Vec<f32>>> = seqs
+            .iter()
+            .map(|seq| seq.iter().map(|x| vec![2.0 * x[0] + 1.0]).collect())
+            .collect();
+
+        let first: f32 = nn.predict_and_update_batch(&seqs, &ans);
+        let mut last: f32 = first;
+        for _i in 0..200 {
+            last = nn.predict_and_update_batch(&seqs, &ans);
+        }
+
+        assert!(last < first * 0.5, "loss went from {first} to {last}");
+    }
+
+    #[test]
+    fn output_grad_uses_end_activation() {
+        init_registry();
+        let mut params: ParameterConfig = ParameterConfig::new(1, 2, 2, vec![3]);
+        let weights: WeightConfig = WeightConfig::new(-1.0, -0.5, -0.2, -0.1);
+        let mut activations: ActivationConfig =
+            ActivationConfig::new(Activation::relu(), Activation::none());
+        let mut nn: NN = NN::new(&mut params, weights, &mut activations);
+
+        let mut update: Update = Update::new(2, 2, &nn.units_by_layer, 1);
+
+        // every raw output is negative, where relu would have no slope but the linear end does
+        let (output, activations, raw_nodes) = nn.feedforward(vec![1.0, 1.0]);
+        assert!(raw_nodes[2].iter().all(|x| *x < 0.0));
+
+        let answer: Vec<f32> = vec![1.0, 1.0];
+        nn.add_update(&mut update, output.clone(), &answer, &activations, &raw_nodes);
+
+        let expected: Vec<f32> = output.iter().zip(answer.iter()).map(|(o, a)| o - a).collect();
+        assert_eq!(update.get_biases_update()[1].to_vec(), expected);
+    }
 }
@@ -14,6 +14,9 @@ use crate::networks::computations::*;
 
 const LEARNING_RATE: f32 = 0.001;
 
+// timesteps an error is carried back through before the recurrence gradient is cut off
+const DEFAULT_UNROLL: usize = 16;
+
 pub struct RNN {
     // p
     layers: usize,
@@ -32,7 +35,17 @@ pub struct RNN {
     biases: Vec<Bias>,
 
     hidden_activation: Activation,
-    end_activation: Activation
+    end_activation: Activation,
+
+    // training only, not saved
+    unroll: usize
+}
+
+// what the backward pass needs from one timestep of the forward pass
+struct Step {
+    output: Array2<f32>,
+    activations: Vec<Array2<f32>>,
+    raw: Vec<Array2<f32>>
 }
 
 impl RNN {
@@ -92,7 +105,8 @@ impl RNN {
             recurrence_weights,
             biases,
             hidden_activation: activations.get_hidden(),
-            end_activation: activations.get_end()
+            end_activation: activations.get_end(),
+            unroll: DEFAULT_UNROLL
         }
     }
 
@@ -112,6 +126,18 @@ impl RNN {
         self.output_size
     }
 
+    pub fn unroll(&self) -> usize {
+        self.unroll
+    }
+
+    pub fn set_unroll(&mut self, unroll: usize) {
+        if unroll < 1 {
+            panic!("Cannot unroll through {unroll} timesteps");
+        }
+
+        self.unroll = unroll;
+    }
+
     pub fn predict(&mut self, seq: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
         let mut output_seq: Vec<Vec<f32>> = Vec::new();
         let mut prev_activations: Vec<Array2<f32>> = Vec::new();
@@ -125,7 +151,7 @@ impl RNN {
         output_seq
     }
 
-    fn feedforward(&mut self, v: Vec<f32>, prev: &Vec<Array2<f32>>) -> (Vec<f32>, Vec<Array2<f32>>, Vec<Array2<f32>>) {
+    fn feedforward(&mut self, v: Vec<f32>, prev: &[Array2<f32>]) -> (Vec<f32>, Vec<Array2<f32>>, Vec<Array2<f32>>) {
         if v.len() != self.input_size {
             panic!("Invalid input size");
         } 
@@ -144,11 +170,10 @@ impl RNN {
             if i < self.layers {
                 let previous: Option<&Array2<f32>> = prev.get(i + 1);
 
-                if previous.is_some() {
+                if let Some(previous) = previous {
                     let recurrent_weight: &Weight = self.recurrence_weights.get(i).expect("Failed to get weights");
-                    let val: Array2<f32> = previous.expect("Failed to get previous activations")
-                        .dot(&recurrent_weight.get_weight_matrix());
-                    arr = arr + &val;
+                    let val: Array2<f32> = previous.dot(&recurrent_weight.get_weight_matrix());
+                    arr += &val;
                 }
             }
 
@@ -178,110 +203,217 @@ impl RNN {
         (output, activations, raw_nodes)
     }
 
-    pub fn predict_and_update(&mut self, seq: Vec<Vec<f32>>, ans: &Vec<Vec<f32>>, batch: usize) {
-        let mut prev_activations: Vec<Array2<f32>> = Vec::new();
-        let mut update: RecurrentUpdate = RecurrentUpdate::new(self.input_size, self.output_size, &self.units_by_layer, batch);
+    // every row is one sequence, prev holds the activations of the same rows one timestep back
+    fn feedforward_batch(&self, inputs: Array2<f32>, prev: &[Array2<f32>]) -> (Array2<f32>, Vec<Array2<f32>>, Vec<Array2<f32>>) {
+        let mut arr: Array2<f32> = inputs;
+        let mut activations: Vec<Array2<f32>> = Vec::with_capacity(self.layers + 1);
+        let mut raw_nodes: Vec<Array2<f32>> = Vec::with_capacity(self.layers + 2);
 
-        for (i, arr) in seq.into_iter().enumerate() {
-            let (output, activations, raw) = self.feedforward(arr, &prev_activations);
-            let answer = ans.get(i).expect("Failed to get desired output vector");
-            self.add_update(&mut update, output, answer, &activations, &prev_activations, &raw);
+        activations.push(arr.clone());
+        raw_nodes.push(arr.clone());
 
-            if update.should_update() {
-                self.process_update(&mut update, LEARNING_RATE);
+        for i in 0..=self.layers {
+            let hidden_weight: &Weight = self.hidden_weights.get(i).expect("Failed to get weight");
+            arr = arr.dot(&hidden_weight.get_weight_matrix());
+
+            if i < self.layers {
+                if let Some(previous) = prev.get(i + 1) {
+                    let recurrent_weight: &Weight = self.recurrence_weights.get(i).expect("Failed to get weights");
+                    arr += &previous.dot(&recurrent_weight.get_weight_matrix());
+                }
             }
 
-            prev_activations = activations;
+            let bias: &Bias = self.biases.get(i).expect("Failed to get bias");
+            arr = arr + bias.get_row_vector();
+
+            raw_nodes.push(arr.clone());
+
+            if i < self.layers {
+                arr.mapv_inplace(|x| self.hidden_activation.of(x));
+                activations.push(arr.clone());
+            }
         }
+
+        arr.mapv_inplace(|x| self.end_activation.of(x));
+
+        // same layout as feedforward, with a row per sequence
+        (arr, activations, raw_nodes)
     }
 
-    fn add_update(&self,
-        grad: &mut RecurrentUpdate,
-        output: Vec<f32>, 
-        answer: &Vec<f32>, 
-        act: &Vec<Array2<f32>>, 
-        prev_act: &Vec<Array2<f32>>,
-        raw: &Vec<Array2<f32>>
-    ) {
-        let mut hidden_grads: Vec<Array2<f32>> = Vec::new();
-        let mut recurrence_grads: Vec<Array2<f32>> = Vec::new();
-        let mut bias_grads: Vec<Array1<f32>> = Vec::new();
+    pub fn predict_batch(&self, seqs: &[Vec<Vec<f32>>]) -> Vec<Vec<Vec<f32>>> {
+        let steps: usize = batch_steps(seqs);
+        let mut output_seqs: Vec<Vec<Vec<f32>>> = vec![Vec::with_capacity(steps); seqs.len()];
+        let mut prev_activations: Vec<Array2<f32>> = Vec::new();
 
-        let raw_outputs: &Array2<f32> = raw.get(self.layers + 1)
-            .expect("Failed to get raw outputs");
-        let mut prev_grad: Array1<f32> = self.get_output_grad(&output, answer, raw_outputs);
+        for t in 0..steps {
+            let inputs: Array2<f32> = timestep_matrix(seqs, t, self.input_size);
+            let (output, activations, _raw) = self.feedforward_batch(inputs, &prev_activations);
+            prev_activations = activations;
 
-        for i in (0..=self.layers).rev() {
-            let layer_activation: &Array2<f32> = act.get(i).expect("Failed to get activations");
-            let raw_input: &Array2<f32> = raw.get(i).expect("Failed to get raw outputs");
+            // padding past the end of a sequence isn't part of its output
+            for ((output_seq, seq), row) in output_seqs.iter_mut().zip(seqs.iter()).zip(output.rows()) {
+                if t < seq.len() {
+                    output_seq.push(row.to_vec());
+                }
+            }
+        }
 
-            let hidden: Array2<f32> = self.get_hidden_grad(i, &prev_grad, layer_activation);
-            let bias: Array1<f32> = self.get_bias_grad(i, &prev_grad);
+        output_seqs
+    }
 
-            hidden_grads.insert(0, hidden);
-            bias_grads.insert(0, bias);
+    /*
+     one update from a batch of sequences, run side by side a timestep at a time. shorter
+     sequences are padded to the longest and masked, so the padding adds no error or gradient.
+     errors are carried back through at most `unroll` timesteps, gradients are summed over the
+     timesteps of a sequence and averaged over the sequences.
+     returns the batch loss, the squared error summed over each sequence and averaged over the batch.
+    */
+    pub fn predict_and_update_batch(&mut self, seqs: &[Vec<Vec<f32>>], ans: &[Vec<Vec<f32>>]) -> f32 {
+        if seqs.len() != ans.len() {
+            panic!("Batch size mismatch: {} sequences but {} answers", seqs.len(), ans.len());
+        } else if seqs.iter().zip(ans.iter()).any(|(seq, answer)| seq.len() != answer.len()) {
+            panic!("Sequence length mismatch between inputs and answers");
+        } else if seqs.is_empty() {
+            return 0.0;
+        }
 
-            if i < self.layers {
-                let prev_activation: Option<&Array2<f32>> = prev_act.get(i + 1);
-                let recurrence: Array2<f32> = self.get_recurrence_grad(i, &prev_grad, prev_activation);
-                recurrence_grads.insert(0, recurrence);
-            }
+        let steps: usize = batch_steps(seqs);
+        let mut state: Vec<Array2<f32>> = Vec::new();
+        let mut update: RecurrentUpdate = RecurrentUpdate::new(self.input_size, self.output_size, &self.units_by_layer, 1);
+        let mut loss: f32 = 0.0;
+
+        for start in (0..steps).step_by(self.unroll) {
+            let end: usize = (start + self.unroll).min(steps);
+            let inputs: Vec<Array2<f32>> = (start..end).map(|t| timestep_matrix(seqs, t, self.input_size)).collect();
+            let answers: Vec<Array2<f32>> = (start..end).map(|t| timestep_matrix(ans, t, self.output_size)).collect();
+            let masks: Vec<Array2<f32>> = (start..end).map(|t| timestep_mask(seqs, t)).collect();
 
-            prev_grad = self.get_backpropogated_grad(i, prev_grad, raw_input);
+            loss += self.add_window_update(&mut update, inputs, &answers, &masks, &mut state);
         }
 
-        grad.combine_update(hidden_grads, recurrence_grads, bias_grads);
-    }
+        self.process_update(&mut update, LEARNING_RATE);
 
-    fn get_output_grad(&self, output: &Vec<f32>, answer: &Vec<f32>, raw: &Array2<f32>) -> Array1<f32> {
-        compute_output_grad(output, answer, raw, &self.hidden_activation)
+        loss / seqs.len() as f32
     }
 
-    fn get_hidden_grad(&self, layer: usize, prev_grad: &Array1<f32>, input_act: &Array2<f32>) -> Array2<f32> {
-        let dim: (usize, usize) = self.hidden_weights[layer].dim();
+    /*
+     trains on a single sequence, unrolled `unroll` timesteps at a time. the update is applied
+     every `batch` timesteps, so a window is cut short where it would run past a batch.
+    */
+    pub fn predict_and_update(&mut self, seq: Vec<Vec<f32>>, ans: &[Vec<f32>], batch: usize) {
+        let mut state: Vec<Array2<f32>> = Vec::new();
+        let mut update: RecurrentUpdate = RecurrentUpdate::new(self.input_size, self.output_size, &self.units_by_layer, batch);
 
-        compute_hidden_grad(dim, prev_grad, input_act)
-    }
+        for (start, end, apply) in update_windows(seq.len(), self.unroll, batch) {
+            let inputs: Vec<Array2<f32>> = seq[start..end].iter()
+                .map(|v| to_batch_matrix(&[v.as_slice()], self.input_size))
+                .collect();
+            let answers: Vec<Array2<f32>> = (start..end)
+                .map(|t| {
+                    let answer: &Vec<f32> = ans.get(t).expect("Failed to get desired output vector");
+                    to_batch_matrix(&[answer.as_slice()], self.output_size)
+                })
+                .collect();
+
+            let masks: Vec<Array2<f32>> = vec![Array2::ones((1, 1)); end - start];
 
-    fn get_bias_grad(&self, layer: usize, prev_grad: &Array1<f32>) -> Array1<f32> {
-        let expected_dim: usize;
-        if layer == self.layers {
-            expected_dim = self.output_size;
-        } else {
-            expected_dim = self.units_by_layer[layer];
+            self.add_window_update(&mut update, inputs, &answers, &masks, &mut state);
+
+            if apply {
+                self.process_update(&mut update, LEARNING_RATE);
+            }
         }
+    }
 
-        if prev_grad.dim() != expected_dim{
-            panic!("Mismatched bias/gradient vector dimensions!");
+    /*
+     runs a window of timesteps forward and then back through time, returning its squared error.
+     masks hold a column per timestep, 1 for the sequences still running and 0 for padding.
+     state holds the activations from before the window, the gradient stops there but the
+     hidden state carries on, so it's replaced with the activations of the window's last timestep.
+    */
+    fn add_window_update(&self,
+        grad: &mut RecurrentUpdate,
+        inputs: Vec<Array2<f32>>,
+        answers: &[Array2<f32>],
+        masks: &[Array2<f32>],
+        state: &mut Vec<Array2<f32>>
+    ) -> f32 {
+        let mut steps: Vec<Step> = Vec::with_capacity(inputs.len());
+
+        for input in inputs {
+            let prev: &[Array2<f32>] = steps.last().map(|step| step.activations.as_slice()).unwrap_or(state.as_slice());
+            let (output, activations, raw) = self.feedforward_batch(input, prev);
+            steps.push(Step { output, activations, raw });
         }
 
-        compute_bias_grad(prev_grad)
-    }
+        let mut loss: f32 = 0.0;
+        let mut future: Vec<Option<Array2<f32>>> = vec![None; self.layers];
+
+        for (t, step) in steps.iter().enumerate().rev() {
+            let answer: &Array2<f32> = answers.get(t).expect("Failed to get desired output");
+            let mask: &Array2<f32> = masks.get(t).expect("Failed to get timestep mask");
+            let prev_act: &[Array2<f32>] = if t == 0 { state.as_slice() } else { &steps[t - 1].activations };
 
-    fn get_recurrence_grad(&self, layer: usize, prev_grad: &Array1<f32>, prev_act: Option<&Array2<f32>>) -> Array2<f32> {
-        if layer >= self.layers {
-            panic!("Invalid layer index!");
+            loss += ((&step.output - answer) * mask).mapv(|x| x * x).sum();
+            self.add_update(grad, step, answer, mask, prev_act, &mut future);
         }
 
-        let layer_dim: usize = self.units_by_layer[layer];
-        let dim: (usize, usize) = (layer_dim, layer_dim);
+        if let Some(last) = steps.pop() {
+            *state = last.activations;
+        }
 
-        compute_recurrence_grad(dim, prev_grad, prev_act)
+        loss
     }
 
-    fn get_backpropogated_grad(
-        &self, 
-        layer: usize, 
-        prev_grad: Array1<f32>, 
-        raw_input: &Array2<f32>,
-    ) -> Array1<f32> {
-        
-        compute_backpropogated_grad(
-            &self.hidden_weights[layer],
-            &self.hidden_activation, 
-            prev_grad, 
-            raw_input
-        )
+    /*
+     gradients of one timestep. future holds the error of every hidden layer one timestep later,
+     which reaches back into this timestep through the recurrence weights, and is replaced with
+     this timestep's error on the way down.
+    */
+    fn add_update(&self,
+        grad: &mut RecurrentUpdate,
+        step: &Step,
+        answer: &Array2<f32>,
+        mask: &Array2<f32>,
+        prev_act: &[Array2<f32>],
+        future: &mut [Option<Array2<f32>>]
+    ) {
+        let mut hidden_grads: Vec<Array2<f32>> = Vec::new();
+        let mut recurrence_grads: Vec<Array2<f32>> = Vec::new();
+        let mut bias_grads: Vec<Array1<f32>> = Vec::new();
+
+        let raw_outputs: &Array2<f32> = step.raw.get(self.layers + 1)
+            .expect("Failed to get raw outputs");
+
+        // a row per sequence, scaling the output gradient averages every gradient behind it
+        let mut prev_grad: Array2<f32> = compute_batch_output_grad(&step.output, answer, raw_outputs, &self.end_activation);
+        prev_grad /= step.output.nrows() as f32;
+        prev_grad *= mask;
+
+        for i in (0..=self.layers).rev() {
+            let layer_activation: &Array2<f32> = step.activations.get(i).expect("Failed to get activations");
+            let raw_input: &Array2<f32> = step.raw.get(i).expect("Failed to get raw outputs");
+
+            if i < self.layers {
+                if let Some(later) = &future[i] {
+                    let raw_layer: &Array2<f32> = step.raw.get(i + 1).expect("Failed to get raw outputs");
+                    prev_grad += &compute_batch_backpropogated_grad(&self.recurrence_weights[i], &self.hidden_activation, later, raw_layer);
+                }
+
+                let layer_dim: usize = self.units_by_layer[i];
+                let recurrence: Array2<f32> = compute_batch_recurrence_grad((layer_dim, layer_dim), &prev_grad, prev_act.get(i + 1));
+                recurrence_grads.insert(0, recurrence);
+
+                future[i] = Some(prev_grad.clone());
+            }
+
+            hidden_grads.insert(0, compute_batch_hidden_grad(&prev_grad, layer_activation));
+            bias_grads.insert(0, compute_batch_bias_grad(&prev_grad));
+
+            prev_grad = compute_batch_backpropogated_grad(&self.hidden_weights[i], &self.hidden_activation, &prev_grad, raw_input);
+        }
+
+        grad.combine_update(hidden_grads, recurrence_grads, bias_grads);
     }
 
     fn process_update(&mut self, grad: &mut RecurrentUpdate, alpha: f32) {
@@ -308,6 +440,39 @@ impl RNN {
     }
 }
 
+// the windows a sequence is trained in, each with whether the update is applied after it
+fn update_windows(len: usize, unroll: usize, batch: usize) -> Vec<(usize, usize, bool)> {
+    let batch: usize = batch.max(1);
+    let mut windows: Vec<(usize, usize, bool)> = Vec::new();
+    let mut start: usize = 0;
+
+    while start < len {
+        let next_update: usize = (start / batch + 1) * batch;
+        let end: usize = (start + unroll).min(next_update).min(len);
+        windows.push((start, end, end == next_update));
+        start = end;
+    }
+
+    windows
+}
+
+// the length of the longest sequence in a batch, the others are padded up to it
+fn batch_steps(seqs: &[Vec<Vec<f32>>]) -> usize {
+    seqs.iter().map(|seq| seq.len()).max().unwrap_or(0)
+}
+
+// the samples of every sequence at one timestep, one per row, zeros past the end of a sequence
+fn timestep_matrix(seqs: &[Vec<Vec<f32>>], t: usize, width: usize) -> Array2<f32> {
+    let padding: Vec<f32> = vec![0.0; width];
+    let rows: Vec<&[f32]> = seqs.iter().map(|seq| seq.get(t).map(|v| v.as_slice()).unwrap_or(&padding)).collect();
+    to_batch_matrix(&rows, width)
+}
+
+// a column with 1 for every sequence that still has timestep t and 0 for padding
+fn timestep_mask(seqs: &[Vec<Vec<f32>>], t: usize) -> Array2<f32> {
+    Array2::from_shape_fn((seqs.len(), 1), |(i, _)| if t < seqs[i].len() { 1.0 } else { 0.0 })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::networks::activation::init_registry;
@@ -320,33 +485,211 @@ mod tests {
         let mut params: ParameterConfig = ParameterConfig::new(2, 2, 2, vec![3, 3]);
         let weights: WeightConfig = WeightConfig::new(0.999, 1.0, -0.01, 0.01);
         let mut activations: ActivationConfig = ActivationConfig::new(Activation::relu(), Activation::none());
-        let mut rnn: RNN = RNN::new(&mut params, weights, &mut activations);
+        let rnn: RNN = RNN::new(&mut params, weights, &mut activations);
 
         let mut update: RecurrentUpdate = RecurrentUpdate::new(params.input_size(), params.output_size(), &rnn.units_by_layer, 4);
 
-        let sample: Vec<f32> = vec![0.0, 0.0];
-        let ( _, prev_activations, _ ) = rnn.feedforward(sample.clone(), &Vec::new());
-        
-        let (
-            output, 
-            activations, 
-            raw_nodes
-        ) = rnn.feedforward(sample, &prev_activations);
+        let sample: Array2<f32> = to_batch_matrix(&[&[0.0, 0.0]], 2);
+        let answer: Array2<f32> = to_batch_matrix(&[&[1.0, 1.0]], 2);
+        let mut state: Vec<Array2<f32>> = Vec::new();
 
-        println!("Finished feedforward prediction");
+        let loss: f32 = rnn.add_window_update(
+            &mut update,
+            vec![sample.clone(), sample],
+            &[answer.clone(), answer],
+            &[Array2::ones((1, 1)), Array2::ones((1, 1))],
+            &mut state
+        );
 
-        let answer: Vec<f32> = vec![1.0, 1.0];
+        println!("Finished window with loss {loss}");
 
+        assert_eq!(state.len(), 3);
+        println!("{:?}", update);
+    }
 
-        rnn.add_update(
-            &mut update, 
-            output, 
-            &answer, 
-            &activations, 
-            &prev_activations, 
-            &raw_nodes
-        );
+    #[test]
+    fn batch_update_averages_sequences() {
+        init_registry();
+        let mut params: ParameterConfig = ParameterConfig::new(2, 2, 2, vec![3, 3]);
+        let weights: WeightConfig = WeightConfig::new(-0.5, 0.5, -0.1, 0.1);
+        let mut activations: ActivationConfig = ActivationConfig::new(Activation::sigmoid(), Activation::none());
+        let mut rnn: RNN = RNN::new(&mut params, weights, &mut activations);
+        rnn.set_unroll(2);
+
+        let seqs: Vec<Vec<Vec<f32>>> = vec![
+            vec![vec![0.5, -1.0], vec![1.0, 2.0], vec![0.0, 1.0]],
+            vec![vec![-0.5, 0.0], vec![2.0, 1.0], vec![1.0, -1.0]]
+        ];
+        let ans: Vec<Vec<Vec<f32>>> = vec![
+            vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]],
+            vec![vec![0.5, 0.5], vec![1.0, -1.0], vec![0.0, 0.0]]
+        ];
+
+        let windowed = |grad: &mut RecurrentUpdate, seqs: &[Vec<Vec<f32>>], ans: &[Vec<Vec<f32>>]| {
+            let mut state: Vec<Array2<f32>> = Vec::new();
+            for (start, end) in [(0, 2), (2, 3)] {
+                let inputs: Vec<Array2<f32>> = (start..end).map(|t| timestep_matrix(seqs, t, 2)).collect();
+                let answers: Vec<Array2<f32>> = (start..end).map(|t| timestep_matrix(ans, t, 2)).collect();
+                let masks: Vec<Array2<f32>> = (start..end).map(|t| timestep_mask(seqs, t)).collect();
+                rnn.add_window_update(grad, inputs, &answers, &masks, &mut state);
+            }
+        };
 
-        println!("{:?}", update);
+        let mut single: RecurrentUpdate = RecurrentUpdate::new(2, 2, &rnn.units_by_layer, 6);
+        for i in 0..2 {
+            windowed(&mut single, &seqs[i..i + 1], &ans[i..i + 1]);
+        }
+
+        let mut batched: RecurrentUpdate = RecurrentUpdate::new(2, 2, &rnn.units_by_layer, 1);
+        windowed(&mut batched, &seqs, &ans);
+
+        let close = |a: &Vec<Array2<f32>>, b: &Vec<Array2<f32>>| {
+            a.iter().zip(b.iter()).all(|(x, y)| x.iter().zip(y.iter()).all(|(p, q)| (p / 2.0 - q).abs() < 1e-5))
+        };
+        assert!(close(single.get_hidden_update(), batched.get_hidden_update()));
+        assert!(close(single.get_recurrence_update(), batched.get_recurrence_update()));
+
+        let predicted: Vec<Vec<Vec<f32>>> = rnn.predict_batch(&seqs);
+        let expected: Vec<Vec<f32>> = rnn.predict(seqs[1].clone());
+        for (a, b) in predicted[1].iter().flatten().zip(expected.iter().flatten()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn updates_every_batch_timesteps() {
+        // a window longer than the batch is cut where the batch fills up
+        assert_eq!(update_windows(12, 16, 5), vec![(0, 5, true), (5, 10, true), (10, 12, false)]);
+        assert_eq!(update_windows(12, 4, 6), vec![(0, 4, false), (4, 6, true), (6, 10, false), (10, 12, true)]);
+        assert_eq!(update_windows(6, 2, 4), vec![(0, 2, false), (2, 4, true), (4, 6, false)]);
+        assert_eq!(update_windows(3, 16, 1), vec![(0, 1, true), (1, 2, true), (2, 3, true)]);
+        assert!(update_windows(0, 16, 5).is_empty());
+    }
+
+    #[test]
+    fn uneven_batches_are_masked() {
+        init_registry();
+        let mut params: ParameterConfig = ParameterConfig::new(2, 2, 1, vec![3, 3]);
+        let weights: WeightConfig = WeightConfig::new(-0.5, 0.5, -0.1, 0.1);
+        let mut activations: ActivationConfig = ActivationConfig::new(Activation::sigmoid(), Activation::none());
+        let rnn: RNN = RNN::new(&mut params, weights, &mut activations);
+
+        let seqs: Vec<Vec<Vec<f32>>> = vec![
+            vec![vec![0.5, -1.0], vec![1.0, 2.0], vec![0.0, 1.0]],
+            vec![vec![-0.5, 0.0]]
+        ];
+        let ans: Vec<Vec<Vec<f32>>> = vec![
+            vec![vec![1.0], vec![0.0], vec![1.0]],
+            vec![vec![0.5]]
+        ];
+        assert_eq!(batch_steps(&seqs), 3);
+
+        let windowed = |grad: &mut RecurrentUpdate, seqs: &[Vec<Vec<f32>>], ans: &[Vec<Vec<f32>>]| -> f32 {
+            let mut state: Vec<Array2<f32>> = Vec::new();
+            let steps: usize = batch_steps(seqs);
+            let inputs: Vec<Array2<f32>> = (0..steps).map(|t| timestep_matrix(seqs, t, 2)).collect();
+            let answers: Vec<Array2<f32>> = (0..steps).map(|t| timestep_matrix(ans, t, 1)).collect();
+            let masks: Vec<Array2<f32>> = (0..steps).map(|t| timestep_mask(seqs, t)).collect();
+            rnn.add_window_update(grad, inputs, &answers, &masks, &mut state)
+        };
+
+        // the padded batch matches the sequences run on their own
+        let mut single: RecurrentUpdate = RecurrentUpdate::new(2, 1, &rnn.units_by_layer, 1);
+        let single_loss: f32 = (0..2).map(|i| windowed(&mut single, &seqs[i..i + 1], &ans[i..i + 1])).sum();
+
+        let mut batched: RecurrentUpdate = RecurrentUpdate::new(2, 1, &rnn.units_by_layer, 1);
+        let batched_loss: f32 = windowed(&mut batched, &seqs, &ans);
+
+        assert!((single_loss - batched_loss).abs() < 1e-5);
+        let close = |a: &Vec<Array2<f32>>, b: &Vec<Array2<f32>>| {
+            a.iter().zip(b.iter()).all(|(x, y)| x.iter().zip(y.iter()).all(|(p, q)| (p / 2.0 - q).abs() < 1e-5))
+        };
+        assert!(close(single.get_hidden_update(), batched.get_hidden_update()));
+        assert!(close(single.get_recurrence_update(), batched.get_recurrence_update()));
+
+        let predicted: Vec<Vec<Vec<f32>>> = rnn.predict_batch(&seqs);
+        assert_eq!(predicted.iter().map(|seq| seq.len()).collect::<Vec<usize>>(), vec![3, 1]);
+    }
+
+    // half the squared error over a whole sequence, which the output gradient is the derivative of
+    fn sequence_loss(rnn: &RNN, seq: &[Vec<f32>], ans: &[Vec<f32>]) -> f32 {
+        let output: Vec<Vec<f32>> = rnn.predict_batch(&[seq.to_vec()]).remove(0);
+        output.iter().flatten().zip(ans.iter().flatten())
+            .map(|(o, a)| 0.5 * (o - a) * (o - a))
+            .sum()
+    }
+
+    // central difference of the loss against every entry of a weight matrix
+    fn numerical_grad(rnn: &mut RNN, recurrent: bool, layer: usize, seq: &[Vec<f32>], ans: &[Vec<f32>]) -> Array2<f32> {
+        let eps: f32 = 1e-2;
+        let dim: (usize, usize) = if recurrent { rnn.recurrence_weights[layer].dim() } else { rnn.hidden_weights[layer].dim() };
+        let mut grads: Array2<f32> = Array2::zeros(dim);
+
+        for ((r, c), grad) in grads.indexed_iter_mut() {
+            let mut nudge: Array2<f32> = Array2::zeros(dim);
+            nudge[[r, c]] = eps;
+
+            let weight: &mut Weight = if recurrent { &mut rnn.recurrence_weights[layer] } else { &mut rnn.hidden_weights[layer] };
+            weight.update(&nudge, -1.0);
+            let above: f32 = sequence_loss(rnn, seq, ans);
+
+            let weight: &mut Weight = if recurrent { &mut rnn.recurrence_weights[layer] } else { &mut rnn.hidden_weights[layer] };
+            weight.update(&nudge, 2.0);
+            let below: f32 = sequence_loss(rnn, seq, ans);
+
+            let weight: &mut Weight = if recurrent { &mut rnn.recurrence_weights[layer] } else { &mut rnn.hidden_weights[layer] };
+            weight.update(&nudge, -1.0);
+
+            *grad = (above - below) / (2.0 * eps);
+        }
+
+        grads
+    }
+
+    #[test]
+    fn gradient_check() {
+        init_registry();
+        let mut params: ParameterConfig = ParameterConfig::new(2, 2, 1, vec![3, 2]);
+        let weights: WeightConfig = WeightConfig::new(-0.8, 0.8, -0.2, 0.2);
+        let mut activations: ActivationConfig = ActivationConfig::new(Activation::sigmoid(), Activation::none());
+        let mut rnn: RNN = RNN::new(&mut params, weights, &mut activations);
+
+        let seq: Vec<Vec<f32>> = vec![vec![1.0, -0.5], vec![0.2, 0.8], vec![-1.0, 0.3], vec![0.6, 0.6], vec![0.0, -0.4]];
+        let ans: Vec<Vec<f32>> = vec![vec![0.5], vec![-0.5], vec![1.0], vec![0.0], vec![2.0]];
+
+        let analytic = |rnn: &RNN| {
+            let mut update: RecurrentUpdate = RecurrentUpdate::new(2, 1, &rnn.units_by_layer, 1);
+            let mut state: Vec<Array2<f32>> = Vec::new();
+            for start in (0..seq.len()).step_by(rnn.unroll()) {
+                let end: usize = (start + rnn.unroll()).min(seq.len());
+                let inputs: Vec<Array2<f32>> = (start..end).map(|t| to_batch_matrix(&[seq[t].as_slice()], 2)).collect();
+                let answers: Vec<Array2<f32>> = (start..end).map(|t| to_batch_matrix(&[ans[t].as_slice()], 1)).collect();
+                let masks: Vec<Array2<f32>> = vec![Array2::ones((1, 1)); end - start];
+                rnn.add_window_update(&mut update, inputs, &answers, &masks, &mut state);
+            }
+            update
+        };
+
+        let matches = |a: &Array2<f32>, b: &Array2<f32>| {
+            a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() <= 1e-2 + 1e-2 * y.abs())
+        };
+
+        // unrolled through the whole sequence the gradient is exact
+        let full: RecurrentUpdate = analytic(&rnn);
+        for layer in 0..2 {
+            let recurrence: Array2<f32> = numerical_grad(&mut rnn, true, layer, &seq, &ans);
+            assert!(matches(&full.get_recurrence_update()[layer], &recurrence), "recurrence {layer}: {:?} vs {recurrence:?}", full.get_recurrence_update()[layer]);
+        }
+        for layer in 0..3 {
+            let hidden: Array2<f32> = numerical_grad(&mut rnn, false, layer, &seq, &ans);
+            assert!(matches(&full.get_hidden_update()[layer], &hidden), "hidden {layer}: {:?} vs {hidden:?}", full.get_hidden_update()[layer]);
+        }
+
+        // cutting the recurrence off after every timestep leaves out the earlier timesteps
+        rnn.set_unroll(1);
+        let truncated: RecurrentUpdate = analytic(&rnn);
+        let recurrence: Array2<f32> = numerical_grad(&mut rnn, true, 0, &seq, &ans);
+        let error = |a: &Array2<f32>| (a - &recurrence).mapv(f32::abs).sum();
+        assert!(error(&truncated.get_recurrence_update()[0]) > 5.0 * error(&full.get_recurrence_update()[0]));
     }
 }
\ No newline at end of file
@@ -3,7 +3,7 @@ use std::fs;
 // external
 
 // internal
-use super::RNN;
+use super::{RNN, DEFAULT_UNROLL};
 use crate::networks::activation::Activation;
 use crate::networks::types::{Bias, Weight};
 
@@ -59,7 +59,8 @@ pub fn from_save(file_path: &str) -> RNN {
         recurrence_weights,
         biases,
         hidden_activation,
-        end_activation
+        end_activation,
+        unroll: DEFAULT_UNROLL
     }
 }
 
@@ -0,0 +1,459 @@
+// builtin
+
+// external
+
+// internal
+use audio_to_spectrum::audio_to_spectrograph;
+use audio_to_spectrum::spectrograph::Spectrograph;
+use midi_encoder::error::MidiError;
+use midi_encoder::span::{event_times, events_to_spans, NoteSpan};
+use midi_encoder::types::NoteEvent;
+use midi_encoder::{read_events, write_events};
+
+const PITCH_CLASSES: usize = 12;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AlignConfig {
+    frame_ms: f64,
+    band: Option<f64>,
+}
+
+impl AlignConfig {
+    // features are pooled into frames of frame_ms before aligning
+    pub fn new(frame_ms: f64) -> AlignConfig {
+        if !(frame_ms > 0.0 && frame_ms.is_finite()) {
+            panic!("Cannot align frames of {frame_ms} ms");
+        }
+
+        AlignConfig {
+            frame_ms,
+            band: None,
+        }
+    }
+
+    /*
+     only lets the path stray this fraction of the longer sequence from the diagonal,
+     which saves time on long pieces that are known to be roughly in step
+    */
+    pub fn set_band(&mut self, band: f64) {
+        if !(band > 0.0 && band <= 1.0) {
+            panic!("Band of {band} is outside (0, 1]");
+        }
+
+        self.band = Some(band);
+    }
+
+    pub fn frame_ms(&self) -> f64 {
+        self.frame_ms
+    }
+
+    pub fn band(&self) -> Option<f64> {
+        self.band
+    }
+}
+
+impl Default for AlignConfig {
+    fn default() -> AlignConfig {
+        AlignConfig::new(100.0)
+    }
+}
+
+/*
+ how a MIDI file's timeline maps onto a recording. the path pairs MIDI frames with audio
+ frames, and every MIDI frame gets the average time of the audio frames it was paired with.
+*/
+#[derive(Debug, Clone)]
+pub struct Alignment {
+    path: Vec<(usize, usize)>,
+    warp_ms: Vec<f64>,
+    frame_ms: f64,
+    cost: f64,
+}
+
+impl Alignment {
+    // (midi frame, audio frame) pairs from the start of both to the end of both
+    pub fn get_path(&self) -> &Vec<(usize, usize)> {
+        &self.path
+    }
+
+    // average cost per step, 0 for identical chroma and up to 1 for unrelated ones
+    pub fn get_cost(&self) -> f64 {
+        self.cost
+    }
+
+    pub fn frame_ms(&self) -> f64 {
+        self.frame_ms
+    }
+
+    // between frame centers the times are interpolated, past the ends they run at the same speed
+    pub fn audio_time_ms(&self, midi_ms: f64) -> f64 {
+        if self.warp_ms.is_empty() {
+            return midi_ms;
+        }
+        let position: f64 = midi_ms / self.frame_ms - 0.5;
+        let last: usize = self.warp_ms.len() - 1;
+
+        if position <= 0.0 {
+            self.warp_ms[0] + position * self.frame_ms
+        } else if position >= last as f64 {
+            self.warp_ms[last] + (position - last as f64) * self.frame_ms
+        } else {
+            let frame: usize = position.floor() as usize;
+            let fraction: f64 = position - frame as f64;
+            self.warp_ms[frame] * (1.0 - fraction) + self.warp_ms[frame + 1] * fraction
+        }
+    }
+
+    // the same events moved onto the recording's timeline
+    pub fn retime(&self, events: &[NoteEvent]) -> Vec<NoteEvent> {
+        let mut previous_ms: f64 = 0.0;
+
+        events
+            .iter()
+            .zip(event_times(events))
+            .map(|(event, midi_ms)| {
+                let audio_ms: f64 = self.audio_time_ms(midi_ms).max(previous_ms);
+                let delta: f64 = audio_ms - previous_ms;
+                previous_ms = audio_ms;
+                event.clone().with_time_delta(delta as f32).at_time(audio_ms)
+            })
+            .collect()
+    }
+}
+
+/*
+ dynamic time warping between the MIDI's chroma and the recording's. the MIDI is projected
+ straight onto pitch classes, weighted by how long and how loud each note sounds in a frame,
+ and the recording's chroma comes from folding its CQT bins. both are pooled to frame_ms and
+ compared by cosine distance.
+*/
+pub fn align(events: &[NoteEvent], spectrograph: &Spectrograph, config: &AlignConfig) -> Alignment {
+    let audio: Vec<Vec<f32>> = pool(
+        &spectrograph.chroma(),
+        spectrograph.frame_ms(),
+        config.frame_ms,
+    );
+    let spans: Vec<NoteSpan> = events_to_spans(events);
+    let end_ms: f64 = spans.iter().map(|s| s.get_offset_ms()).fold(0.0, f64::max);
+    let midi: Vec<Vec<f32>> = midi_chroma(
+        &spans,
+        config.frame_ms,
+        (end_ms / config.frame_ms).ceil() as usize,
+    );
+
+    if midi.is_empty() || audio.is_empty() {
+        return Alignment {
+            path: Vec::new(),
+            warp_ms: Vec::new(),
+            frame_ms: config.frame_ms,
+            cost: 0.0,
+        };
+    }
+
+    let (path, total) = warp_path(&midi, &audio, config.band);
+
+    let mut sums: Vec<(f64, usize)> = vec![(0.0, 0); midi.len()];
+    for (i, j) in path.iter() {
+        sums[*i].0 += (*j as f64 + 0.5) * config.frame_ms;
+        sums[*i].1 += 1;
+    }
+    let warp_ms: Vec<f64> = sums
+        .iter()
+        .map(|(sum, count)| sum / *count as f64)
+        .collect();
+
+    Alignment {
+        cost: total / path.len() as f64,
+        path,
+        warp_ms,
+        frame_ms: config.frame_ms,
+    }
+}
+
+// aligns a MIDI file to a recording and writes the re-timed events out
+pub fn align_files(
+    midi_path: &str,
+    audio_path: &str,
+    output_path: &str,
+    config: &AlignConfig,
+) -> Result<Alignment, MidiError> {
+    let events: Vec<NoteEvent> = read_events(midi_path)?;
+    let alignment: Alignment = align(&events, &audio_to_spectrograph(audio_path), config);
+    write_events(&alignment.retime(&events), output_path)?;
+    Ok(alignment)
+}
+
+pub fn midi_chroma(spans: &[NoteSpan], frame_ms: f64, num_frames: usize) -> Vec<Vec<f32>> {
+    let mut frames: Vec<Vec<f32>> = vec![vec![0.0; PITCH_CLASSES]; num_frames];
+
+    for span in spans {
+        let pitch_class: usize = (span.get_key() % 12) as usize;
+        let loudness: f64 = span.get_velocity().max(1) as f64 / 127.0;
+        let first: usize = (span.get_onset_ms() / frame_ms).floor().max(0.0) as usize;
+        let last: usize = ((span.get_offset_ms() / frame_ms).ceil() as usize).min(num_frames);
+
+        for (i, frame) in frames.iter_mut().enumerate().take(last).skip(first) {
+            let start: f64 = i as f64 * frame_ms;
+            let overlap: f64 =
+                span.get_offset_ms().min(start + frame_ms) - span.get_onset_ms().max(start);
+            frame[pitch_class] += (overlap.max(0.0) / frame_ms * loudness) as f32;
+        }
+    }
+
+    frames.iter().map(|frame| normalize(frame)).collect()
+}
+
+// sums the frames starting inside each longer frame, only the direction is kept anyway
+fn pool(frames: &[Vec<f32>], from_ms: f64, to_ms: f64) -> Vec<Vec<f32>> {
+    let num_frames: usize = (frames.len() as f64 * from_ms / to_ms).ceil() as usize;
+    let mut pooled: Vec<Vec<f32>> = vec![vec![0.0; PITCH_CLASSES]; num_frames];
+
+    for (i, frame) in frames.iter().enumerate() {
+        let target: usize = ((i as f64 * from_ms / to_ms).floor() as usize).min(num_frames - 1);
+        for (pitch_class, value) in frame.iter().take(PITCH_CLASSES).enumerate() {
+            pooled[target][pitch_class] += value;
+        }
+    }
+
+    pooled.iter().map(|frame| normalize(frame)).collect()
+}
+
+// unit length, silence becomes flat so that it matches other silence
+fn normalize(frame: &[f32]) -> Vec<f32> {
+    let norm: f32 = frame.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm <= f32::EPSILON {
+        return vec![1.0 / (PITCH_CLASSES as f32).sqrt(); PITCH_CLASSES];
+    }
+    frame.iter().map(|v| v / norm).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Diagonal,
+    Up,
+    Left,
+}
+
+/*
+ symmetric steps, a diagonal step costs twice so that it's no cheaper than going around.
+ only the cells inside the band are stored, each with the step that reached it, and the
+ path is traced back through those steps. the band always holds both corners.
+*/
+fn warp_path(
+    midi: &[Vec<f32>],
+    audio: &[Vec<f32>],
+    band: Option<f64>,
+) -> (Vec<(usize, usize)>, f64) {
+    let (n, m) = (midi.len(), audio.len());
+    let radius: Option<f64> = band.map(|b| (b * n.max(m) as f64).max(1.0));
+
+    // the columns [start, end) of each row inside the band, around the line joining the corners
+    let columns: Vec<(usize, usize)> = (0..n)
+        .map(|i| match radius {
+            Some(radius) => {
+                let center: f64 = i as f64 * (m - 1).max(1) as f64 / (n - 1).max(1) as f64;
+                let end: usize = ((center + radius).floor() + 1.0).clamp(0.0, m as f64) as usize;
+                let start: usize = ((center - radius).ceil().max(0.0) as usize).min(end);
+                (start, end)
+            }
+            None => (0, m),
+        })
+        .collect();
+    let at = |row: &[f64], start: usize, j: usize| -> f64 {
+        if j >= start && j - start < row.len() {
+            row[j - start]
+        } else {
+            f64::INFINITY
+        }
+    };
+
+    let mut steps: Vec<Vec<Step>> = Vec::with_capacity(n);
+    let (mut previous, mut previous_start): (Vec<f64>, usize) = (Vec::new(), 0);
+    for (i, (start, end)) in columns.iter().copied().enumerate() {
+        let mut row: Vec<f64> = vec![f64::INFINITY; end - start];
+        let mut row_steps: Vec<Step> = Vec::with_capacity(end - start);
+        for j in start..end {
+            let cost: f64 = distance(&midi[i], &audio[j]);
+            let options: [(f64, Step, bool); 3] = [
+                (
+                    at(&previous, previous_start, j.wrapping_sub(1)) + 2.0 * cost,
+                    Step::Diagonal,
+                    i > 0 && j > 0,
+                ),
+                (at(&previous, previous_start, j) + cost, Step::Up, i > 0),
+                (at(&row, start, j.wrapping_sub(1)) + cost, Step::Left, j > 0),
+            ];
+            // unreachable cells still point somewhere, the first step that stays on the grid
+            let (total, step, _) = options
+                .iter()
+                .copied()
+                .filter(|(_, _, valid)| *valid)
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .unwrap_or((cost, Step::Diagonal, true));
+            row[j - start] = total;
+            row_steps.push(step);
+        }
+        steps.push(row_steps);
+        previous = row;
+        previous_start = start;
+    }
+    let total: f64 = at(&previous, previous_start, m - 1);
+
+    let mut path: Vec<(usize, usize)> = vec![(n - 1, m - 1)];
+    let (mut i, mut j) = (n - 1, m - 1);
+    while i > 0 || j > 0 {
+        let (start, end) = columns[i];
+        let step: Step = if j >= start && j < end {
+            steps[i][j - start]
+        } else if i > 0 && j > 0 {
+            Step::Diagonal
+        } else if i > 0 {
+            Step::Up
+        } else {
+            Step::Left
+        };
+        match step {
+            Step::Diagonal => (i, j) = (i - 1, j - 1),
+            Step::Up => i -= 1,
+            Step::Left => j -= 1,
+        }
+        path.push((i, j));
+    }
+    path.reverse();
+
+    (path, total)
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f64 {
+    let similarity: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    (1.0 - similarity).max(0.0) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midi_encoder::span::spans_to_events;
+
+    // a stand-in recording, each sounding key lighting up its own CQT bin from A0
+    fn render(spans: &[NoteSpan], frame_ms: f64, num_frames: usize) -> Spectrograph {
+        let mut graph: Vec<Vec<f32>> = vec![vec![0.0; 96]; num_frames];
+        for span in spans {
+            let bin: usize = (span.get_key() - 21) as usize;
+            let first: usize = (span.get_onset_ms() / frame_ms) as usize;
+            let last: usize = ((span.get_offset_ms() / frame_ms) as usize).min(num_frames);
+            for frame in graph.iter_mut().take(last).skip(first) {
+                frame[bin] = span.get_velocity() as f32 / 127.0;
+            }
+        }
+        Spectrograph::new(graph, (frame_ms / 1000.0) as f32)
+    }
+
+    // the first half played slower and the second half faster
+    fn perform(ms: f64) -> f64 {
+        if ms < 4000.0 {
+            ms * 1.25
+        } else {
+            5000.0 + (ms - 4000.0) * 0.75
+        }
+    }
+
+    #[test]
+    fn recovers_tempo_changes() {
+        let keys: [u8; 8] = [60, 64, 67, 62, 65, 69, 71, 59];
+        let score: Vec<NoteSpan> = (0..16)
+            .map(|i| NoteSpan::new(keys[i % 8], i as f64 * 500.0, (i + 1) as f64 * 500.0, 90))
+            .collect();
+        let played: Vec<NoteSpan> = score
+            .iter()
+            .map(|s| {
+                NoteSpan::new(
+                    s.get_key(),
+                    perform(s.get_onset_ms()),
+                    perform(s.get_offset_ms()),
+                    90,
+                )
+            })
+            .collect();
+
+        let audio_frame_ms: f64 = 512.0 / 44100.0 * 1000.0;
+        let recording: Spectrograph =
+            render(&played, audio_frame_ms, (8200.0 / audio_frame_ms) as usize);
+        let mut config: AlignConfig = AlignConfig::new(50.0);
+        config.set_band(0.2);
+        let alignment: Alignment = align(&spans_to_events(&score), &recording, &config);
+        assert!(alignment.get_cost() < 0.2);
+
+        let retimed: Vec<NoteSpan> = events_to_spans(&alignment.retime(&spans_to_events(&score)));
+        assert_eq!(retimed.len(), played.len());
+        let error: f64 = retimed
+            .iter()
+            .zip(played.iter())
+            .map(|(r, p)| (r.get_onset_ms() - p.get_onset_ms()).abs())
+            .sum::<f64>()
+            / played.len() as f64;
+        assert!(error < 60.0, "mean onset error {} ms", error);
+    }
+
+    #[test]
+    fn warp_is_monotonic() {
+        let a: Vec<Vec<f32>> = (0..5).map(|i| normalize(&[i as f32, 1.0, 0.0])).collect();
+        let (path, total) = warp_path(&a, &a, None);
+        assert_eq!(
+            path,
+            (0..5).map(|i| (i, i)).collect::<Vec<(usize, usize)>>()
+        );
+        assert!(total.abs() < 1e-6);
+
+        let alignment: Alignment = Alignment {
+            path,
+            warp_ms: vec![50.0, 150.0, 400.0],
+            frame_ms: 100.0,
+            cost: 0.0,
+        };
+        assert_eq!(alignment.audio_time_ms(100.0), 100.0);
+        assert_eq!(alignment.audio_time_ms(200.0), 275.0);
+        assert_eq!(alignment.audio_time_ms(300.0), 450.0);
+        assert_eq!(alignment.audio_time_ms(0.0), 0.0);
+    }
+
+    #[test]
+    fn path_cost_matches_total() {
+        // uneven lengths force steps off the diagonal
+        let frames = |len: usize, seed: usize| -> Vec<Vec<f32>> {
+            (0..len)
+                .map(|i| {
+                    let frame: Vec<f32> = (0..PITCH_CLASSES)
+                        .map(|k| ((i * 7 + k * 5 + seed) % 11) as f32)
+                        .collect();
+                    normalize(&frame)
+                })
+                .collect()
+        };
+        let (midi, audio) = (frames(9, 1), frames(14, 4));
+
+        for band in [None, Some(0.3)] {
+            let (path, total) = warp_path(&midi, &audio, band);
+            assert_eq!((path[0], path[path.len() - 1]), ((0, 0), (8, 13)));
+
+            let mut walked: f64 = distance(&midi[0], &audio[0]);
+            for step in path.windows(2) {
+                let ((i, j), (next_i, next_j)) = (step[0], step[1]);
+                let weight: f64 = if next_i > i && next_j > j { 2.0 } else { 1.0 };
+                walked += weight * distance(&midi[next_i], &audio[next_j]);
+            }
+            assert!((walked - total).abs() < 1e-9, "{walked} walked for {total}");
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn frames_need_a_length() {
+        AlignConfig::new(0.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn band_is_a_fraction() {
+        AlignConfig::default().set_band(-0.2);
+    }
+}
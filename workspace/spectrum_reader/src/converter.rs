@@ -5,7 +5,8 @@
 // internal
 use crate::types::{ConverterConfig, Translator};
 use audio_to_spectrum::spectrograph::{constants, Spectrograph};
-use midi_encoder::types::{MIDIEncoding, ENCODING_LENGTH};
+use midi_encoder::encoding::{ChordLayout, Encoding};
+use midi_encoder::types::MIDIEncoding;
 use models::networks::activation::init_registry;
 use models::networks::configs::*;
 use models::RNN;
@@ -13,6 +14,7 @@ use models::RNN;
 pub struct RNNConverter {
     rnn: RNN,
     batch: usize,
+    layout: Box<dyn Encoding>,
 }
 
 impl RNNConverter {
@@ -27,7 +29,8 @@ impl RNNConverter {
             * (constants::MAX_FREQ / constants::MIN_FREQ).log2().ceil())
             as usize;
 
-        let output_size: usize = ENCODING_LENGTH;
+        let layout: Box<dyn Encoding> = config.take_layout();
+        let output_size: usize = layout.vector_length();
 
         let mut params: ParameterConfig = ParameterConfig::new(
             config.layers(),
@@ -40,23 +43,31 @@ impl RNNConverter {
 
         let batch: usize = config.batch_size();
 
-        RNNConverter { rnn, batch }
+        RNNConverter { rnn, batch, layout }
     }
 
     pub fn from_file(path: &str, batch: usize) -> RNNConverter {
+        RNNConverter::from_file_with_layout(path, batch, Box::new(ChordLayout::default()))
+    }
+
+    pub fn from_file_with_layout(
+        path: &str,
+        batch: usize,
+        layout: Box<dyn Encoding>,
+    ) -> RNNConverter {
         let rnn: RNN = RNN::from_save(path);
 
         let input_size: usize = ((constants::BINS_PER_OCTAVE as f32)
             * (constants::MAX_FREQ / constants::MIN_FREQ).log2().ceil())
             as usize;
 
-        let output_size: usize = ENCODING_LENGTH;
+        let output_size: usize = layout.vector_length();
 
         if rnn.input_dim() != input_size || rnn.output_dim() != output_size {
             panic!("Invalid configuration file for Audio-to-MIDI converter");
         }
 
-        RNNConverter { rnn, batch }
+        RNNConverter { rnn, batch, layout }
     }
 
     pub fn save(&self, path: &str) {
@@ -69,16 +80,21 @@ impl Translator for RNNConverter {
         let freq_seq: Vec<Vec<f32>> = spectrum.graph();
         let output_seq: Vec<Vec<f32>> = self.rnn.predict(freq_seq);
 
-        MIDIEncoding::from_vector(output_seq, cutoff)
+        MIDIEncoding::from_vector(output_seq, cutoff, self.layout.as_ref())
     }
 
     fn update(&mut self, mut spectrum: Spectrograph, encoding: MIDIEncoding) {
         let seq: Vec<Vec<f32>> = spectrum.graph();
-        let output_seq: Vec<Vec<f32>> = encoding
-            .get_encoding()
-            .iter()
-            .map(|chord| chord.get_encoding())
-            .collect();
+        let output_seq: Vec<Vec<f32>> = encoding.to_vectors(self.layout.as_ref());
+
+        // targets have to line up with the input frames, see midi_encoder::get_frame_encoding
+        if seq.len() != output_seq.len() {
+            panic!(
+                "Expected one encoding per spectrograph frame, got {} frames and {} encodings",
+                seq.len(),
+                output_seq.len()
+            );
+        }
 
         println!("{:?}", seq[0]);
 
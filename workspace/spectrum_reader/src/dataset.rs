@@ -0,0 +1,580 @@
+// builtin
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// external
+use ndarray_rand::rand::Rng;
+use ndarray_rand::rand::rngs::StdRng;
+use ndarray_rand::rand::SeedableRng;
+use serde_json::Value;
+
+// internal
+use crate::error::DatasetError;
+use audio_to_spectrum::spectrograph::Spectrograph;
+use audio_to_spectrum::{audio_to_spectrograph, get_window_spectrograph};
+use midi_encoder::types::MIDIEncoding;
+use midi_encoder::{get_duration_ms, get_frame_encoding, get_window_frame_encoding};
+
+const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "wav", "flac", "ogg"];
+const MIDI_EXTENSIONS: [&str; 2] = ["midi", "mid"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Split {
+    Train,
+    Validation,
+    Test,
+}
+
+impl Split {
+    // the names used in MAESTRO's metadata
+    pub fn from_name(name: &str) -> Option<Split> {
+        match name.trim().to_lowercase().as_str() {
+            "train" => Some(Split::Train),
+            "validation" => Some(Split::Validation),
+            "test" => Some(Split::Test),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Split::Train => "train",
+            Split::Validation => "validation",
+            Split::Test => "test",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    audio_path: PathBuf,
+    midi_path: PathBuf,
+    split: Split,
+    duration_sec: Option<f32>,
+}
+
+impl Recording {
+    pub fn new(audio_path: PathBuf, midi_path: PathBuf, split: Split) -> Recording {
+        Recording {
+            audio_path,
+            midi_path,
+            split,
+            duration_sec: None,
+        }
+    }
+
+    pub fn set_duration(&mut self, duration_sec: f32) {
+        self.duration_sec = Some(duration_sec);
+    }
+
+    pub fn get_audio_path(&self) -> &Path {
+        &self.audio_path
+    }
+
+    pub fn get_midi_path(&self) -> &Path {
+        &self.midi_path
+    }
+
+    pub fn get_split(&self) -> Split {
+        self.split
+    }
+
+    // as listed in the manifest, if it was
+    pub fn get_duration(&self) -> Option<f32> {
+        self.duration_sec
+    }
+
+    // falls back to the end of the last note, which is where a matching recording ends too
+    pub fn duration(&self) -> Result<f32, DatasetError> {
+        match self.duration_sec {
+            Some(duration) => Ok(duration),
+            None => Ok((get_duration_ms(&path_str(&self.midi_path)?)? / 1000.0) as f32),
+        }
+    }
+}
+
+// one spectrograph and the frame encoding lined up with it
+pub struct Pair {
+    spectrograph: Spectrograph,
+    encoding: MIDIEncoding,
+    recording: usize,
+    offset_sec: f32,
+}
+
+impl Pair {
+    pub fn get_spectrograph(&self) -> &Spectrograph {
+        &self.spectrograph
+    }
+
+    pub fn get_encoding(&self) -> &MIDIEncoding {
+        &self.encoding
+    }
+
+    // index of the recording in its dataset
+    pub fn get_recording(&self) -> usize {
+        self.recording
+    }
+
+    pub fn get_offset(&self) -> f32 {
+        self.offset_sec
+    }
+
+    // in the order Translator::update takes them
+    pub fn into_parts(self) -> (Spectrograph, MIDIEncoding) {
+        (self.spectrograph, self.encoding)
+    }
+}
+
+/*
+ recordings paired with their MIDI transcriptions, each in a train, validation or test split.
+ a dataset is read from a directory of audio files with MIDI files of the same name next to
+ them, or from a MAESTRO style manifest listing audio_filename, midi_filename, split and
+ optionally duration, with paths relative to the manifest.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    recordings: Vec<Recording>,
+}
+
+impl Dataset {
+    pub fn new(recordings: Vec<Recording>) -> Dataset {
+        Dataset { recordings }
+    }
+
+    /*
+     searches the directory and everything below it. files are split by a hash of their
+     name, a tenth each for validation and test, so adding files never moves the old ones.
+    */
+    pub fn from_directory(dir: &str) -> Result<Dataset, DatasetError> {
+        let mut audio_files: Vec<PathBuf> = Vec::new();
+        collect_audio(Path::new(dir), &mut audio_files)?;
+        audio_files.sort();
+
+        let recordings: Vec<Recording> = audio_files
+            .into_iter()
+            .filter_map(|audio| {
+                let midi: PathBuf = MIDI_EXTENSIONS
+                    .iter()
+                    .map(|extension| audio.with_extension(extension))
+                    .find(|path| path.is_file())?;
+                let name: String = audio.file_stem()?.to_string_lossy().to_string();
+                Some(Recording::new(audio, midi, hashed_split(&name)))
+            })
+            .collect();
+
+        Ok(Dataset { recordings })
+    }
+
+    pub fn from_manifest(path: &str) -> Result<Dataset, DatasetError> {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("csv") => Dataset::from_csv(path),
+            Some("json") => Dataset::from_json(path),
+            _ => Err(DatasetError::Manifest(format!(
+                "{} is neither a CSV nor a JSON file",
+                path
+            ))),
+        }
+    }
+
+    // a header row naming the columns, then one recording per row
+    pub fn from_csv(path: &str) -> Result<Dataset, DatasetError> {
+        let text: String = fs::read_to_string(path)?;
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header: Vec<String> = match lines.next() {
+            Some(line) => csv_fields(line),
+            None => return Err(DatasetError::Manifest(String::from("empty CSV file"))),
+        };
+
+        let rows: Vec<HashMap<String, String>> = lines
+            .map(|line| header.iter().cloned().zip(csv_fields(line)).collect())
+            .collect();
+        Dataset::from_rows(path, &rows)
+    }
+
+    // either MAESTRO's column layout, {"split": {"0": "train", ...}, ...}, or a list of objects
+    pub fn from_json(path: &str) -> Result<Dataset, DatasetError> {
+        let json: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+        let rows: Vec<HashMap<String, String>> = match json {
+            Value::Array(records) => records
+                .iter()
+                .filter_map(|record| record.as_object())
+                .map(|record| {
+                    record
+                        .iter()
+                        .map(|(column, value)| (column.clone(), json_text(value)))
+                        .collect()
+                })
+                .collect(),
+            Value::Object(columns) => {
+                let mut rows: HashMap<String, HashMap<String, String>> = HashMap::new();
+                for (column, values) in columns.iter() {
+                    let Some(values) = values.as_object() else {
+                        continue;
+                    };
+                    for (row, value) in values.iter() {
+                        rows.entry(row.clone())
+                            .or_default()
+                            .insert(column.clone(), json_text(value));
+                    }
+                }
+
+                // rows are keyed by their index as text
+                let mut keyed: Vec<(String, HashMap<String, String>)> = rows.into_iter().collect();
+                keyed.sort_by_key(|(row, _)| {
+                    (row.parse::<usize>().unwrap_or(usize::MAX), row.clone())
+                });
+                keyed.into_iter().map(|(_, row)| row).collect()
+            }
+            _ => {
+                return Err(DatasetError::Manifest(String::from(
+                    "expected a JSON object or array",
+                )))
+            }
+        };
+        Dataset::from_rows(path, &rows)
+    }
+
+    fn from_rows(path: &str, rows: &[HashMap<String, String>]) -> Result<Dataset, DatasetError> {
+        let root: PathBuf = Path::new(path)
+            .parent()
+            .map(|parent| parent.to_path_buf())
+            .unwrap_or_default();
+        let field = |row: &HashMap<String, String>, column: &str| -> Result<String, DatasetError> {
+            row.get(column)
+                .cloned()
+                .ok_or_else(|| DatasetError::Manifest(format!("missing column {}", column)))
+        };
+
+        let mut recordings: Vec<Recording> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let split_name: String = field(row, "split")?;
+            let split: Split = Split::from_name(&split_name)
+                .ok_or_else(|| DatasetError::Manifest(format!("unknown split {}", split_name)))?;
+
+            let mut recording: Recording = Recording::new(
+                root.join(field(row, "audio_filename")?),
+                root.join(field(row, "midi_filename")?),
+                split,
+            );
+            if let Some(duration) = row.get("duration").and_then(|d| d.parse::<f32>().ok()) {
+                recording.set_duration(duration);
+            }
+            recordings.push(recording);
+        }
+
+        Ok(Dataset { recordings })
+    }
+
+    pub fn get_recordings(&self) -> &Vec<Recording> {
+        &self.recordings
+    }
+
+    pub fn len(&self) -> usize {
+        self.recordings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recordings.is_empty()
+    }
+
+    // the recordings of one split, as a dataset of their own
+    pub fn split(&self, split: Split) -> Dataset {
+        Dataset {
+            recordings: self
+                .recordings
+                .iter()
+                .filter(|r| r.get_split() == split)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    // the whole recording, with one encoding per spectrograph frame
+    pub fn load(&self, index: usize) -> Result<Pair, DatasetError> {
+        let recording: &Recording = self.recording(index)?;
+        let spectrograph: Spectrograph =
+            audio_to_spectrograph(&path_str(recording.get_audio_path())?);
+        let encoding: MIDIEncoding = get_frame_encoding(
+            &path_str(recording.get_midi_path())?,
+            spectrograph.frame_ms(),
+            spectrograph.num_timestamps(),
+        )?;
+
+        Ok(Pair {
+            spectrograph,
+            encoding,
+            recording: index,
+            offset_sec: 0.0,
+        })
+    }
+
+    /*
+     len_sec of the recording from offset_sec. decoding stops once the window is full, but
+     the audio before offset_sec is still decoded and thrown away, so later windows load slower.
+    */
+    pub fn load_window(
+        &self,
+        index: usize,
+        offset_sec: f32,
+        len_sec: f32,
+    ) -> Result<Pair, DatasetError> {
+        let recording: &Recording = self.recording(index)?;
+        let spectrograph: Spectrograph =
+            get_window_spectrograph(&path_str(recording.get_audio_path())?, offset_sec, len_sec);
+        let encoding: MIDIEncoding = get_window_frame_encoding(
+            &path_str(recording.get_midi_path())?,
+            offset_sec as f64 * 1000.0,
+            spectrograph.frame_ms(),
+            spectrograph.num_timestamps(),
+        )?;
+
+        Ok(Pair {
+            spectrograph,
+            encoding,
+            recording: index,
+            offset_sec,
+        })
+    }
+
+    // a recording picked at random and a window starting anywhere it fits
+    pub fn random_window<R: Rng>(&self, rng: &mut R, len_sec: f32) -> Result<Pair, DatasetError> {
+        if self.is_empty() {
+            return Err(DatasetError::Manifest(String::from("no recordings to sample")));
+        }
+        let index: usize = rng.gen_range(0..self.len());
+        let latest: f32 = (self.recordings[index].duration()? - len_sec).max(0.0);
+        let offset_sec: f32 = if latest > 0.0 {
+            rng.gen_range(0.0..latest)
+        } else {
+            0.0
+        };
+
+        self.load_window(index, offset_sec, len_sec)
+    }
+
+    // count random windows, the same ones every time for the same seed
+    pub fn sample_windows(&self, count: usize, len_sec: f32, seed: u64) -> Windows<'_> {
+        Windows {
+            dataset: self,
+            rng: StdRng::seed_from_u64(seed),
+            remaining: count,
+            len_sec,
+        }
+    }
+
+    fn recording(&self, index: usize) -> Result<&Recording, DatasetError> {
+        let recording: &Recording = self
+            .recordings
+            .get(index)
+            .ok_or_else(|| DatasetError::Manifest(format!("no recording at index {}", index)))?;
+
+        for path in [recording.get_audio_path(), recording.get_midi_path()] {
+            if !path.is_file() {
+                return Err(DatasetError::MissingFile(path.display().to_string()));
+            }
+        }
+        Ok(recording)
+    }
+}
+
+pub struct Windows<'a> {
+    dataset: &'a Dataset,
+    rng: StdRng,
+    remaining: usize,
+    len_sec: f32,
+}
+
+impl Iterator for Windows<'_> {
+    type Item = Result<Pair, DatasetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(self.dataset.random_window(&mut self.rng, self.len_sec))
+    }
+}
+
+fn collect_audio(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), DatasetError> {
+    for entry in fs::read_dir(dir)? {
+        let path: PathBuf = entry?.path();
+        if path.is_dir() {
+            collect_audio(&path, files)?;
+            continue;
+        }
+
+        let extension: String = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// FNV-1a, which unlike the standard hasher is the same on every platform and release
+fn hashed_split(name: &str) -> Split {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in name.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    match hash % 10 {
+        0 => Split::Test,
+        1 => Split::Validation,
+        _ => Split::Train,
+    }
+}
+
+// fields split on commas outside quotes, with "" inside quotes for a quote
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut field: String = String::new();
+    let mut quoted: bool = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields.iter().map(|f| f.trim().to_string()).collect()
+}
+
+fn json_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn path_str(path: &Path) -> Result<String, DatasetError> {
+    path.to_str()
+        .map(|p| p.to_string())
+        .ok_or_else(|| DatasetError::Manifest(format!("{} is not valid UTF-8", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "./tests/output/dataset";
+
+    fn write_manifest(name: &str, contents: &str) -> String {
+        fs::create_dir_all(OUTPUT).expect("Failed to create output directory");
+        let path: String = format!("{}/{}", OUTPUT, name);
+        fs::write(&path, contents).expect("Failed to write manifest");
+        path
+    }
+
+    #[test]
+    fn csv_manifest() {
+        let path: String = write_manifest(
+            "maestro.csv",
+            "canonical_composer,canonical_title,split,year,midi_filename,audio_filename,duration\n\
+             Frédéric Chopin,\"Ballade No. 1, Op. 23\",train,2004,2004/a.midi,2004/a.wav,539.5\n\
+             Franz Schubert,\"Sonata in A \"\"Major\"\"\",test,2006,2006/b.midi,2006/b.wav,\n",
+        );
+        let dataset: Dataset = Dataset::from_manifest(&path).expect("Failed to read manifest");
+
+        assert_eq!(dataset.len(), 2);
+        let first: &Recording = &dataset.get_recordings()[0];
+        assert_eq!(first.get_split(), Split::Train);
+        assert_eq!(first.get_audio_path(), Path::new(OUTPUT).join("2004/a.wav"));
+        assert_eq!(first.get_duration(), Some(539.5));
+        assert_eq!(dataset.get_recordings()[1].get_duration(), None);
+
+        assert_eq!(dataset.split(Split::Test).len(), 1);
+        assert!(dataset.split(Split::Validation).is_empty());
+        assert_eq!(csv_fields("a,\"b, \"\"c\"\"\",d"), vec!["a", "b, \"c\"", "d"]);
+    }
+
+    #[test]
+    fn json_manifest() {
+        let columns: String = write_manifest(
+            "maestro.json",
+            r#"{
+                "split": {"0": "validation", "1": "train", "10": "test", "2": "train"},
+                "midi_filename": {"0": "a.midi", "1": "b.midi", "10": "k.midi", "2": "c.midi"},
+                "audio_filename": {"0": "a.wav", "1": "b.wav", "10": "k.wav", "2": "c.wav"},
+                "duration": {"0": 10.5, "1": 20.0, "10": 3.0, "2": 4.0}
+            }"#,
+        );
+        let dataset: Dataset = Dataset::from_json(&columns).expect("Failed to read manifest");
+        let splits: Vec<Split> = dataset.get_recordings().iter().map(|r| r.get_split()).collect();
+        assert_eq!(splits, vec![Split::Validation, Split::Train, Split::Train, Split::Test]);
+        assert_eq!(dataset.get_recordings()[0].get_duration(), Some(10.5));
+
+        let records: String = write_manifest(
+            "records.json",
+            r#"[{"split": "train", "midi_filename": "a.mid", "audio_filename": "a.mp3"}]"#,
+        );
+        assert_eq!(Dataset::from_json(&records).expect("Failed to read manifest").len(), 1);
+
+        let broken: String = write_manifest("broken.json", r#"[{"split": "train"}]"#);
+        assert!(matches!(Dataset::from_json(&broken), Err(DatasetError::Manifest(_))));
+    }
+
+    #[test]
+    fn directory_pairs() {
+        let dir: String = format!("{}/directory/nested", OUTPUT);
+        fs::create_dir_all(&dir).expect("Failed to create output directory");
+        for file in ["one.mp3", "one.mid", "two.wav", "two.midi", "lonely.mp3", "notes.txt"] {
+            fs::write(format!("{}/{}", dir, file), "").expect("Failed to write file");
+        }
+
+        let dataset: Dataset = Dataset::from_directory(&format!("{}/directory", OUTPUT))
+            .expect("Failed to read directory");
+        assert_eq!(dataset.len(), 2);
+        assert_eq!(dataset.get_recordings()[0].get_midi_path(), Path::new(&dir).join("one.mid"));
+        assert_eq!(dataset.get_recordings()[0].get_split(), hashed_split("one"));
+    }
+
+    #[test]
+    fn aligned_windows() {
+        // any recording will do to check that frames and encodings line up, the duration
+        // keeps windows inside the audio as the MIDI file runs longer
+        let path: String = write_manifest(
+            "windows.csv",
+            "split,audio_filename,midi_filename,duration\n\
+             train,../../../../audio_to_spectrum/tests/Happy_bday.mp3,../../Data_test.midi,8\n",
+        );
+        let dataset: Dataset = Dataset::from_csv(&path).expect("Failed to read manifest");
+
+        let windows: Vec<Pair> = dataset
+            .sample_windows(2, 1.5, 7)
+            .collect::<Result<Vec<Pair>, DatasetError>>()
+            .expect("Failed to load windows");
+        for pair in windows.iter() {
+            assert_eq!(pair.get_spectrograph().num_timestamps(), pair.get_encoding().len());
+            assert!(pair.get_offset() >= 0.0);
+        }
+
+        let again: Vec<f32> = dataset
+            .sample_windows(2, 1.5, 7)
+            .map(|pair| pair.expect("Failed to load window").get_offset())
+            .collect();
+        assert_eq!(again, windows.iter().map(|p| p.get_offset()).collect::<Vec<f32>>());
+
+        let missing: Dataset = Dataset::new(vec![Recording::new(
+            PathBuf::from("./tests/none.mp3"),
+            PathBuf::from("./tests/Data_test.midi"),
+            Split::Train,
+        )]);
+        assert!(matches!(missing.load(0), Err(DatasetError::MissingFile(_))));
+    }
+}
@@ -0,0 +1,59 @@
+// builtin
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+// external
+
+// internal
+use midi_encoder::error::MidiError;
+
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(io::Error),
+    Midi(MidiError),
+    Json(serde_json::Error),
+    Manifest(String),
+    MissingFile(String),
+}
+
+impl Display for DatasetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Io(err) => write!(f, "Failed to read dataset: {}", err),
+            DatasetError::Midi(err) => write!(f, "Failed to read MIDI target: {}", err),
+            DatasetError::Json(err) => write!(f, "Failed to parse JSON manifest: {}", err),
+            DatasetError::Manifest(reason) => write!(f, "Invalid manifest: {}", reason),
+            DatasetError::MissingFile(path) => write!(f, "Dataset file {} does not exist", path),
+        }
+    }
+}
+
+impl Error for DatasetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DatasetError::Io(err) => Some(err),
+            DatasetError::Midi(err) => Some(err),
+            DatasetError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DatasetError {
+    fn from(err: io::Error) -> Self {
+        DatasetError::Io(err)
+    }
+}
+
+impl From<MidiError> for DatasetError {
+    fn from(err: MidiError) -> Self {
+        DatasetError::Midi(err)
+    }
+}
+
+impl From<serde_json::Error> for DatasetError {
+    fn from(err: serde_json::Error) -> Self {
+        DatasetError::Json(err)
+    }
+}
@@ -3,6 +3,9 @@
 // external
 
 // internal
+pub mod alignment;
+pub mod dataset;
+pub mod error;
 pub mod types;
 mod converter;
 
@@ -12,7 +15,7 @@ pub use crate::converter::RNNConverter;
 #[cfg(test)]
 mod tests {
     use audio_to_spectrum::{get_sample_spectrograph, spectrograph::Spectrograph};
-    use midi_encoder::{get_sample_encoding, types::MIDIEncoding};
+    use midi_encoder::{get_frame_encoding, types::MIDIEncoding};
     use models::networks::configs::{ActivationConfig, WeightConfig};
     use models::networks::activation::{Activation, init_registry};
 
@@ -29,11 +32,6 @@ mod tests {
         let mut converter: RNNConverter = RNNConverter::new(config, weights, activations);
         
         let graph: Spectrograph = get_sample_spectrograph("./tests/Data_test.mp3", 3.0);
-        let encoding: MIDIEncoding = get_sample_encoding("./tests/Data_test.midi", 2.99);
-
-        for _i in 1..1 {
-            converter.update(graph.clone(), encoding.clone());
-        }
 
         let output: MIDIEncoding = converter.translate_spectrum(graph, 0.7);
 
@@ -48,7 +46,8 @@ mod tests {
         let mut converter: RNNConverter = RNNConverter::from_file("./tests/converter_weights.txt", 6);
         
         let graph: Spectrograph = get_sample_spectrograph("./tests/Data_test.mp3", 3.0);
-        let encoding: MIDIEncoding = get_sample_encoding("./tests/Data_test.midi", 2.99);
+        let encoding: MIDIEncoding = get_frame_encoding("./tests/Data_test.midi", graph.frame_ms(), graph.num_timestamps())
+            .expect("Failed to encode MIDI file");
 
         for _i in 1..2 {
             converter.update(graph.clone(), encoding.clone());
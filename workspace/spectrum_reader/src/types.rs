@@ -1,10 +1,11 @@
 // builtin 
-use std::mem::take;
+use std::mem::{replace, take};
 
 // external
 
 // internal
 use audio_to_spectrum::spectrograph::Spectrograph;
+use midi_encoder::encoding::{ChordLayout, Encoding};
 use midi_encoder::types::MIDIEncoding;
 
 
@@ -17,7 +18,8 @@ pub trait Translator {
 pub struct ConverterConfig {
     layers: usize, 
     units_by_layer: Vec<usize>,
-    batch_size: usize
+    batch_size: usize,
+    layout: Box<dyn Encoding>,
 }
 
 impl ConverterConfig {
@@ -26,9 +28,19 @@ impl ConverterConfig {
             layers,
             units_by_layer,
             batch_size,
+            layout: Box::new(ChordLayout::default()),
         }
     }
 
+    pub fn set_layout(&mut self, layout: Box<dyn Encoding>) {
+        self.layout = layout;
+    }
+
+    // hands the layout over to the converter, a default chord layout is left in its place
+    pub fn take_layout(&mut self) -> Box<dyn Encoding> {
+        replace(&mut self.layout, Box::new(ChordLayout::default()))
+    }
+
     pub fn layers(&self) -> usize {
         self.layers
     }